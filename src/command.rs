@@ -1,15 +1,39 @@
+use crate::common;
+use crate::common::Commit;
 use crate::common::GitObject;
 use crate::common::Kind;
+use crate::common::Tag;
+use crate::common::Tree;
+use crate::error::{bail, ensure, Error, Result};
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::index::IndexEntry;
+use crate::index::Stat;
+use crate::pack;
+use crate::pack::PackIndex;
+use crate::pack::PackOptions;
+use crate::reflog;
+use crate::refs;
+use crate::refs::PackedRef;
+use crate::refs::Ref;
+use crate::refs::RefChange;
+use crate::refs::RefUpdate;
+use crate::revision;
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::fs;
 use std::io;
 use std::path::Path;
 
-pub fn init(path: &Path, writer: &mut dyn io::Write) -> anyhow::Result<()> {
-  fs::create_dir(path.join(".git"))?;
-  fs::create_dir(path.join(".git/objects"))?;
-  fs::create_dir(path.join(".git/refs"))?;
-  fs::write(path.join(".git/HEAD"), "ref: refs/heads/master\n")?;
-  write!(writer, "Initialized git directory")?;
+pub fn init(path: &Path, writer: &mut dyn io::Write) -> Result<()> {
+  for dir in [".git", ".git/objects", ".git/refs"] {
+    let dir = path.join(dir);
+    fs::create_dir(&dir).map_err(Error::io(dir))?;
+  }
+  let head = path.join(".git/HEAD");
+  fs::write(&head, "ref: refs/heads/master\n").map_err(Error::io(head))?;
+  write!(writer, "Initialized git directory").map_err(Error::stream)?;
   Ok(())
 }
 
@@ -18,47 +42,225 @@ pub fn hash_object(
   stdout: &mut dyn io::Write,
   repo: &Path,
   write: bool,
-) -> anyhow::Result<()> {
+) -> Result<()> {
   let git_object = GitObject::build_file_object(path)?;
   let hash = git_object.hash()?;
-  writeln!(stdout, "{hash}")?;
+  writeln!(stdout, "{hash}").map_err(Error::stream)?;
   if write {
     git_object.write(repo)?;
   }
   Ok(())
 }
 
+/// What `cat-file` reports about the object it is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatFileMode {
+  PrettyPrint,
+  Type,
+  Size,
+  Exists,
+}
+
 pub fn cat_file(
   object_hash: &str,
   writer: &mut dyn io::Write,
   repo_path: &Path,
-  pretty_print: bool,
-) -> anyhow::Result<()> {
-  anyhow::ensure!(pretty_print, "only supports pretty print");
-  let git_object = GitObject::read_object(repo_path, object_hash)?;
-  git_object.stdout(writer)?;
+  mode: CatFileMode,
+) -> Result<()> {
+  // Reading fails for names that match no object, which is all `-e` checks.
+  let object_hash = revision::resolve(repo_path, object_hash)?;
+  let git_object = GitObject::read_object(repo_path, &object_hash)?;
+  match mode {
+    CatFileMode::PrettyPrint => git_object.stdout(writer)?,
+    CatFileMode::Type => writeln!(writer, "{}", git_object.kind).map_err(Error::stream)?,
+    CatFileMode::Size => writeln!(writer, "{}", git_object.size).map_err(Error::stream)?,
+    CatFileMode::Exists => {}
+  }
+  Ok(())
+}
+
+/// Answers one object name per line of `stdin` with `<sha> <type> <size>`,
+/// followed by the raw content when `contents` is set (`--batch`), or with
+/// `<name> missing` or `<name> ambiguous` for names that do not identify
+/// exactly one object.
+pub fn cat_file_batch(
+  stdin: &mut dyn io::BufRead,
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  contents: bool,
+) -> Result<()> {
+  let mut line = String::new();
+  loop {
+    line.clear();
+    if stdin.read_line(&mut line).map_err(Error::stream)? == 0 {
+      break;
+    }
+    let name = line.trim_end_matches(['\n', '\r']);
+    // Only names that identify no single object are answered in-line; an
+    // object that exists but cannot be read stops the batch.
+    let found = match revision::resolve(repo_path, name).map_err(Error::from) {
+      Ok(object_hash) => match GitObject::read_object(repo_path, &object_hash) {
+        Ok(git_object) => Some(git_object),
+        Err(Error::MissingObject(_)) => None,
+        Err(error) => return Err(error),
+      },
+      Err(error @ (Error::CorruptObject { .. } | Error::Io { .. })) => return Err(error),
+      Err(_) => None,
+    };
+    let Some(git_object) = found else {
+      let status = match GitObject::matching_hashes(repo_path, name)?.len() {
+        0 | 1 => "missing",
+        _ => "ambiguous",
+      };
+      writeln!(writer, "{name} {status}").map_err(Error::stream)?;
+      writer.flush().map_err(Error::stream)?;
+      continue;
+    };
+    writeln!(
+      writer,
+      "{} {} {}",
+      git_object.hash()?,
+      git_object.kind,
+      git_object.size
+    )
+    .map_err(Error::stream)?;
+    if contents {
+      writer
+        .write_all(git_object.content())
+        .map_err(Error::stream)?;
+      writeln!(writer).map_err(Error::stream)?;
+    }
+    writer.flush().map_err(Error::stream)?;
+  }
   Ok(())
 }
 
-pub fn write_tree(repo_path: &Path, stdout: &mut dyn io::Write) -> anyhow::Result<()> {
-  let tree_object = GitObject::build_tree_object(repo_path)?;
-  tree_object.write(repo_path)?;
-  writeln!(stdout, "{}", tree_object.hash()?)?;
+/// Writes the staged content as tree objects and prints the root tree's
+/// hash. The refreshed tree cache is saved back to the index.
+pub fn write_tree(repo_path: &Path, stdout: &mut dyn io::Write) -> Result<()> {
+  let lock = Index::lock(repo_path)?;
+  let mut index = Index::read(repo_path)?;
+  let hash = index.write_tree(repo_path)?;
+  index.write(lock)?;
+  writeln!(stdout, "{hash}").map_err(Error::stream)?;
   Ok(())
 }
 
+/// Flags accepted by `ls-tree`.
+#[derive(Debug, Default)]
+pub struct LsTreeOptions {
+  /// `--name-only`: print paths without mode, type and hash.
+  pub name_only: bool,
+  /// `-r`: recurse into subtrees.
+  pub recursive: bool,
+  /// `-t`: show tree entries even when recursing into them.
+  pub show_trees: bool,
+  /// `-d`: show only tree entries.
+  pub only_trees: bool,
+  /// `-l`: show the size of blob entries.
+  pub long: bool,
+  /// `--full-name`: paths are always shown from the repository root, which is
+  /// also where every command of this tool runs, so this is accepted for
+  /// compatibility only.
+  pub full_name: bool,
+}
+
+/// Follows tags and commits down to the tree they point at.
+fn peel_to_tree(repo_path: &Path, object_hash: &str) -> Result<GitObject> {
+  if GitObject::read_object(repo_path, object_hash)?.kind == Kind::Blob {
+    bail!("fatal: not a tree object");
+  }
+  let tree_hash = revision::peel_to(repo_path, object_hash, Kind::Tree)?;
+  GitObject::read_object(repo_path, &tree_hash)
+}
+
 pub fn ls_tree(
   object_hash: &str,
   writer: &mut dyn io::Write,
   repo_path: &Path,
-  name_only: bool,
-) -> anyhow::Result<()> {
-  anyhow::ensure!(name_only, "only --name-only is supported");
-  let git_object = GitObject::read_object(repo_path, object_hash)?;
-  if git_object.kind != Kind::Tree {
-    Err(anyhow::anyhow!("fatal: not a tree object"))?;
+  options: &LsTreeOptions,
+  paths: &[String],
+) -> Result<()> {
+  let object_hash = revision::resolve(repo_path, object_hash)?;
+  let tree_object = peel_to_tree(repo_path, &object_hash)?;
+  ls_tree_entries(&tree_object, b"", writer, repo_path, options, paths)
+}
+
+fn ls_tree_entries(
+  tree_object: &GitObject,
+  prefix: &[u8],
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  options: &LsTreeOptions,
+  paths: &[String],
+) -> Result<()> {
+  for entry in Tree::parse(tree_object.content())?.entries {
+    let full_path = [prefix, &entry.name].concat();
+    let path = String::from_utf8_lossy(&full_path);
+    let is_tree = entry.kind() == Kind::Tree;
+    let matches = paths.is_empty()
+      || paths
+        .iter()
+        .any(|spec| path == *spec || path.starts_with(&format!("{}/", spec.trim_end_matches('/'))));
+    // Without -r, only descend into trees that lead towards a longer pathspec.
+    let descend = is_tree
+      && if options.recursive {
+        matches
+          || paths
+            .iter()
+            .any(|spec| spec.starts_with(&format!("{path}/")))
+      } else {
+        paths
+          .iter()
+          .any(|spec| spec.starts_with(&format!("{path}/")))
+      };
+    let show = if descend {
+      options.show_trees || (options.only_trees && (matches || options.recursive))
+    } else {
+      matches && (is_tree || !options.only_trees)
+    };
+    if show {
+      let path = common::quote_path(&full_path);
+      if options.name_only {
+        writeln!(writer, "{path}").map_err(Error::stream)?;
+      } else if options.long {
+        let size = match entry.kind() {
+          Kind::Blob => GitObject::read_object(repo_path, &entry.hash())?
+            .size
+            .to_string(),
+          _ => "-".to_string(),
+        };
+        writeln!(
+          writer,
+          "{:0>6} {} {} {size:>7}\t{path}",
+          entry.mode,
+          entry.kind(),
+          entry.hash()
+        )
+        .map_err(Error::stream)?;
+      } else {
+        writeln!(
+          writer,
+          "{:0>6} {} {}\t{path}",
+          entry.mode,
+          entry.kind(),
+          entry.hash()
+        )
+        .map_err(Error::stream)?;
+      }
+    }
+    if descend {
+      let subtree = GitObject::read_object(repo_path, &entry.hash())?;
+      ls_tree_entries(
+        &subtree,
+        &[&full_path[..], b"/"].concat(),
+        writer,
+        repo_path,
+        options,
+        paths,
+      )?;
+    }
   }
-  git_object.stdout(writer)?;
   Ok(())
 }
 
@@ -68,9 +270,2112 @@ pub fn commit_tree(
   repo_path: &Path,
   message: &str,
   parent: Option<String>,
-) -> anyhow::Result<()> {
+) -> Result<()> {
+  let tree_hash = revision::peel_to(
+    repo_path,
+    &revision::resolve(repo_path, &tree_hash)?,
+    Kind::Tree,
+  )?;
+  let parent = parent
+    .map(|parent| {
+      revision::peel_to(
+        repo_path,
+        &revision::resolve(repo_path, &parent)?,
+        Kind::Commit,
+      )
+    })
+    .transpose()?;
   let commit_tree = GitObject::build_commit_object(&tree_hash, repo_path, message, parent)?;
   commit_tree.write(repo_path)?;
-  writeln!(stdout, "{}", commit_tree.hash()?)?;
+  writeln!(stdout, "{}", commit_tree.hash()?).map_err(Error::stream)?;
+  Ok(())
+}
+
+pub fn repack(
+  repo_path: &Path,
+  stdout: &mut dyn io::Write,
+  delete: bool,
+  options: &PackOptions,
+) -> Result<()> {
+  let hashes = GitObject::loose_object_hashes(repo_path)?;
+  if hashes.is_empty() {
+    writeln!(stdout, "Nothing new to pack.").map_err(Error::stream)?;
+    return Ok(());
+  }
+  let objects = hashes
+    .iter()
+    .map(|hash| GitObject::read_object(repo_path, hash))
+    .collect::<Result<Vec<_>>>()?;
+  let name = pack::write_pack(
+    &common::git_dir(repo_path).join("objects/pack"),
+    &objects,
+    options,
+  )?;
+  if delete {
+    for hash in &hashes {
+      let fanout_dir = common::git_dir(repo_path).join(format!("objects/{}", &hash[..2]));
+      let object_path = fanout_dir.join(&hash[2..]);
+      fs::remove_file(&object_path).map_err(Error::io(object_path))?;
+      let mut rest = fs::read_dir(&fanout_dir).map_err(Error::io(&fanout_dir))?;
+      if rest.next().is_none() {
+        fs::remove_dir(&fanout_dir).map_err(Error::io(&fanout_dir))?;
+      }
+    }
+  }
+  writeln!(stdout, "{name}").map_err(Error::stream)?;
+  Ok(())
+}
+
+pub fn index_pack(pack_path: &Path, stdout: &mut dyn io::Write) -> Result<()> {
+  let data = fs::read(pack_path).map_err(Error::io(pack_path))?;
+  let (entries, checksum) = pack::scan_pack(&data)?;
+  let entries = entries
+    .into_iter()
+    .map(|entry| pack::IndexEntry {
+      oid: entry.oid,
+      crc: entry.crc,
+      offset: entry.offset,
+    })
+    .collect::<Vec<_>>();
+  let index = pack::write_index(&entries, &checksum)?;
+  let idx_path = pack_path.with_extension("idx");
+  fs::write(&idx_path, index).map_err(Error::io(&idx_path))?;
+  writeln!(stdout, "{}", hex::encode(checksum)).map_err(Error::stream)?;
+  Ok(())
+}
+
+pub fn verify_pack(path: &Path, stdout: &mut dyn io::Write, verbose: bool) -> Result<()> {
+  let pack_path = path.with_extension("pack");
+  let index = PackIndex::read(&path.with_extension("idx"))?;
+  let data = fs::read(&pack_path).map_err(Error::io(&pack_path))?;
+  let (entries, checksum) =
+    pack::scan_pack(&data).with_context(|| format!("verifying {}", pack_path.display()))?;
+  ensure!(
+    index.pack_checksum == checksum,
+    "{}: pack checksum does not match its index",
+    pack_path.display()
+  );
+  ensure!(
+    index.oids.len() == entries.len(),
+    "{}: index lists {} objects, pack has {}",
+    pack_path.display(),
+    index.oids.len(),
+    entries.len()
+  );
+  for entry in &entries {
+    let oid = hex::encode(entry.oid);
+    let Some(position) = index.position(&entry.oid) else {
+      bail!("{oid} is missing from the index");
+    };
+    ensure!(
+      index.offsets[position] == entry.offset,
+      "{oid}: index offset {} does not match pack offset {}",
+      index.offsets[position],
+      entry.offset
+    );
+    ensure!(
+      index.crcs[position] == entry.crc,
+      "{oid}: CRC mismatch at offset {}",
+      entry.offset
+    );
+  }
+  if !verbose {
+    return Ok(());
+  }
+  let mut chains = BTreeMap::<usize, usize>::new();
+  for entry in &entries {
+    *chains.entry(entry.depth).or_default() += 1;
+    write!(
+      stdout,
+      "{} {:<6} {} {} {}",
+      hex::encode(entry.oid),
+      entry.kind.to_string(),
+      entry.size,
+      entry.packed_size,
+      entry.offset
+    )
+    .map_err(Error::stream)?;
+    if let Some(base) = entry.base {
+      write!(stdout, " {} {}", entry.depth, hex::encode(base)).map_err(Error::stream)?;
+    }
+    writeln!(stdout).map_err(Error::stream)?;
+  }
+  let plural = |count: usize| if count == 1 { "" } else { "s" };
+  for (depth, count) in chains {
+    if depth == 0 {
+      writeln!(stdout, "non delta: {count} object{}", plural(count)).map_err(Error::stream)?;
+    } else {
+      writeln!(
+        stdout,
+        "chain length = {depth}: {count} object{}",
+        plural(count)
+      )
+      .map_err(Error::stream)?;
+    }
+  }
+  writeln!(stdout, "{}: ok", pack_path.display()).map_err(Error::stream)?;
+  Ok(())
+}
+
+pub fn mktag(stdin: &mut dyn io::Read, stdout: &mut dyn io::Write, repo_path: &Path) -> Result<()> {
+  let mut content = String::new();
+  stdin.read_to_string(&mut content).map_err(Error::stream)?;
+  ensure!(
+    content.contains("\n\n"),
+    "fatal: tag input does not end its header with a blank line"
+  );
+  let Tag {
+    object,
+    kind,
+    tag,
+    tagger,
+    extra_headers,
+    ..
+  } = Tag::parse(content.as_bytes())?;
+  ensure!(!tag.is_empty(), "fatal: tag input has an empty tag name");
+  let Some(tagger) = tagger else {
+    bail!("fatal: tag input is missing the 'tagger' line");
+  };
+  let tagger = String::from_utf8_lossy(&tagger).into_owned();
+  let target = GitObject::read_object(repo_path, &object)?;
+  ensure!(
+    target.kind == kind,
+    "fatal: object {object} is a {}, not a {kind}",
+    target.kind
+  );
+  ensure!(
+    tagger.contains("> ")
+      && tagger
+        .rsplit(' ')
+        .nth(1)
+        .is_some_and(|ts| ts.parse::<u64>().is_ok()),
+    "fatal: tag input has a malformed tagger line: '{tagger}'"
+  );
+  ensure!(
+    extra_headers.is_empty(),
+    "fatal: tag input has unexpected extra header lines"
+  );
+  let tag_object = GitObject::from_content(Kind::Tag, content.as_bytes());
+  tag_object.write(repo_path)?;
+  writeln!(stdout, "{}", tag_object.hash()?).map_err(Error::stream)?;
+  Ok(())
+}
+
+/// Creates the tag `name` for `object_hash`: a lightweight tag pointing
+/// straight at the object, or an annotated tag object when `annotate` is set
+/// or a message is given.
+pub fn tag(
+  name: &str,
+  repo_path: &Path,
+  annotate: bool,
+  message: Option<String>,
+  object_hash: &str,
+) -> Result<()> {
+  let ref_name = format!("refs/tags/{name}");
+  ensure!(
+    refs::is_valid_ref_name(&ref_name),
+    "fatal: '{name}' is not a valid tag name."
+  );
+  ensure!(
+    refs::read_ref(repo_path, &ref_name)?.is_none(),
+    "fatal: tag '{name}' already exists"
+  );
+  let target = GitObject::read_object(repo_path, &revision::resolve(repo_path, object_hash)?)?;
+  let hash = match message {
+    Some(message) => {
+      let tag_object = GitObject::build_tag_object(
+        &target.hash()?,
+        target.kind,
+        name,
+        &common::identity()?,
+        &message,
+      )?;
+      tag_object.write(repo_path)?;
+      tag_object.hash()?
+    }
+    None if annotate => bail!("fatal: annotated tags need a message (-m)"),
+    None => target.hash()?,
+  };
+  Ok(refs::transaction(
+    repo_path,
+    &[RefUpdate {
+      name: ref_name,
+      change: RefChange::Set(hash),
+      old: Some(refs::ZERO_HASH.to_string()),
+      message: String::new(),
+    }],
+  )?)
+}
+
+/// Deletes the tags `names`.
+pub fn tag_delete(names: &[String], writer: &mut dyn io::Write, repo_path: &Path) -> Result<()> {
+  for name in names {
+    let ref_name = format!("refs/tags/{name}");
+    let Some(hash) = refs::resolve_ref(repo_path, &ref_name)? else {
+      bail!("error: tag '{name}' not found.");
+    };
+    refs::transaction(
+      repo_path,
+      &[RefUpdate {
+        name: ref_name,
+        change: RefChange::Delete,
+        old: Some(hash.clone()),
+        message: String::new(),
+      }],
+    )?;
+    let short = revision::abbreviate(repo_path, &hash, 7)?;
+    writeln!(writer, "Deleted tag '{name}' (was {short})").map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct TagListOptions {
+  /// Only list tags matching one of these globs; all tags when empty.
+  pub patterns: Vec<String>,
+  /// `refname` or `version:refname`, optionally prefixed with `-` to reverse.
+  pub sort: Option<String>,
+  /// Print this many lines of each tag's annotation, or of the commit
+  /// message for lightweight tags.
+  pub lines: Option<usize>,
+}
+
+/// Lists the tags, sorted by name unless `options.sort` says otherwise.
+pub fn tag_list(
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  options: &TagListOptions,
+) -> Result<()> {
+  let mut tags = refs::list_refs(repo_path)?
+    .into_iter()
+    .filter_map(|(name, hash)| Some((name.strip_prefix("refs/tags/")?.to_string(), hash)))
+    .filter(|(name, _)| {
+      options.patterns.is_empty()
+        || options
+          .patterns
+          .iter()
+          .any(|pattern| common::glob_match(pattern, name))
+    })
+    .collect::<Vec<_>>();
+  let sort = options.sort.as_deref().unwrap_or("refname");
+  let (reverse, key) = match sort.strip_prefix('-') {
+    Some(key) => (true, key),
+    None => (false, sort),
+  };
+  match key {
+    "refname" => {}
+    "version:refname" | "v:refname" => {
+      tags.sort_by(|(a, _), (b, _)| version_cmp(a, b).then_with(|| a.cmp(b)))
+    }
+    _ => bail!("fatal: unsupported sort specification '{sort}'"),
+  }
+  if reverse {
+    tags.reverse();
+  }
+  for (name, hash) in tags {
+    let Some(lines) = options.lines else {
+      writeln!(writer, "{name}").map_err(Error::stream)?;
+      continue;
+    };
+    let git_object = GitObject::read_object(repo_path, &hash)?;
+    let message = match git_object.kind {
+      Kind::Tag => Tag::parse(git_object.content())?.message_str().into_owned(),
+      Kind::Commit => Commit::parse(git_object.content())?
+        .message_str()
+        .into_owned(),
+      _ => String::new(),
+    };
+    let mut annotation = message.lines().take(lines.max(1));
+    write!(writer, "{name:<15} {}", annotation.next().unwrap_or("")).map_err(Error::stream)?;
+    for line in annotation {
+      write!(writer, "\n    {line}").map_err(Error::stream)?;
+    }
+    writeln!(writer).map_err(Error::stream)?;
+  }
   Ok(())
 }
+
+/// Compares names the way `--sort=version:refname` does: runs of digits are
+/// compared as numbers, so `v1.9` sorts before `v1.10`.
+fn version_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+  let (mut a, mut b) = (a, b);
+  loop {
+    let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+      return a.len().cmp(&b.len());
+    };
+    if x.is_ascii_digit() && y.is_ascii_digit() {
+      let a_len = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+      let b_len = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+      let (a_number, b_number) = (
+        a[..a_len].trim_start_matches('0'),
+        b[..b_len].trim_start_matches('0'),
+      );
+      let ordering = a_number
+        .len()
+        .cmp(&b_number.len())
+        .then_with(|| a_number.cmp(b_number));
+      if ordering.is_ne() {
+        return ordering;
+      }
+      (a, b) = (&a[a_len..], &b[b_len..]);
+    } else if x != y {
+      return x.cmp(&y);
+    } else {
+      (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+    }
+  }
+}
+
+fn ref_target(repo_path: &Path, name: &str, no_deref: bool) -> Result<String> {
+  if no_deref {
+    Ok(name.to_string())
+  } else {
+    Ok(refs::resolve_symbolic(repo_path, name)?)
+  }
+}
+
+/// Resolves a new or old value for a ref; empty and all-zero values stand for
+/// "no ref".
+fn ref_value(repo_path: &Path, value: &str) -> Result<String> {
+  if value.is_empty() || value == refs::ZERO_HASH {
+    return Ok(refs::ZERO_HASH.to_string());
+  }
+  match revision::resolve(repo_path, value) {
+    Ok(hash) => Ok(hash),
+    Err(_) => bail!("fatal: {value}: not a valid SHA1"),
+  }
+}
+
+/// Points `name` at `new_value`, or deletes it with `delete`, provided it
+/// currently points at `old_value` when one is given. Symbolic refs such as
+/// `HEAD` are followed so the branch they name moves, unless `no_deref` is
+/// set. `message` is recorded in the reflog.
+pub fn update_ref(
+  name: &str,
+  new_value: Option<&str>,
+  old_value: Option<&str>,
+  repo_path: &Path,
+  message: &str,
+  delete: bool,
+  no_deref: bool,
+) -> Result<()> {
+  let target = ref_target(repo_path, name, no_deref)?;
+  let change = if delete {
+    RefChange::Delete
+  } else {
+    let Some(new_value) = new_value else {
+      bail!("fatal: update-ref needs a new value");
+    };
+    match ref_value(repo_path, new_value)? {
+      hash if hash == refs::ZERO_HASH => RefChange::Delete,
+      hash => RefChange::Set(hash),
+    }
+  };
+  let old = old_value
+    .map(|value| ref_value(repo_path, value))
+    .transpose()?;
+  Ok(refs::transaction(
+    repo_path,
+    &[RefUpdate {
+      name: target,
+      change,
+      old,
+      message: message.to_string(),
+    }],
+  )?)
+}
+
+/// Reads `update`, `create`, `delete` and `verify` commands, one per line as
+/// in `git update-ref --stdin`, and applies them as a single transaction.
+pub fn update_ref_stdin(
+  stdin: &mut dyn io::BufRead,
+  repo_path: &Path,
+  message: &str,
+  no_deref: bool,
+) -> Result<()> {
+  let mut updates = Vec::new();
+  let mut line = String::new();
+  loop {
+    line.clear();
+    if stdin.read_line(&mut line).map_err(Error::stream)? == 0 {
+      break;
+    }
+    let line = line.trim_end_matches(['\n', '\r']);
+    if line.is_empty() {
+      continue;
+    }
+    let args = line.split(' ').collect::<Vec<_>>();
+    let (change, old) = match args[..] {
+      ["update", _, new] => (new, None),
+      ["update", _, new, old] => (new, Some(old)),
+      ["create", _, new] => (new, Some(refs::ZERO_HASH)),
+      ["delete", _] => (refs::ZERO_HASH, None),
+      ["delete", _, old] => (refs::ZERO_HASH, Some(old)),
+      ["verify", _] => ("", Some(refs::ZERO_HASH)),
+      ["verify", _, old] => ("", Some(old)),
+      _ => bail!("fatal: unknown command: {line}"),
+    };
+    let name = ref_target(repo_path, args[1], no_deref)?;
+    let change = match (args[0], ref_value(repo_path, change)?) {
+      ("verify", _) => RefChange::Verify,
+      ("create", hash) if hash == refs::ZERO_HASH => {
+        bail!("fatal: create {name}: zero <new-value>")
+      }
+      (_, hash) if hash == refs::ZERO_HASH => RefChange::Delete,
+      (_, hash) => RefChange::Set(hash),
+    };
+    let old = old.map(|value| ref_value(repo_path, value)).transpose()?;
+    updates.push(RefUpdate {
+      name,
+      change,
+      old,
+      message: message.to_string(),
+    });
+  }
+  Ok(refs::transaction(repo_path, &updates)?)
+}
+
+/// Prints the ref that the symbolic ref `name` points at, or repoints it at
+/// `target`.
+pub fn symbolic_ref(
+  name: &str,
+  target: Option<&str>,
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  delete: bool,
+  short: bool,
+) -> Result<()> {
+  if let Some(target) = target {
+    return Ok(refs::write_symbolic_ref(repo_path, name, target)?);
+  }
+  let Some(Ref::Symbolic(current)) = refs::read_ref(repo_path, name)? else {
+    if delete {
+      bail!("fatal: Cannot delete {name}, not a symbolic ref");
+    }
+    bail!("fatal: ref {name} is not a symbolic ref");
+  };
+  if delete {
+    ensure!(name != "HEAD", "fatal: deleting '{name}' is not allowed");
+    return Ok(refs::delete_ref(repo_path, name)?);
+  }
+  let current = if short {
+    refs::shorten_ref_name(&current)
+  } else {
+    &current
+  };
+  writeln!(writer, "{current}").map_err(Error::stream)?;
+  Ok(())
+}
+
+/// Moves loose refs into `.git/packed-refs`. As in git, only tags and refs
+/// that are already packed move unless `all` is set; symbolic refs stay loose.
+pub fn pack_refs(repo_path: &Path, all: bool) -> Result<()> {
+  let lock = refs::lock_packed_refs(repo_path)?;
+  let mut packed = refs::read_packed_refs(repo_path)?
+    .into_iter()
+    .map(|packed_ref| (packed_ref.name.clone(), packed_ref))
+    .collect::<BTreeMap<_, _>>();
+  let mut moved = Vec::new();
+  for (name, value) in refs::loose_refs(repo_path)? {
+    let Ref::Direct(hash) = value else {
+      continue;
+    };
+    if !(all || name.starts_with("refs/tags/") || packed.contains_key(&name)) {
+      continue;
+    }
+    let peeled = refs::peel(repo_path, &hash)?;
+    packed.insert(
+      name.clone(),
+      PackedRef {
+        name: name.clone(),
+        hash,
+        peeled,
+      },
+    );
+    moved.push(name);
+  }
+  refs::write_packed_refs(repo_path, lock, &packed.into_values().collect::<Vec<_>>())?;
+  for name in moved {
+    refs::delete_loose_ref(repo_path, &name)?;
+  }
+  Ok(())
+}
+
+fn reflog_ref(repo_path: &Path, name: &str) -> Result<String> {
+  match refs::dwim_ref(repo_path, name)? {
+    Some(full_name) => Ok(full_name),
+    None => {
+      bail!("fatal: ambiguous argument '{name}': unknown revision or path not in the working tree.")
+    }
+  }
+}
+
+/// Lists the reflog of `name`, newest entry first, as
+/// `<short hash> <name>@{<n>}: <message>`.
+pub fn reflog_show(name: &str, writer: &mut dyn io::Write, repo_path: &Path) -> Result<()> {
+  let full_name = reflog_ref(repo_path, name)?;
+  let entries = reflog::read_reflog(repo_path, &full_name)?;
+  for (n, entry) in entries.iter().rev().enumerate() {
+    writeln!(
+      writer,
+      "{} {name}@{{{n}}}: {}",
+      &entry.new[..7],
+      entry.message
+    )
+    .map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+/// Prunes entries older than `expire` (default 90 days) from the reflogs of
+/// `names`, or of every ref with `all`.
+pub fn reflog_expire(
+  names: &[String],
+  repo_path: &Path,
+  expire: Option<&str>,
+  all: bool,
+) -> Result<()> {
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .context("system clock is before the Unix epoch")?
+    .as_secs() as i64;
+  let cutoff = reflog::parse_expiry(expire.unwrap_or("90.days.ago"), now)?;
+  let full_names = if all {
+    reflog::reflog_names(repo_path)?
+  } else {
+    names
+      .iter()
+      .map(|name| reflog_ref(repo_path, name))
+      .collect::<Result<Vec<_>>>()?
+  };
+  for full_name in full_names {
+    let mut entries = reflog::read_reflog(repo_path, &full_name)?;
+    let len = entries.len();
+    entries.retain(|entry| entry.timestamp >= cutoff);
+    if entries.len() != len {
+      reflog::write_reflog(repo_path, &full_name, &entries)?;
+    }
+  }
+  Ok(())
+}
+
+/// Removes single reflog entries named by `<ref>@{<n>}` selectors.
+pub fn reflog_delete(selectors: &[String], repo_path: &Path) -> Result<()> {
+  for selector in selectors {
+    let Some((full_name, n)) = reflog::parse_selector(repo_path, selector)? else {
+      bail!("fatal: not a reflog: {selector}");
+    };
+    let mut entries = reflog::read_reflog(repo_path, &full_name)?;
+    ensure!(n < entries.len(), "error: no reflog for '{selector}'");
+    entries.remove(entries.len() - 1 - n);
+    reflog::write_reflog(repo_path, &full_name, &entries)?;
+  }
+  Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct RevParseOptions {
+  /// Require exactly one revision naming an existing object.
+  pub verify: bool,
+  /// Abbreviate hashes to at least this many digits.
+  pub short: Option<usize>,
+  /// Print short ref names instead of hashes.
+  pub abbrev_ref: bool,
+}
+
+/// Prints what `git rev-parse` prints for `args`: one hash per revision and
+/// `^<hash>` for excluded ones. `A..B` becomes `B` and `^A`; `A...B` becomes
+/// `B`, `A` and `^` each merge base.
+pub fn rev_parse(
+  args: &[String],
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  options: &RevParseOptions,
+) -> Result<()> {
+  if options.verify {
+    let [spec] = args else {
+      bail!("fatal: Needed a single revision");
+    };
+    let Ok(hash) = revision::resolve(repo_path, spec) else {
+      bail!("fatal: Needed a single revision");
+    };
+    ensure!(
+      GitObject::read_object(repo_path, &hash).is_ok(),
+      "fatal: Needed a single revision"
+    );
+    writeln!(writer, "{}", format_hash(repo_path, spec, &hash, options)?).map_err(Error::stream)?;
+    return Ok(());
+  }
+  let mut lines = Vec::new();
+  for arg in args {
+    let or_head = |side: &str| {
+      if side.is_empty() {
+        "HEAD".to_string()
+      } else {
+        side.to_string()
+      }
+    };
+    if let Some((a, b)) = arg.split_once("...") {
+      let (a, b) = (or_head(a), or_head(b));
+      let (a_hash, b_hash) = (
+        revision::resolve(repo_path, &a)?,
+        revision::resolve(repo_path, &b)?,
+      );
+      lines.push(format_hash(repo_path, &b, &b_hash, options)?);
+      lines.push(format_hash(repo_path, &a, &a_hash, options)?);
+      // A merge base has no name to abbreviate, even with --abbrev-ref.
+      for base in revision::merge_bases(repo_path, &a_hash, &b_hash)? {
+        lines.push(format!("^{}", abbreviate_hash(repo_path, &base, options)?));
+      }
+    } else if let Some((a, b)) = arg.split_once("..") {
+      let (a, b) = (or_head(a), or_head(b));
+      lines.push(format_hash(
+        repo_path,
+        &b,
+        &revision::resolve(repo_path, &b)?,
+        options,
+      )?);
+      lines.push(format!(
+        "^{}",
+        format_hash(repo_path, &a, &revision::resolve(repo_path, &a)?, options)?
+      ));
+    } else if let Some(excluded) = arg.strip_prefix('^').filter(|rest| !rest.is_empty()) {
+      let hash = revision::resolve(repo_path, excluded)?;
+      lines.push(format!(
+        "^{}",
+        format_hash(repo_path, excluded, &hash, options)?
+      ));
+    } else {
+      lines.push(format_hash(
+        repo_path,
+        arg,
+        &revision::resolve(repo_path, arg)?,
+        options,
+      )?);
+    }
+  }
+  for line in lines {
+    writeln!(writer, "{line}").map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+fn format_hash(
+  repo_path: &Path,
+  spec: &str,
+  hash: &str,
+  options: &RevParseOptions,
+) -> Result<String> {
+  if options.abbrev_ref {
+    return Ok(refs::shorten_ref_name(&revision::symbolic_full_name(repo_path, spec)?).to_string());
+  }
+  abbreviate_hash(repo_path, hash, options)
+}
+
+/// `hash`, shortened when `--short` asks for it.
+fn abbreviate_hash(repo_path: &Path, hash: &str, options: &RevParseOptions) -> Result<String> {
+  match options.short {
+    Some(len) => Ok(revision::abbreviate(repo_path, hash, len)?),
+    None => Ok(hash.to_string()),
+  }
+}
+
+fn branch_ref(name: &str) -> Result<String> {
+  let full_name = format!("refs/heads/{name}");
+  ensure!(
+    name != "HEAD" && !name.starts_with('-') && refs::is_valid_ref_name(&full_name),
+    "fatal: '{name}' is not a valid branch name"
+  );
+  Ok(full_name)
+}
+
+/// The branch `HEAD` points at, if it is not detached.
+fn current_branch(repo_path: &Path) -> Result<Option<String>> {
+  let target = refs::resolve_symbolic(repo_path, "HEAD")?;
+  Ok(target.starts_with("refs/heads/").then_some(target))
+}
+
+#[derive(Debug, Default)]
+pub struct BranchListOptions {
+  /// Show the abbreviated hash and subject of each branch tip.
+  pub verbose: bool,
+  /// Only list branches whose tip has this commit as an ancestor.
+  pub contains: Option<String>,
+}
+
+/// Lists the branches, marking the current one with `*`. A detached `HEAD`
+/// is listed first as `(HEAD detached at <short hash>)`.
+pub fn branch_list(
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  options: &BranchListOptions,
+) -> Result<()> {
+  let current = current_branch(repo_path)?;
+  let mut branches = Vec::new();
+  if let (None, Some(hash)) = (&current, refs::head(repo_path)?) {
+    let short = revision::abbreviate(repo_path, &hash, 7)?;
+    branches.push((true, format!("(HEAD detached at {short})"), hash));
+  }
+  for (name, hash) in refs::list_refs(repo_path)? {
+    if let Some(short) = name.strip_prefix("refs/heads/") {
+      branches.push((current.as_ref() == Some(&name), short.to_string(), hash));
+    }
+  }
+  if let Some(contains) = &options.contains {
+    let commit = revision::peel_to(
+      repo_path,
+      &revision::resolve(repo_path, contains)?,
+      Kind::Commit,
+    )?;
+    let mut kept = Vec::new();
+    for branch in branches {
+      if revision::ancestors(repo_path, &branch.2)?.contains(&commit) {
+        kept.push(branch);
+      }
+    }
+    branches = kept;
+  }
+  let width = branches
+    .iter()
+    .map(|(_, name, _)| name.len())
+    .max()
+    .unwrap_or(0);
+  for (is_current, name, hash) in branches {
+    let marker = if is_current { '*' } else { ' ' };
+    if !options.verbose {
+      writeln!(writer, "{marker} {name}").map_err(Error::stream)?;
+      continue;
+    }
+    let short = revision::abbreviate(repo_path, &hash, 7)?;
+    let subject = subject(&revision::read_commit(repo_path, &hash)?.message_str());
+    writeln!(writer, "{marker} {name:<width$} {short} {subject}").map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+/// Creates the branch `name` at `start`, `HEAD` by default.
+pub fn branch_create(name: &str, start: Option<&str>, repo_path: &Path) -> Result<()> {
+  let full_name = branch_ref(name)?;
+  ensure!(
+    refs::read_ref(repo_path, &full_name)?.is_none(),
+    "fatal: a branch named '{name}' already exists"
+  );
+  let start = start.unwrap_or("HEAD");
+  let Ok(hash) = revision::resolve(repo_path, start)
+    .and_then(|hash| revision::peel_to(repo_path, &hash, Kind::Commit))
+  else {
+    bail!("fatal: not a valid object name: '{start}'");
+  };
+  Ok(refs::transaction(
+    repo_path,
+    &[RefUpdate {
+      name: full_name,
+      change: RefChange::Set(hash),
+      old: Some(refs::ZERO_HASH.to_string()),
+      message: format!("branch: Created from {start}"),
+    }],
+  )?)
+}
+
+/// Deletes the branches `names`. Unless `force` is set, each must be merged
+/// into its upstream, or into `HEAD` when it has none.
+pub fn branch_delete(
+  names: &[String],
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  force: bool,
+) -> Result<()> {
+  let current = current_branch(repo_path)?;
+  for name in names {
+    let full_name = format!("refs/heads/{name}");
+    let Some(hash) = refs::resolve_ref(repo_path, &full_name)? else {
+      bail!("error: branch '{name}' not found.");
+    };
+    ensure!(
+      current.as_ref() != Some(&full_name),
+      "error: Cannot delete branch '{name}' checked out at '{}'",
+      repo_path.display()
+    );
+    if !force {
+      let base = match refs::upstream(repo_path, &full_name)? {
+        Some(upstream) => refs::resolve_ref(repo_path, &upstream)?,
+        None => refs::head(repo_path)?,
+      };
+      let merged = match base {
+        Some(base) => revision::ancestors(repo_path, &base)?.contains(&hash),
+        None => false,
+      };
+      ensure!(
+        merged,
+        "error: The branch '{name}' is not fully merged.\n\
+         If you are sure you want to delete it, run 'git branch -D {name}'."
+      );
+    }
+    refs::transaction(
+      repo_path,
+      &[RefUpdate {
+        name: full_name,
+        change: RefChange::Delete,
+        old: Some(hash.clone()),
+        message: String::new(),
+      }],
+    )?;
+    let short = revision::abbreviate(repo_path, &hash, 7)?;
+    writeln!(writer, "Deleted branch {name} (was {short}).").map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+/// Renames the branch `old`, the current branch by default, to `new`, moving
+/// its reflog along. An existing `new` is only overwritten with `force`.
+pub fn branch_rename(old: Option<&str>, new: &str, repo_path: &Path, force: bool) -> Result<()> {
+  let old_name = match old {
+    Some(old) => format!("refs/heads/{old}"),
+    None => match current_branch(repo_path)? {
+      Some(current) => current,
+      None => bail!("fatal: cannot rename the current branch while not on any"),
+    },
+  };
+  let new_name = branch_ref(new)?;
+  let short = refs::shorten_ref_name(&old_name);
+  ensure!(
+    refs::resolve_ref(repo_path, &old_name)?.is_some(),
+    "fatal: No branch named '{short}'."
+  );
+  ensure!(
+    force || refs::read_ref(repo_path, &new_name)?.is_none(),
+    "fatal: a branch named '{new}' already exists"
+  );
+  Ok(refs::rename_ref(
+    repo_path,
+    &old_name,
+    &new_name,
+    &format!("Branch: renamed {old_name} to {new_name}"),
+    force,
+  )?)
+}
+
+/// Prints the name of the current branch; nothing when `HEAD` is detached.
+pub fn branch_show_current(writer: &mut dyn io::Write, repo_path: &Path) -> Result<()> {
+  if let Some(current) = current_branch(repo_path)? {
+    writeln!(writer, "{}", refs::shorten_ref_name(&current)).map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct ShowRefOptions {
+  /// Only show branches.
+  pub heads: bool,
+  /// Only show tags.
+  pub tags: bool,
+  /// Require each pattern to be the full name of an existing ref.
+  pub verify: bool,
+}
+
+/// Prints `<hash> <name>` for every ref, or for the refs whose name equals
+/// one of `patterns` or ends with `/<pattern>`.
+pub fn show_ref(
+  patterns: &[String],
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  options: &ShowRefOptions,
+) -> Result<()> {
+  if options.verify {
+    ensure!(!patterns.is_empty(), "fatal: --verify requires a reference");
+    for name in patterns {
+      let hash = (name == "HEAD" || name.starts_with("refs/"))
+        .then(|| refs::resolve_ref(repo_path, name))
+        .transpose()?
+        .flatten();
+      let Some(hash) = hash else {
+        bail!("fatal: '{name}' - not a valid ref");
+      };
+      writeln!(writer, "{hash} {name}").map_err(Error::stream)?;
+    }
+    return Ok(());
+  }
+  let mut found = false;
+  for (name, hash) in refs::list_refs(repo_path)? {
+    let kind_matches = !(options.heads || options.tags)
+      || (options.heads && name.starts_with("refs/heads/"))
+      || (options.tags && name.starts_with("refs/tags/"));
+    let pattern_matches = patterns.is_empty()
+      || patterns
+        .iter()
+        .any(|pattern| name == *pattern || name.ends_with(&format!("/{pattern}")));
+    if kind_matches && pattern_matches {
+      writeln!(writer, "{hash} {name}").map_err(Error::stream)?;
+      found = true;
+    }
+  }
+  ensure!(found, "error: no matching refs");
+  Ok(())
+}
+
+/// The first paragraph of a commit or tag message, joined into one line.
+fn subject(message: &str) -> String {
+  message
+    .lines()
+    .skip_while(|line| line.trim().is_empty())
+    .take_while(|line| !line.trim().is_empty())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+const REF_ATOMS: [&str; 9] = [
+  "refname",
+  "refname:short",
+  "objectname",
+  "objectname:short",
+  "objecttype",
+  "subject",
+  "authordate",
+  "upstream",
+  "upstream:short",
+];
+
+/// A ref and what `for-each-ref` shows about the object it points at.
+struct RefDetails {
+  name: String,
+  hash: String,
+  kind: Kind,
+  subject: String,
+  author: Option<String>,
+}
+
+impl RefDetails {
+  fn read(repo_path: &Path, name: String, hash: String) -> Result<RefDetails> {
+    let git_object = GitObject::read_object(repo_path, &hash)?;
+    let (subject, author) = match git_object.kind {
+      Kind::Commit => {
+        let commit = Commit::parse(git_object.content())?;
+        let author = commit.author_str().into_owned();
+        (subject(&commit.message_str()), Some(author))
+      }
+      Kind::Tag => (
+        subject(&Tag::parse(git_object.content())?.message_str()),
+        None,
+      ),
+      _ => (String::new(), None),
+    };
+    Ok(RefDetails {
+      name,
+      hash,
+      kind: git_object.kind,
+      subject,
+      author,
+    })
+  }
+
+  fn atom(&self, repo_path: &Path, atom: &str) -> Result<String> {
+    Ok(match atom {
+      "refname" => self.name.clone(),
+      "refname:short" => refs::shorten_ref_name(&self.name).to_string(),
+      "objectname" => self.hash.clone(),
+      "objectname:short" => revision::abbreviate(repo_path, &self.hash, 7)?,
+      "objecttype" => self.kind.to_string(),
+      "subject" => self.subject.clone(),
+      "authordate" => self
+        .author
+        .as_deref()
+        .map(revision::signature_date)
+        .unwrap_or_default(),
+      "upstream" => refs::upstream(repo_path, &self.name)?.unwrap_or_default(),
+      "upstream:short" => refs::upstream(repo_path, &self.name)?
+        .map(|upstream| refs::shorten_ref_name(&upstream).to_string())
+        .unwrap_or_default(),
+      _ => bail!("fatal: unknown field name: {atom}"),
+    })
+  }
+}
+
+enum FormatPart {
+  Literal(String),
+  Atom(String),
+}
+
+/// Splits a `--format` string into literal text and `%(atom)` placeholders;
+/// `%%` is a literal `%`.
+fn parse_ref_format(format: &str) -> Result<Vec<FormatPart>> {
+  let mut parts = Vec::new();
+  let mut literal = String::new();
+  let mut rest = format;
+  while let Some(index) = rest.find('%') {
+    literal.push_str(&rest[..index]);
+    rest = &rest[index + 1..];
+    if let Some(after) = rest.strip_prefix('%') {
+      literal.push('%');
+      rest = after;
+    } else if let Some((atom, after)) = rest.strip_prefix('(').and_then(|r| r.split_once(')')) {
+      ensure!(
+        REF_ATOMS.contains(&atom),
+        "fatal: unknown field name: {atom}"
+      );
+      parts.push(FormatPart::Literal(std::mem::take(&mut literal)));
+      parts.push(FormatPart::Atom(atom.to_string()));
+      rest = after;
+    } else {
+      literal.push('%');
+    }
+  }
+  literal.push_str(rest);
+  parts.push(FormatPart::Literal(literal));
+  Ok(parts)
+}
+
+#[derive(Debug, Default)]
+pub struct ForEachRefOptions {
+  /// Defaults to `%(objectname) %(objecttype)\t%(refname)`.
+  pub format: Option<String>,
+  /// A field name to sort by, prefixed with `-` to reverse; `refname` by
+  /// default.
+  pub sort: Option<String>,
+  /// Stop after this many refs.
+  pub count: Option<usize>,
+}
+
+/// Prints each ref matching `patterns` (all refs when empty) using
+/// `options.format`. A pattern matches the refs below it, so `refs/tags`
+/// matches every tag, or is a glob matched against the full name.
+pub fn for_each_ref(
+  patterns: &[String],
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  options: &ForEachRefOptions,
+) -> Result<()> {
+  let format = parse_ref_format(
+    options
+      .format
+      .as_deref()
+      .unwrap_or("%(objectname) %(objecttype)\t%(refname)"),
+  )?;
+  let sort = options.sort.as_deref().unwrap_or("refname");
+  let (reverse, key) = match sort.strip_prefix('-') {
+    Some(key) => (true, key),
+    None => (false, sort),
+  };
+  ensure!(REF_ATOMS.contains(&key), "fatal: unknown field name: {key}");
+
+  let mut matching = Vec::new();
+  for (name, hash) in refs::list_refs(repo_path)? {
+    let matches = patterns.is_empty()
+      || patterns.iter().any(|pattern| {
+        let prefix = pattern.trim_end_matches('/');
+        name
+          .strip_prefix(prefix)
+          .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+          || common::glob_match(pattern, &name)
+      });
+    if matches {
+      matching.push(RefDetails::read(repo_path, name, hash)?);
+    }
+  }
+  let mut keyed = Vec::new();
+  for details in matching {
+    let key = match key {
+      "authordate" => (
+        details
+          .author
+          .as_deref()
+          .map_or(0, revision::signature_time),
+        String::new(),
+      ),
+      _ => (0, details.atom(repo_path, key)?),
+    };
+    keyed.push((key, details));
+  }
+  keyed.sort_by(|(a, _), (b, _)| if reverse { b.cmp(a) } else { a.cmp(b) });
+
+  for (_, details) in keyed.into_iter().take(options.count.unwrap_or(usize::MAX)) {
+    let mut line = String::new();
+    for part in &format {
+      match part {
+        FormatPart::Literal(text) => line.push_str(text),
+        FormatPart::Atom(atom) => line.push_str(&details.atom(repo_path, atom)?),
+      }
+    }
+    writeln!(writer, "{line}").map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+/// Every file in the work tree below `repo_path`, as sorted `/`-separated
+/// paths relative to it; `.git` is skipped, and so is whatever `rules`
+/// exclude, ignored directories being left unvisited. Nested repositories
+/// are listed as single paths.
+fn worktree_files(repo_path: &Path, rules: Option<&mut IgnoreRules>) -> Result<Vec<String>> {
+  fn walk(
+    repo_path: &Path,
+    dir: &Path,
+    mut rules: Option<&mut IgnoreRules>,
+    files: &mut Vec<String>,
+  ) -> Result<()> {
+    for entry in fs::read_dir(dir).map_err(Error::io(dir))? {
+      let entry = entry.map_err(Error::io(dir))?;
+      let path = entry.path();
+      if dir == repo_path && entry.file_name() == ".git" {
+        continue;
+      }
+      let relative = path
+        .strip_prefix(repo_path)
+        .context("walking outside the work tree")?
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/");
+      let is_dir = entry.file_type().map_err(Error::io(&path))?.is_dir();
+      if let Some(rules) = rules.as_deref_mut() {
+        if rules.is_ignored(&relative, is_dir)? {
+          continue;
+        }
+      }
+      if is_dir && !path.join(".git").exists() {
+        walk(repo_path, &path, rules.as_deref_mut(), files)?;
+      } else {
+        files.push(relative);
+      }
+    }
+    Ok(())
+  }
+  let mut files = Vec::new();
+  walk(repo_path, repo_path, rules, &mut files)?;
+  files.sort();
+  Ok(files)
+}
+
+/// Turns a command line path into a pathspec relative to the top of the
+/// work tree; `.` becomes the empty pathspec, which matches everything.
+fn normalize_pathspec(pathspec: &str) -> String {
+  let pathspec = pathspec.trim_end_matches('/');
+  let pathspec = pathspec.strip_prefix("./").unwrap_or(pathspec);
+  if pathspec == "." {
+    String::new()
+  } else {
+    pathspec.to_string()
+  }
+}
+
+/// Whether `path` is `pathspec`, lies below it, or matches it as a glob.
+fn pathspec_matches(pathspec: &str, path: &str) -> bool {
+  pathspec.is_empty()
+    || path
+      .strip_prefix(pathspec)
+      .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    || (pathspec.contains(['*', '?', '[']) && common::glob_match(pathspec, path))
+}
+
+/// Hashes the work tree file, symlink or nested repository at `path` into
+/// the object store and stages it.
+fn stage_file(repo_path: &Path, index: &mut Index, path: &str) -> Result<()> {
+  let full_path = repo_path.join(path);
+  let worktree_entry = GitObject::build_worktree_entry(&full_path)?;
+  if let Some(blob) = &worktree_entry.blob {
+    blob.write(repo_path)?;
+  }
+  let metadata = fs::symlink_metadata(&full_path).map_err(Error::io(&full_path))?;
+  index.insert(IndexEntry {
+    stat: Stat::from_metadata(&metadata),
+    ..IndexEntry::new(path, worktree_entry.mode, worktree_entry.oid)
+  });
+  Ok(())
+}
+
+/// How the existing work tree path of `entry` differs from what is staged:
+/// `T` when it changed between file, symlink and gitlink, `M` for other
+/// changes of content or mode. A plain directory replacing a file is a
+/// deletion, but where a gitlink is staged it is a nested repository that
+/// is not checked out, which is no change.
+fn worktree_change(repo_path: &Path, entry: &IndexEntry) -> Result<Option<char>> {
+  let full_path = repo_path.join(&entry.path);
+  let is_dir = fs::symlink_metadata(&full_path).is_ok_and(|metadata| metadata.is_dir());
+  if is_dir && !full_path.join(".git").exists() {
+    return Ok((entry.mode != 0o160000).then_some('D'));
+  }
+  let worktree_entry = GitObject::build_worktree_entry(&full_path)?;
+  Ok(if worktree_entry.mode & 0o170000 != entry.mode & 0o170000 {
+    Some('T')
+  } else if worktree_entry.mode != entry.mode || worktree_entry.oid != entry.oid {
+    Some('M')
+  } else {
+    None
+  })
+}
+
+/// Stages the files matching `pathspecs`: new and modified files are added
+/// and tracked files that were deleted are removed. With `update` only
+/// tracked files are considered; `all` or `update` without pathspecs cover
+/// the whole work tree. Untracked files excluded by the ignore rules are
+/// skipped, and naming one explicitly is an error, unless `force` is set.
+pub fn add(
+  pathspecs: &[String],
+  repo_path: &Path,
+  all: bool,
+  update: bool,
+  force: bool,
+) -> Result<()> {
+  let mut pathspecs = pathspecs
+    .iter()
+    .map(|pathspec| normalize_pathspec(pathspec))
+    .collect::<Vec<_>>();
+  if pathspecs.is_empty() {
+    ensure!(
+      all || update,
+      "Nothing specified, nothing added.\nMaybe you wanted to say 'git add .'?"
+    );
+    pathspecs.push(String::new());
+  }
+  let matches = |path: &str| {
+    pathspecs
+      .iter()
+      .any(|pathspec| pathspec_matches(pathspec, path))
+  };
+  let lock = Index::lock(repo_path)?;
+  let mut index = Index::read(repo_path)?;
+  let mut tracked = index
+    .entries()
+    .iter()
+    .map(|entry| entry.path.clone())
+    .filter(|path| matches(path))
+    .collect::<Vec<_>>();
+  tracked.dedup();
+
+  let mut rules = IgnoreRules::load(repo_path)?;
+  if !force && !update {
+    let mut ignored = Vec::new();
+    for pathspec in pathspecs.iter().filter(|pathspec| !pathspec.is_empty()) {
+      let Ok(metadata) = fs::symlink_metadata(repo_path.join(pathspec)) else {
+        continue;
+      };
+      if index.get(pathspec).is_none()
+        && rules
+          .check(pathspec, metadata.is_dir())?
+          .is_some_and(|pattern| !pattern.negated)
+      {
+        ignored.push(pathspec.as_str());
+      }
+    }
+    ensure!(
+      ignored.is_empty(),
+      "The following paths are ignored by one of your .gitignore files:\n{}\n\
+       hint: Use -f if you really want to add them.",
+      ignored.join("\n")
+    );
+  }
+  let files = worktree_files(repo_path, (!force).then_some(&mut rules))?;
+  for pathspec in &pathspecs {
+    ensure!(
+      files.iter().any(|path| pathspec_matches(pathspec, path))
+        || index
+          .entries()
+          .iter()
+          .any(|entry| pathspec_matches(pathspec, &entry.path)),
+      "fatal: pathspec '{pathspec}' did not match any files"
+    );
+  }
+
+  // Tracked files are looked up directly: the ignore rules do not apply to
+  // them.
+  for path in &tracked {
+    if fs::symlink_metadata(repo_path.join(path)).is_err() {
+      index.remove(path);
+    } else {
+      stage_file(repo_path, &mut index, path)?;
+    }
+  }
+  if !update {
+    for path in files.iter().filter(|path| matches(path)) {
+      if tracked.binary_search(path).is_err() {
+        stage_file(repo_path, &mut index, path)?;
+      }
+    }
+  }
+  Ok(index.write(lock)?)
+}
+
+/// Unstages the paths matching `pathspecs` and, unless `cached` is set,
+/// deletes them from the work tree. Directories need `recursive`. Unless
+/// `force` is set, paths whose content would be lost are refused.
+pub fn rm(
+  pathspecs: &[String],
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  cached: bool,
+  recursive: bool,
+  force: bool,
+) -> Result<()> {
+  let lock = Index::lock(repo_path)?;
+  let mut index = Index::read(repo_path)?;
+  let mut paths = Vec::new();
+  for original in pathspecs {
+    let pathspec = normalize_pathspec(original);
+    let matching = index
+      .entries()
+      .iter()
+      .filter(|entry| pathspec_matches(&pathspec, &entry.path))
+      .map(|entry| entry.path.clone())
+      .collect::<Vec<_>>();
+    ensure!(
+      !matching.is_empty(),
+      "fatal: pathspec '{pathspec}' did not match any files"
+    );
+    // Only a pathspec naming a directory needs -r; a glob matches files.
+    let glob = pathspec.contains(['*', '?', '[']);
+    ensure!(
+      recursive
+        || !matching.iter().any(|path| {
+          let below = pathspec.is_empty()
+            || path
+              .strip_prefix(pathspec.as_str())
+              .is_some_and(|rest| rest.starts_with('/'));
+          below && !(glob && common::glob_match(&pathspec, path))
+        }),
+      "fatal: not removing '{original}' recursively without -r"
+    );
+    paths.extend(matching);
+  }
+  paths.sort();
+  paths.dedup();
+
+  if !force {
+    let head_tree = match refs::head(repo_path)? {
+      Some(head) => Some(revision::peel_to(repo_path, &head, Kind::Tree)?),
+      None => None,
+    };
+    for path in &paths {
+      let Some(entry) = index.get(path) else {
+        continue;
+      };
+      let staged = match &head_tree {
+        Some(tree) => revision::tree_path(repo_path, tree, path)? != Some(entry.hash()),
+        None => true,
+      };
+      let modified = fs::symlink_metadata(repo_path.join(path)).is_ok()
+        && worktree_change(repo_path, entry)?.is_some();
+      let problem = match (staged, modified, cached) {
+        (true, true, _) => "has staged content different from both the\nfile and the HEAD",
+        (true, false, false) => "has changes staged in the index",
+        (false, true, false) => "has local modifications",
+        _ => continue,
+      };
+      let hint = if staged && modified {
+        "(use -f to force removal)"
+      } else {
+        "(use --cached to keep the file, or -f to force removal)"
+      };
+      bail!("error: the following file {problem}:\n    {path}\n{hint}");
+    }
+  }
+
+  for path in &paths {
+    index.remove(path);
+    writeln!(writer, "rm '{path}'").map_err(Error::stream)?;
+    if cached {
+      continue;
+    }
+    let full_path = repo_path.join(path);
+    match fs::remove_file(&full_path) {
+      Err(e) if e.kind() != io::ErrorKind::NotFound => {
+        return Err(Error::Io {
+          path: full_path,
+          source: e,
+        })
+      }
+      _ => {}
+    }
+    let mut dir = full_path.parent();
+    while let Some(current) = dir {
+      if current == repo_path || fs::remove_dir(current).is_err() {
+        break;
+      }
+      dir = current.parent();
+    }
+  }
+  Ok(index.write(lock)?)
+}
+
+/// A tracked path that differs between `HEAD`, the index and the work tree.
+/// `staged` and `unstaged` are the letters of `git status --short`.
+struct StatusChange {
+  path: String,
+  /// The mode and hash in `HEAD` and in the index, if the path is there.
+  head: Option<(u32, String)>,
+  index: Option<(u32, String)>,
+  /// The work tree file's mode, 0 when it is missing.
+  worktree: u32,
+  staged: char,
+  unstaged: char,
+  /// What changed inside the nested repository, for gitlinks.
+  submodule: Option<SubmoduleChanges>,
+}
+
+/// How a nested repository differs from the gitlink staged for it.
+#[derive(Debug, Default, Clone, Copy)]
+struct SubmoduleChanges {
+  /// Its `HEAD` is not the staged commit.
+  new_commits: bool,
+  /// It has staged or unstaged changes of its own.
+  modified: bool,
+  /// It has untracked files.
+  untracked: bool,
+}
+
+impl SubmoduleChanges {
+  /// The `S<c><m><u>` field of `status --porcelain=v2`.
+  fn porcelain_v2(&self) -> String {
+    format!(
+      "S{}{}{}",
+      if self.new_commits { 'C' } else { '.' },
+      if self.modified { 'M' } else { '.' },
+      if self.untracked { 'U' } else { '.' }
+    )
+  }
+
+  /// The note after the path in the long format, e.g. `(new commits)`.
+  fn describe(&self) -> String {
+    let parts = [
+      (self.new_commits, "new commits"),
+      (self.modified, "modified content"),
+      (self.untracked, "untracked content"),
+    ];
+    let parts = parts
+      .iter()
+      .filter(|(changed, _)| *changed)
+      .map(|(_, part)| *part)
+      .collect::<Vec<_>>();
+    if parts.is_empty() {
+      String::new()
+    } else {
+      format!(" ({})", parts.join(", "))
+    }
+  }
+}
+
+/// A path with merge conflict stages in the index.
+struct UnmergedPath {
+  path: String,
+  /// The mode and hash of the base, ours and theirs stages.
+  stages: [Option<(u32, String)>; 3],
+  worktree: u32,
+}
+
+impl UnmergedPath {
+  /// The short format code and the long format label of the conflict.
+  fn describe(&self) -> (&'static str, &'static str) {
+    match self.stages.each_ref().map(Option::is_some) {
+      [true, true, true] => ("UU", "both modified:"),
+      [false, true, true] => ("AA", "both added:"),
+      [true, true, false] => ("UD", "deleted by them:"),
+      [true, false, true] => ("DU", "deleted by us:"),
+      [false, true, false] => ("AU", "added by us:"),
+      [false, false, true] => ("UA", "added by them:"),
+      _ => ("DD", "both deleted:"),
+    }
+  }
+}
+
+struct WorktreeStatus {
+  changes: Vec<StatusChange>,
+  unmerged: Vec<UnmergedPath>,
+  /// Untracked files; directories without tracked files are listed once,
+  /// with a trailing `/`.
+  untracked: Vec<String>,
+}
+
+/// Compares `HEAD` with the index and the index with the work tree. Files
+/// whose stat data matches the index are not rehashed unless they changed
+/// within the same second the index was written; stat data found stale for
+/// unchanged files is saved back when the index can be locked.
+fn collect_status(repo_path: &Path) -> Result<WorktreeStatus> {
+  let head_files = match refs::head(repo_path)? {
+    Some(head) => {
+      revision::tree_files(repo_path, &revision::peel_to(repo_path, &head, Kind::Tree)?)?
+    }
+    None => BTreeMap::new(),
+  };
+  let lock = Index::lock(repo_path).ok();
+  let mut index = Index::read(repo_path)?;
+  let index_mtime = fs::metadata(common::git_dir(repo_path).join("index"))
+    .ok()
+    .map(|metadata| Stat::from_metadata(&metadata));
+
+  let mut changes = Vec::new();
+  let mut unmerged: Vec<UnmergedPath> = Vec::new();
+  let mut refreshed = Vec::new();
+  for entry in index.entries() {
+    let full_path = repo_path.join(&entry.path);
+    let metadata = fs::symlink_metadata(&full_path).ok();
+    let worktree = metadata.as_ref().map_or(0, common::worktree_mode);
+    if entry.stage != 0 {
+      if unmerged.last().is_none_or(|last| last.path != entry.path) {
+        unmerged.push(UnmergedPath {
+          path: entry.path.clone(),
+          stages: Default::default(),
+          worktree,
+        });
+      }
+      let last = unmerged.last_mut().expect("pushed above");
+      last.stages[entry.stage as usize - 1] = Some((entry.mode, entry.hash()));
+      continue;
+    }
+
+    let head = head_files.get(&entry.path).cloned();
+    let staged_side = (!entry.intent_to_add).then(|| (entry.mode, entry.hash()));
+    let staged = match (&head, &staged_side) {
+      (None, None) => ' ',
+      (None, Some(_)) => 'A',
+      (Some(_), None) => 'D',
+      (Some(head), Some(staged_side)) if head == staged_side => ' ',
+      (Some((head_mode, _)), Some((index_mode, _)))
+        if head_mode & 0o170000 != index_mode & 0o170000 =>
+      {
+        'T'
+      }
+      (Some(_), Some(_)) => 'M',
+    };
+    let mut unstaged = match &metadata {
+      None => 'D',
+      Some(_) if entry.intent_to_add => 'A',
+      Some(metadata) => {
+        let stat = Stat::from_metadata(metadata);
+        let racy = index_mtime.is_some_and(|index_mtime| {
+          (entry.stat.mtime, entry.stat.mtime_nsec) >= (index_mtime.mtime, index_mtime.mtime_nsec)
+        });
+        // A gitlink's directory keeps its stat data when the nested
+        // repository moves to another commit.
+        if stat == entry.stat && !racy && entry.mode != 0o160000 {
+          ' '
+        } else if let Some(change) = worktree_change(repo_path, entry)? {
+          change
+        } else {
+          if stat != entry.stat {
+            refreshed.push((entry.path.clone(), stat));
+          }
+          ' '
+        }
+      }
+    };
+    let submodule = (entry.mode == 0o160000).then(|| SubmoduleChanges {
+      new_commits: unstaged == 'M',
+      ..Default::default()
+    });
+    let submodule = match submodule {
+      Some(submodule) if full_path.join(".git").exists() => {
+        let nested = collect_status(&full_path)?;
+        let submodule = SubmoduleChanges {
+          modified: !nested.changes.is_empty() || !nested.unmerged.is_empty(),
+          untracked: !nested.untracked.is_empty(),
+          ..submodule
+        };
+        if submodule.modified || submodule.untracked {
+          unstaged = 'M';
+        }
+        Some(submodule)
+      }
+      submodule => submodule,
+    };
+    if staged != ' ' || unstaged != ' ' {
+      changes.push(StatusChange {
+        path: entry.path.clone(),
+        head,
+        index: staged_side,
+        worktree,
+        staged,
+        unstaged,
+        submodule,
+      });
+    }
+  }
+
+  let tracked = index
+    .entries()
+    .iter()
+    .map(|entry| entry.path.as_str())
+    .collect::<HashSet<_>>();
+  for (path, head) in &head_files {
+    if !tracked.contains(path.as_str()) {
+      changes.push(StatusChange {
+        path: path.clone(),
+        head: Some(head.clone()),
+        index: None,
+        worktree: 0,
+        staged: 'D',
+        unstaged: ' ',
+        submodule: (head.0 == 0o160000).then(SubmoduleChanges::default),
+      });
+    }
+  }
+  changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+  let tracked_dirs = tracked
+    .iter()
+    .flat_map(|path| path.match_indices('/').map(|(at, _)| &path[..at]))
+    .collect::<HashSet<_>>();
+  let mut untracked = Vec::new();
+  let mut rules = IgnoreRules::load(repo_path)?;
+  for path in worktree_files(repo_path, Some(&mut rules))? {
+    if tracked.contains(path.as_str()) {
+      continue;
+    }
+    let untracked_dir = path
+      .match_indices('/')
+      .map(|(at, _)| &path[..at])
+      .find(|dir| !tracked_dirs.contains(dir));
+    let is_repository = repo_path.join(&path).is_dir();
+    untracked.push(match untracked_dir {
+      Some(dir) => format!("{dir}/"),
+      None if is_repository => format!("{path}/"),
+      None => path.clone(),
+    });
+  }
+  untracked.dedup();
+
+  if let (false, Some(lock)) = (refreshed.is_empty(), lock) {
+    for (path, stat) in refreshed {
+      index.refresh(&path, stat);
+    }
+    index.write(lock)?;
+  }
+  Ok(WorktreeStatus {
+    changes,
+    unmerged,
+    untracked,
+  })
+}
+
+/// The checked out branch and how it compares with its upstream.
+struct BranchStatus {
+  /// The commit `HEAD` resolves to; `None` before the first commit.
+  head: Option<String>,
+  /// The short branch name; `None` when `HEAD` is detached.
+  name: Option<String>,
+  /// The short upstream name and the commits only on the branch and only
+  /// on the upstream; the counts are `None` when the upstream is gone.
+  upstream: Option<(String, Option<(usize, usize)>)>,
+}
+
+fn branch_status(repo_path: &Path) -> Result<BranchStatus> {
+  let head = refs::head(repo_path)?;
+  let branch = current_branch(repo_path)?;
+  let mut upstream = None;
+  if let (Some(branch), Some(head)) = (&branch, &head) {
+    if let Some(upstream_ref) = refs::upstream(repo_path, branch)? {
+      let counts = match refs::resolve_ref(repo_path, &upstream_ref)? {
+        Some(upstream_hash) => {
+          let ours = revision::ancestors(repo_path, head)?;
+          let theirs = revision::ancestors(repo_path, &upstream_hash)?;
+          Some((
+            ours.difference(&theirs).count(),
+            theirs.difference(&ours).count(),
+          ))
+        }
+        None => None,
+      };
+      upstream = Some((refs::shorten_ref_name(&upstream_ref).to_string(), counts));
+    }
+  }
+  Ok(BranchStatus {
+    head,
+    name: branch.map(|branch| refs::shorten_ref_name(&branch).to_string()),
+    upstream,
+  })
+}
+
+/// Which format `status` prints in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFormat {
+  /// The human readable default, with hints.
+  #[default]
+  Long,
+  /// Two letter codes per path, also used for `--porcelain=v1`: paths are
+  /// always relative to the top of the work tree and never coloured.
+  Short,
+  /// `--porcelain=v2`, which adds modes and hashes.
+  PorcelainV2,
+}
+
+#[derive(Debug, Default)]
+pub struct StatusOptions {
+  pub format: StatusFormat,
+  /// Show the branch and upstream in the short and porcelain formats.
+  pub branch: bool,
+}
+
+/// Shows the changes staged for commit, the changes in the work tree that
+/// are not staged, the untracked files and the state of the branch.
+pub fn status(writer: &mut dyn io::Write, repo_path: &Path, options: &StatusOptions) -> Result<()> {
+  let branch = branch_status(repo_path)?;
+  let status = collect_status(repo_path)?;
+  match options.format {
+    StatusFormat::Long => status_long(writer, repo_path, &branch, &status),
+    StatusFormat::Short => status_short(writer, &branch, &status, options.branch),
+    StatusFormat::PorcelainV2 => status_porcelain_v2(writer, &branch, &status, options.branch),
+  }
+}
+
+fn commits(count: usize) -> &'static str {
+  if count == 1 {
+    "commit"
+  } else {
+    "commits"
+  }
+}
+
+fn status_long(
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  branch: &BranchStatus,
+  status: &WorktreeStatus,
+) -> Result<()> {
+  match (&branch.name, &branch.head) {
+    (Some(name), _) => writeln!(writer, "On branch {name}").map_err(Error::stream)?,
+    (None, Some(head)) => writeln!(
+      writer,
+      "HEAD detached at {}",
+      revision::abbreviate(repo_path, head, 7)?
+    )
+    .map_err(Error::stream)?,
+    (None, None) => writeln!(writer, "Not currently on any branch.").map_err(Error::stream)?,
+  }
+  if let Some((upstream, counts)) = &branch.upstream {
+    match *counts {
+      None => writeln!(
+        writer,
+        "Your branch is based on '{upstream}', but the upstream is gone.\n  \
+         (use \"git branch --unset-upstream\" to fixup)"
+      )
+      .map_err(Error::stream)?,
+      Some((0, 0)) => {
+        writeln!(writer, "Your branch is up to date with '{upstream}'.").map_err(Error::stream)?
+      }
+      Some((ahead, 0)) => writeln!(
+        writer,
+        "Your branch is ahead of '{upstream}' by {ahead} {}.\n  \
+         (use \"git push\" to publish your local commits)",
+        commits(ahead)
+      )
+      .map_err(Error::stream)?,
+      Some((0, behind)) => writeln!(
+        writer,
+        "Your branch is behind '{upstream}' by {behind} {}, and can be fast-forwarded.\n  \
+         (use \"git pull\" to update your local branch)",
+        commits(behind)
+      )
+      .map_err(Error::stream)?,
+      Some((ahead, behind)) => writeln!(
+        writer,
+        "Your branch and '{upstream}' have diverged,\n\
+         and have {ahead} and {behind} different commits each, respectively.\n  \
+         (use \"git pull\" to merge the remote branch into yours)"
+      )
+      .map_err(Error::stream)?,
+    }
+    writeln!(writer).map_err(Error::stream)?;
+  }
+  let initial = branch.head.is_none();
+  if initial {
+    writeln!(writer, "\nNo commits yet\n").map_err(Error::stream)?;
+  }
+  let unstage_hint = if initial {
+    "  (use \"git rm --cached <file>...\" to unstage)"
+  } else {
+    "  (use \"git restore --staged <file>...\" to unstage)"
+  };
+  let label = |code: char| match code {
+    'A' => "new file:",
+    'D' => "deleted:",
+    'T' => "typechange:",
+    _ => "modified:",
+  };
+
+  let staged = status
+    .changes
+    .iter()
+    .filter(|change| change.staged != ' ')
+    .collect::<Vec<_>>();
+  if !staged.is_empty() {
+    writeln!(writer, "Changes to be committed:\n{unstage_hint}").map_err(Error::stream)?;
+    for change in &staged {
+      let path = common::quote_path(change.path.as_bytes());
+      writeln!(writer, "\t{:<12}{path}", label(change.staged)).map_err(Error::stream)?;
+    }
+    writeln!(writer).map_err(Error::stream)?;
+  }
+
+  if !status.unmerged.is_empty() {
+    let codes = status
+      .unmerged
+      .iter()
+      .map(|path| path.describe().0)
+      .collect::<Vec<_>>();
+    let deletion = codes.iter().any(|code| ["UD", "DU"].contains(code));
+    let resolution_hint = match (codes.contains(&"DD"), deletion) {
+      (false, false) => "  (use \"git add <file>...\" to mark resolution)",
+      (true, false) => "  (use \"git rm <file>...\" to mark resolution)",
+      (_, true) => "  (use \"git add/rm <file>...\" as appropriate to mark resolution)",
+    };
+    writeln!(writer, "Unmerged paths:\n{unstage_hint}\n{resolution_hint}")
+      .map_err(Error::stream)?;
+    for path in &status.unmerged {
+      let quoted = common::quote_path(path.path.as_bytes());
+      writeln!(writer, "\t{:<17}{quoted}", path.describe().1).map_err(Error::stream)?;
+    }
+    writeln!(writer).map_err(Error::stream)?;
+  }
+
+  let unstaged = status
+    .changes
+    .iter()
+    .filter(|change| change.unstaged != ' ')
+    .collect::<Vec<_>>();
+  if !unstaged.is_empty() {
+    let verb = if unstaged.iter().any(|change| change.unstaged == 'D') {
+      "add/rm"
+    } else {
+      "add"
+    };
+    writeln!(
+      writer,
+      "Changes not staged for commit:\n  \
+       (use \"git {verb} <file>...\" to update what will be committed)\n  \
+       (use \"git restore <file>...\" to discard changes in working directory)"
+    )
+    .map_err(Error::stream)?;
+    if unstaged.iter().any(|change| {
+      change
+        .submodule
+        .is_some_and(|submodule| submodule.modified || submodule.untracked)
+    }) {
+      writeln!(
+        writer,
+        "  (commit or discard the untracked or modified content in submodules)"
+      )
+      .map_err(Error::stream)?;
+    }
+    for change in &unstaged {
+      let note = change
+        .submodule
+        .map(|submodule| submodule.describe())
+        .unwrap_or_default();
+      writeln!(
+        writer,
+        "\t{:<12}{}{note}",
+        label(change.unstaged),
+        common::quote_path(change.path.as_bytes())
+      )
+      .map_err(Error::stream)?;
+    }
+    writeln!(writer).map_err(Error::stream)?;
+  }
+
+  if !status.untracked.is_empty() {
+    writeln!(
+      writer,
+      "Untracked files:\n  (use \"git add <file>...\" to include in what will be committed)"
+    )
+    .map_err(Error::stream)?;
+    for path in &status.untracked {
+      let path = common::quote_path(path.as_bytes());
+      writeln!(writer, "\t{path}").map_err(Error::stream)?;
+    }
+    writeln!(writer).map_err(Error::stream)?;
+  }
+
+  if !staged.is_empty() {
+    return Ok(());
+  }
+  if !unstaged.is_empty() || !status.unmerged.is_empty() {
+    writeln!(
+      writer,
+      "no changes added to commit (use \"git add\" and/or \"git commit -a\")"
+    )
+    .map_err(Error::stream)?;
+  } else if !status.untracked.is_empty() {
+    writeln!(
+      writer,
+      "nothing added to commit but untracked files present (use \"git add\" to track)"
+    )
+    .map_err(Error::stream)?;
+  } else if initial {
+    writeln!(
+      writer,
+      "nothing to commit (create/copy files and use \"git add\" to track)"
+    )
+    .map_err(Error::stream)?;
+  } else {
+    writeln!(writer, "nothing to commit, working tree clean").map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+fn status_short(
+  writer: &mut dyn io::Write,
+  branch: &BranchStatus,
+  status: &WorktreeStatus,
+  show_branch: bool,
+) -> Result<()> {
+  if show_branch {
+    let mut header = match (&branch.name, &branch.head) {
+      (Some(name), None) => format!("No commits yet on {name}"),
+      (Some(name), Some(_)) => name.clone(),
+      (None, _) => "HEAD (no branch)".to_string(),
+    };
+    if let Some((upstream, counts)) = &branch.upstream {
+      header.push_str(&format!("...{upstream}"));
+      match *counts {
+        None => header.push_str(" [gone]"),
+        Some((0, 0)) => {}
+        Some((ahead, 0)) => header.push_str(&format!(" [ahead {ahead}]")),
+        Some((0, behind)) => header.push_str(&format!(" [behind {behind}]")),
+        Some((ahead, behind)) => header.push_str(&format!(" [ahead {ahead}, behind {behind}]")),
+      }
+    }
+    writeln!(writer, "## {header}").map_err(Error::stream)?;
+  }
+  let mut lines = status
+    .changes
+    .iter()
+    .map(|change| {
+      // A nested repository still at the staged commit but with changes of
+      // its own shows `m`, or `?` when it only has untracked files.
+      let unstaged = match change.submodule {
+        Some(submodule) if change.unstaged == 'M' && !submodule.new_commits => {
+          if submodule.modified {
+            'm'
+          } else {
+            '?'
+          }
+        }
+        _ => change.unstaged,
+      };
+      (change.path.as_str(), format!("{}{unstaged}", change.staged))
+    })
+    .chain(
+      status
+        .unmerged
+        .iter()
+        .map(|path| (path.path.as_str(), path.describe().0.to_string())),
+    )
+    .collect::<Vec<_>>();
+  lines.sort();
+  for (path, code) in lines {
+    let path = common::quote_path(path.as_bytes());
+    writeln!(writer, "{code} {path}").map_err(Error::stream)?;
+  }
+  for path in &status.untracked {
+    let path = common::quote_path(path.as_bytes());
+    writeln!(writer, "?? {path}").map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+fn status_porcelain_v2(
+  writer: &mut dyn io::Write,
+  branch: &BranchStatus,
+  status: &WorktreeStatus,
+  show_branch: bool,
+) -> Result<()> {
+  if show_branch {
+    writeln!(
+      writer,
+      "# branch.oid {}",
+      branch.head.as_deref().unwrap_or("(initial)")
+    )
+    .map_err(Error::stream)?;
+    writeln!(
+      writer,
+      "# branch.head {}",
+      branch.name.as_deref().unwrap_or("(detached)")
+    )
+    .map_err(Error::stream)?;
+    if let Some((upstream, counts)) = &branch.upstream {
+      writeln!(writer, "# branch.upstream {upstream}").map_err(Error::stream)?;
+      if let Some((ahead, behind)) = counts {
+        writeln!(writer, "# branch.ab +{ahead} -{behind}").map_err(Error::stream)?;
+      }
+    }
+  }
+  let side = |side: &Option<(u32, String)>| match side {
+    Some((mode, hash)) => (*mode, hash.clone()),
+    None => (0, refs::ZERO_HASH.to_string()),
+  };
+  for change in &status.changes {
+    let (head_mode, head_hash) = side(&change.head);
+    let (index_mode, index_hash) = side(&change.index);
+    writeln!(
+      writer,
+      "1 {}{} {} {head_mode:06o} {index_mode:06o} {:06o} {head_hash} {index_hash} {}",
+      change.staged.to_string().replace(' ', "."),
+      change.unstaged.to_string().replace(' ', "."),
+      change
+        .submodule
+        .map_or("N...".to_string(), |submodule| submodule.porcelain_v2()),
+      change.worktree,
+      common::quote_path(change.path.as_bytes())
+    )
+    .map_err(Error::stream)?;
+  }
+  for path in &status.unmerged {
+    let stages = path.stages.each_ref().map(side);
+    writeln!(
+      writer,
+      "u {} N... {:06o} {:06o} {:06o} {:06o} {} {} {} {}",
+      path.describe().0,
+      stages[0].0,
+      stages[1].0,
+      stages[2].0,
+      path.worktree,
+      stages[0].1,
+      stages[1].1,
+      stages[2].1,
+      common::quote_path(path.path.as_bytes())
+    )
+    .map_err(Error::stream)?;
+  }
+  for path in &status.untracked {
+    let path = common::quote_path(path.as_bytes());
+    writeln!(writer, "? {path}").map_err(Error::stream)?;
+  }
+  Ok(())
+}
+
+/// Prints each of `paths` that the ignore rules exclude. With `verbose`,
+/// lines read `<source>:<line>:<pattern>\t<path>` and also cover paths a
+/// negated pattern re-includes; `non_matching` adds `::\t<path>` for the
+/// others. Tracked paths never match. Returns whether any path is ignored.
+pub fn check_ignore(
+  paths: &[String],
+  writer: &mut dyn io::Write,
+  repo_path: &Path,
+  verbose: bool,
+  non_matching: bool,
+) -> Result<bool> {
+  let index = Index::read(repo_path)?;
+  let mut rules = IgnoreRules::load(repo_path)?;
+  let mut any_ignored = false;
+  for path in paths {
+    let pathspec = normalize_pathspec(path);
+    let is_dir = path.ends_with('/')
+      || fs::symlink_metadata(repo_path.join(&pathspec)).is_ok_and(|metadata| metadata.is_dir());
+    let pattern = match index.get(&pathspec) {
+      Some(_) => None,
+      None => rules.check(&pathspec, is_dir)?,
+    };
+    match pattern {
+      Some(pattern) if verbose => {
+        any_ignored |= !pattern.negated;
+        writeln!(
+          writer,
+          "{}:{}:{}\t{path}",
+          pattern.source, pattern.line, pattern.text
+        )
+        .map_err(Error::stream)?;
+      }
+      Some(pattern) if !pattern.negated => {
+        any_ignored = true;
+        writeln!(writer, "{path}").map_err(Error::stream)?;
+      }
+      _ if verbose && non_matching => writeln!(writer, "::\t{path}").map_err(Error::stream)?,
+      _ => {}
+    }
+  }
+  Ok(any_ignored)
+}
@@ -1,27 +1,37 @@
+use crate::error::{bail, ensure, Error, Result};
+use crate::ignore::IgnoreRules;
+use crate::pack;
+use crate::refs;
 use anyhow::Context;
 use flate2::bufread::ZlibDecoder;
 use flate2::{write::ZlibEncoder, Compression};
 use sha1::Digest;
 use sha1::Sha1;
+use std::borrow::Cow;
 use std::ffi::CStr;
 use std::fmt::Display;
 use std::io::Read;
 use std::io::Write;
 use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Kind {
   Blob,
   Tree,
   Commit,
+  Tag,
 }
 impl Kind {
-  fn from_str(kind: &str) -> anyhow::Result<Kind> {
+  pub(crate) fn from_str(kind: &str) -> Result<Kind> {
     match kind {
       "blob" => Ok(Kind::Blob),
       "tree" => Ok(Kind::Tree),
-      _ => anyhow::bail!("should not be called for: '{kind}'"),
+      "commit" => Ok(Kind::Commit),
+      "tag" => Ok(Kind::Tag),
+      _ => bail!("should not be called for: '{kind}'"),
     }
   }
 }
@@ -32,10 +42,190 @@ impl Display for Kind {
       Kind::Blob => write!(f, "blob"),
       Kind::Tree => write!(f, "tree"),
       Kind::Commit => write!(f, "commit"),
+      Kind::Tag => write!(f, "tag"),
     }
   }
 }
 
+/// The repository directory of the work tree `repo_path`: normally its
+/// `.git` directory, but a `.git` file, as git writes for submodules, names
+/// the directory with a `gitdir: <path>` line.
+pub fn git_dir(repo_path: &Path) -> PathBuf {
+  let dot_git = repo_path.join(".git");
+  if dot_git.is_file() {
+    if let Ok(content) = std::fs::read_to_string(&dot_git) {
+      if let Some(dir) = content.trim_end().strip_prefix("gitdir: ") {
+        return repo_path.join(dir);
+      }
+    }
+  }
+  dot_git
+}
+
+/// Quotes a path the way git prints it with `core.quotePath` on: paths with
+/// control characters, `"`, `\\` or non-ASCII bytes are wrapped in double
+/// quotes, with C escapes and octal for the bytes that need them.
+pub fn quote_path(path: &[u8]) -> String {
+  let needs_quoting = |b: u8| b < 0x20 || b == b'"' || b == b'\\' || b >= 0x7f;
+  if !path.iter().any(|&b| needs_quoting(b)) {
+    return String::from_utf8_lossy(path).into_owned();
+  }
+  let mut quoted = String::from("\"");
+  for &b in path {
+    match b {
+      0x07 => quoted.push_str("\\a"),
+      0x08 => quoted.push_str("\\b"),
+      b'\t' => quoted.push_str("\\t"),
+      b'\n' => quoted.push_str("\\n"),
+      0x0b => quoted.push_str("\\v"),
+      0x0c => quoted.push_str("\\f"),
+      b'\r' => quoted.push_str("\\r"),
+      b'"' => quoted.push_str("\\\""),
+      b'\\' => quoted.push_str("\\\\"),
+      b if needs_quoting(b) => quoted.push_str(&format!("\\{b:03o}")),
+      b => quoted.push(b as char),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// The identity and current time recorded in tags and reflog entries, as
+/// `Name <email> <unix seconds> <tz>`.
+pub(crate) fn identity() -> Result<String> {
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .context("system clock is before the Unix epoch")?;
+  Ok(format!("Coder <coder@crafters.io> {} +0000", now.as_secs()))
+}
+
+/// Matches a `[...]` character class at the start of `pattern` against `c`,
+/// returning whether it matched and the length of the class; `None` when
+/// the class is not terminated.
+fn glob_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+  let mut i = 1;
+  let negated = matches!(pattern.get(i), Some('!' | '^'));
+  if negated {
+    i += 1;
+  }
+  let mut matched = false;
+  let start = i;
+  loop {
+    let first = *pattern.get(i)?;
+    if first == ']' && i > start {
+      return Some((matched != negated, i + 1));
+    }
+    if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&last| last != ']') {
+      matched |= (first..=pattern[i + 2]).contains(&c);
+      i += 3;
+    } else {
+      matched |= first == c;
+      i += 1;
+    }
+  }
+}
+
+/// Whether `text` matches the shell glob `pattern`, as `fnmatch` without
+/// flags: `*` matches any run of characters, `/` included, `?` any single
+/// character, `[...]` a character class (negated by a leading `!` or `^`)
+/// and `\` escapes the character after it.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+  fn matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+      None => text.is_empty(),
+      Some('*') => {
+        let rest = &pattern[1..];
+        (0..=text.len()).any(|skip| matches(rest, &text[skip..]))
+      }
+      Some('?') => !text.is_empty() && matches(&pattern[1..], &text[1..]),
+      Some('[') if !text.is_empty() => match glob_class(pattern, text[0]) {
+        Some((matched, len)) => matched && matches(&pattern[len..], &text[1..]),
+        None => text[0] == '[' && matches(&pattern[1..], &text[1..]),
+      },
+      Some('\\') if pattern.len() > 1 => {
+        text.first() == Some(&pattern[1]) && matches(&pattern[2..], &text[1..])
+      }
+      Some(&c) => text.first() == Some(&c) && matches(&pattern[1..], &text[1..]),
+    }
+  }
+  matches(
+    &pattern.chars().collect::<Vec<_>>(),
+    &text.chars().collect::<Vec<_>>(),
+  )
+}
+
+/// Whether the `/`-separated `path` matches `pattern`, as git's `wildmatch`
+/// with `WM_PATHNAME`: like [`glob_match`], except that `*`, `?` and
+/// classes never match `/`, while a `**` that makes up a whole component
+/// matches any number of directories, none included.
+pub fn wildmatch(pattern: &str, path: &str) -> bool {
+  fn matches(pattern: &[char], at: usize, text: &[char]) -> bool {
+    match pattern.get(at) {
+      None => text.is_empty(),
+      Some('*') => {
+        let mut end = at;
+        while pattern.get(end) == Some(&'*') {
+          end += 1;
+        }
+        let whole_component = end - at >= 2
+          && (at == 0 || pattern[at - 1] == '/')
+          && pattern.get(end).is_none_or(|&c| c == '/');
+        if !whole_component {
+          let limit = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+          return (0..=limit).any(|skip| matches(pattern, end, &text[skip..]));
+        }
+        if end == pattern.len() {
+          return true;
+        }
+        // `**/` matches nothing or any run of leading directories.
+        matches(pattern, end + 1, text)
+          || (0..text.len()).any(|i| text[i] == '/' && matches(pattern, end + 1, &text[i + 1..]))
+      }
+      Some('?') => text.first().is_some_and(|&c| c != '/') && matches(pattern, at + 1, &text[1..]),
+      Some('[') if text.first().is_some_and(|&c| c != '/') => {
+        match glob_class(&pattern[at..], text[0]) {
+          Some((matched, len)) => matched && matches(pattern, at + len, &text[1..]),
+          None => text[0] == '[' && matches(pattern, at + 1, &text[1..]),
+        }
+      }
+      Some('\\') if at + 1 < pattern.len() => {
+        text.first() == Some(&pattern[at + 1]) && matches(pattern, at + 2, &text[1..])
+      }
+      Some(&c) => text.first() == Some(&c) && matches(pattern, at + 1, &text[1..]),
+    }
+  }
+  matches(
+    &pattern.chars().collect::<Vec<_>>(),
+    0,
+    &path.chars().collect::<Vec<_>>(),
+  )
+}
+
+/// The mode git records for a work tree path. Files with the owner's
+/// execute bit are `100755`; a directory at a tracked path is a nested
+/// repository, recorded as a `160000` gitlink.
+pub fn worktree_mode(metadata: &std::fs::Metadata) -> u32 {
+  let file_type = metadata.file_type();
+  if file_type.is_symlink() {
+    0o120000
+  } else if file_type.is_dir() {
+    0o160000
+  } else if metadata.permissions().mode() & 0o100 != 0 {
+    0o100755
+  } else {
+    0o100644
+  }
+}
+
+/// A work tree path as git records it in trees and the index.
+pub struct WorktreeEntry {
+  pub mode: u32,
+  pub oid: [u8; 20],
+  /// The blob holding the file content or symlink target, not yet written;
+  /// `None` for a gitlink, whose commit lives in the nested repository.
+  pub blob: Option<GitObject>,
+}
+
 #[allow(clippy::manual_non_exhaustive)]
 pub struct GitObject {
   pub kind: Kind,
@@ -45,121 +235,188 @@ pub struct GitObject {
 }
 
 impl GitObject {
-  pub fn hash_bytes(&self) -> anyhow::Result<[u8; 20]> {
+  /// Wraps serialized content, such as the output of [`Tree::serialize`], in
+  /// an object of the given kind.
+  pub fn from_content(kind: Kind, content: &[u8]) -> GitObject {
+    let mut data = Vec::from(format!("{} {}\0", kind, content.len()).as_bytes());
+    data.extend_from_slice(content);
+    GitObject {
+      kind,
+      size: content.len() as u64,
+      data,
+      _private: (),
+    }
+  }
+
+  /// The object content without its `<kind> <size>\0` header.
+  pub fn content(&self) -> &[u8] {
+    &self.data[self.data.len() - self.size as usize..]
+  }
+
+  pub fn hash_bytes(&self) -> Result<[u8; 20]> {
     GitObject::_hash(&self.data)
   }
 
-  pub fn hash(&self) -> anyhow::Result<String> {
+  pub fn hash(&self) -> Result<String> {
     let hash = self.hash_bytes()?;
     Ok(hex::encode(hash))
   }
 
-  pub fn write(&self, repo_path: &Path) -> anyhow::Result<()> {
+  pub fn write(&self, repo_path: &Path) -> Result<()> {
     let hash = self.hash()?;
     let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-    e.write_all(&self.data)?;
+    e.write_all(&self.data).context("compressing object")?;
     let out = e.finish().context("completing the write")?;
-    let dest_dir = repo_path.join(format!(".git/objects/{}", &hash[..2]));
-    std::fs::create_dir_all(dest_dir.clone()).context("creating git objects directory")?;
+    let dest_dir = git_dir(repo_path).join(format!("objects/{}", &hash[..2]));
+    std::fs::create_dir_all(&dest_dir).map_err(Error::io(&dest_dir))?;
     let dest_file = dest_dir.join(&hash[2..]);
-    let mut write = std::fs::File::create(dest_file).context("writing hashed file")?;
-    write.write_all(&out)?;
-    write.flush()?;
+    let mut write = std::fs::File::create(&dest_file).map_err(Error::io(&dest_file))?;
+    write.write_all(&out).map_err(Error::io(&dest_file))?;
+    write.flush().map_err(Error::io(&dest_file))?;
     Ok(())
   }
 
-  pub(crate) fn stdout(&self, writer: &mut dyn Write) -> anyhow::Result<()> {
-    let mut reader = BufReader::new(&self.data[..]);
-    let mut buf_header = Vec::<u8>::new();
-    reader.read_until(0, &mut buf_header)?;
-    let header = CStr::from_bytes_with_nul(&buf_header)
-      .context("malformed blob object")?
-      .to_str()?;
-    let header = header.to_string();
-    let (kind, size) = header
-      .split_once(' ')
-      .ok_or(anyhow::anyhow!("malformed blob object"))?;
-    let size = size.parse::<u64>().context("malformed blob object")?;
-    let kind = Kind::from_str(kind)?;
-    let mut reader = reader.take(size);
-    match kind {
+  pub(crate) fn stdout(&self, writer: &mut dyn Write) -> Result<()> {
+    match self.kind {
       Kind::Blob => {
-        let n =
-          std::io::copy(&mut reader, writer).context("copying from .git/objects to stdout")?;
-        anyhow::ensure!(
-          n == self.size,
-          ".git/object file not of expected size, expected: '{}', actual: '{}'",
-          self.size,
-          n
-        );
+        writer
+          .write_all(self.content())
+          .context("copying blob object to stdout")?;
       }
       Kind::Tree => {
-        let mut buf = Vec::new();
-        let mut entries = Vec::new();
-        loop {
-          buf.clear();
-          let n = reader.read_until(0, &mut buf)?;
-          if n == 0 {
-            break;
-          }
-          let line = CStr::from_bytes_with_nul(&buf)
-            .context("malformed tree object")?
-            .to_str()?;
-          let line = line.to_string();
-          let (_mode, name) = line
-            .split_once(' ')
-            .ok_or(anyhow::anyhow!("malformed tree object"))?;
-          entries.push(name.to_string());
-          reader.read_exact(&mut [0u8; 20])?; // ignore sha
+        for entry in Tree::parse(self.content())?.entries {
+          writeln!(
+            writer,
+            "{:0>6} {} {}\t{}",
+            entry.mode,
+            entry.kind(),
+            entry.hash(),
+            quote_path(&entry.name)
+          )
+          .context("writing tree entry to stdout")?;
         }
-        entries
-          .into_iter()
-          .for_each(|entry| writeln!(writer, "{}", entry).unwrap());
       }
-      Kind::Commit => anyhow::bail!("stdout not implemented for commit object"),
+      Kind::Commit => {
+        // Parsing only checks the structure: the content is printed as
+        // stored, in whatever `encoding` the commit declares.
+        Commit::parse(self.content())?;
+        writer
+          .write_all(self.content())
+          .context("copying commit object to stdout")?;
+      }
+      Kind::Tag => {
+        Tag::parse(self.content())?;
+        writer
+          .write_all(self.content())
+          .context("copying tag object to stdout")?;
+      }
     }
     Ok(())
   }
 }
 
 impl GitObject {
-  pub fn _hash(blob: &[u8]) -> anyhow::Result<[u8; 20]> {
+  pub fn _hash(blob: &[u8]) -> Result<[u8; 20]> {
     let mut hasher = Sha1::new();
     hasher.update(blob);
     let hash = hasher.finalize();
     Ok(hash.as_slice().try_into().expect("hash is always 20 bytes"))
   }
 
-  pub fn read_object(repo_path: &Path, object_hash: &str) -> anyhow::Result<GitObject> {
-    let filepath = format!(".git/objects/{}/{}", &object_hash[..2], &object_hash[2..]);
-    let filepath = repo_path.join(filepath);
-    let f = std::fs::File::open(&filepath)
-      .with_context(|| format!("opening file {}", &filepath.to_string_lossy()))?;
+  /// Every stored object whose hash starts with `name`, which must be 4 to 40
+  /// hex digits; anything else matches nothing.
+  pub fn matching_hashes(repo_path: &Path, name: &str) -> Result<Vec<String>> {
+    let prefix = name.to_ascii_lowercase();
+    if !(4..=40).contains(&prefix.len()) || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+      return Ok(Vec::new());
+    }
+    let mut candidates = Vec::new();
+    let fanout_dir = git_dir(repo_path).join(format!("objects/{}", &prefix[..2]));
+    if fanout_dir.is_dir() {
+      for entry in std::fs::read_dir(&fanout_dir).map_err(Error::io(&fanout_dir))? {
+        let rest = entry
+          .map_err(Error::io(&fanout_dir))?
+          .file_name()
+          .to_string_lossy()
+          .to_string();
+        if rest.len() == 38 && rest.starts_with(&prefix[2..]) {
+          candidates.push(format!("{}{rest}", &prefix[..2]));
+        }
+      }
+    }
+    for pack in pack::Pack::all(repo_path)? {
+      candidates.extend(pack.index.find_prefix(&prefix).iter().map(hex::encode));
+    }
+    candidates.sort();
+    candidates.dedup();
+    Ok(candidates)
+  }
+
+  /// Expands a full or abbreviated (at least 4 hex digits) object name to the
+  /// full hash of the single loose or packed object it identifies. Ref names
+  /// and other revision syntax are handled by [`crate::revision::resolve`].
+  pub fn resolve_hash(repo_path: &Path, name: &str) -> Result<String> {
+    if !(4..=40).contains(&name.len()) || !name.chars().all(|c| c.is_ascii_hexdigit()) {
+      return Err(Error::InvalidHash(name.to_string()));
+    }
+    let candidates = GitObject::matching_hashes(repo_path, name)?;
+    match &candidates[..] {
+      [] => Err(Error::MissingObject(name.to_string())),
+      [hash] => Ok(hash.clone()),
+      _ => {
+        let mut message =
+          format!("error: short object ID {name} is ambiguous\nhint: The candidates are:");
+        for hash in &candidates {
+          let kind = GitObject::read_object(repo_path, hash)?.kind;
+          message.push_str(&format!("\nhint:   {hash} {kind}"));
+        }
+        bail!(message)
+      }
+    }
+  }
+
+  pub fn read_object(repo_path: &Path, object_hash: &str) -> Result<GitObject> {
+    if object_hash.len() != 40 || !object_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+      return Err(Error::InvalidHash(object_hash.to_string()));
+    }
+    let filepath = format!("objects/{}/{}", &object_hash[..2], &object_hash[2..]);
+    let filepath = git_dir(repo_path).join(filepath);
+    if !filepath.exists() {
+      return match pack::read_packed_object(repo_path, object_hash)? {
+        Some(git_object) => Ok(git_object),
+        None => Err(Error::MissingObject(object_hash.to_string())),
+      };
+    }
+    let malformed = |reason: &str| Error::CorruptObject {
+      hash: object_hash.to_string(),
+      reason: reason.to_string(),
+    };
+    let f = std::fs::File::open(&filepath).map_err(Error::io(&filepath))?;
     let f = BufReader::new(f);
     let z = ZlibDecoder::new(f);
     let mut z = BufReader::new(z);
     let mut buf = Vec::<u8>::new();
     z.read_until(0, &mut buf)
-      .context("read header from .git/objects")?;
+      .map_err(|_| malformed("cannot inflate header"))?;
     let header =
-      CStr::from_bytes_with_nul(&buf[..]).expect("expecting valid c-string ending with '\\0'");
+      CStr::from_bytes_with_nul(&buf[..]).map_err(|_| malformed("unterminated header"))?;
     let header = header
       .to_str()
-      .context("git/objects file header isn't valid UTF-8")?;
-    let Some((kind, size)) = header.split_once(' ') else {
-      anyhow::bail!(".git/objects file header did not start with a known type: '{header}'");
-    };
-    let kind = match kind {
-      "blob" => Kind::Blob,
-      "tree" => Kind::Tree,
-      _ => anyhow::bail!("don't support kind: '{kind}'"),
-    };
+      .map_err(|_| malformed("header isn't valid UTF-8"))?;
+    let (kind, size) = header
+      .split_once(' ')
+      .ok_or_else(|| malformed("header has no size"))?;
+    let kind = Kind::from_str(kind).map_err(|_| malformed(&format!("unknown type '{kind}'")))?;
     let size = size
       .parse::<u64>()
-      .context(".git/objects file header has invalid size: {size}")?;
+      .map_err(|_| malformed(&format!("invalid size '{size}'")))?;
+    let header_len = buf.len() as u64;
     let mut z = z.take(size);
     z.read_to_end(&mut buf)
-      .context("reading from .git/objects to end")?;
+      .map_err(|_| malformed("cannot inflate content"))?;
+    if buf.len() as u64 != header_len + size {
+      return Err(malformed("content is shorter than its header says"));
+    }
     Ok(GitObject {
       kind,
       data: buf,
@@ -168,16 +425,42 @@ impl GitObject {
     })
   }
 
-  pub fn build_file_object(file: &Path) -> anyhow::Result<GitObject> {
+  /// Lists the hashes of every loose object under `.git/objects`, sorted.
+  pub fn loose_object_hashes(repo_path: &Path) -> Result<Vec<String>> {
+    let objects_dir = git_dir(repo_path).join("objects");
+    let mut hashes = Vec::new();
+    for fanout in std::fs::read_dir(&objects_dir).map_err(Error::io(&objects_dir))? {
+      let fanout = fanout.map_err(Error::io(&objects_dir))?;
+      let prefix = fanout.file_name().to_string_lossy().to_string();
+      if prefix.len() != 2 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+        continue;
+      }
+      let fanout_dir = fanout.path();
+      for entry in std::fs::read_dir(&fanout_dir).map_err(Error::io(&fanout_dir))? {
+        let rest = entry
+          .map_err(Error::io(&fanout_dir))?
+          .file_name()
+          .to_string_lossy()
+          .to_string();
+        if rest.len() == 38 && rest.chars().all(|c| c.is_ascii_hexdigit()) {
+          hashes.push(format!("{prefix}{rest}"));
+        }
+      }
+    }
+    hashes.sort();
+    Ok(hashes)
+  }
+
+  pub fn build_file_object(file: &Path) -> Result<GitObject> {
     if !file.is_file() {
-      anyhow::bail!("{} is not a file", file.display());
+      bail!("{} is not a file", file.display());
     }
-    let stat = std::fs::metadata(file).with_context(|| format!("stat {}", file.display()))?;
+    let stat = std::fs::metadata(file).map_err(Error::io(file))?;
     let size = format!("{}", stat.len());
     let mut data = Vec::<u8>::new();
     data.extend_from_slice(format!("{} {}\0", Kind::Blob, size).as_bytes());
-    let mut f = std::fs::File::open(file).context("reading the passed file")?;
-    f.read_to_end(&mut data).context("reading the given file")?;
+    let mut f = std::fs::File::open(file).map_err(Error::io(file))?;
+    f.read_to_end(&mut data).map_err(Error::io(file))?;
     Ok(GitObject {
       kind: Kind::Blob,
       size: stat.len(),
@@ -186,61 +469,101 @@ impl GitObject {
     })
   }
 
-  pub fn build_tree_object(current_path: &Path) -> anyhow::Result<GitObject> {
+  /// Hashes the file, symlink or nested repository at `path`: a symlink is
+  /// stored as a blob of its target and a nested repository as a gitlink to
+  /// the commit its `HEAD` points at.
+  pub fn build_worktree_entry(path: &Path) -> Result<WorktreeEntry> {
+    let metadata = std::fs::symlink_metadata(path).map_err(Error::io(path))?;
+    let file_type = metadata.file_type();
+    if !(file_type.is_file() || file_type.is_dir() || file_type.is_symlink()) {
+      return Err(Error::UnsupportedMode {
+        path: path.to_path_buf(),
+        mode: metadata.permissions().mode(),
+      });
+    }
+    let mode = worktree_mode(&metadata);
+    let blob = match mode {
+      0o120000 => {
+        let target = std::fs::read_link(path).map_err(Error::io(path))?;
+        GitObject::from_content(Kind::Blob, target.as_os_str().as_bytes())
+      }
+      0o160000 => {
+        let head = refs::head(path)?.ok_or(anyhow::anyhow!(
+          "error: '{}' does not have a commit checked out",
+          path.display()
+        ))?;
+        let oid = hex::decode(&head)
+          .ok()
+          .and_then(|oid| oid.try_into().ok())
+          .ok_or_else(|| Error::InvalidHash(head.clone()))?;
+        return Ok(WorktreeEntry {
+          mode,
+          oid,
+          blob: None,
+        });
+      }
+      _ => GitObject::build_file_object(path)?,
+    };
+    Ok(WorktreeEntry {
+      mode,
+      oid: blob.hash_bytes()?,
+      blob: Some(blob),
+    })
+  }
+
+  /// Hashes the directory `current_path`, taken as the top of a work tree,
+  /// as a tree. `.git` and the paths its ignore rules exclude are left out,
+  /// and so are directories with nothing left in them, as git has no empty
+  /// subtrees.
+  pub fn build_tree_object(current_path: &Path) -> Result<GitObject> {
     if !current_path.is_dir() {
-      anyhow::bail!("{} is not a directory", current_path.display())
-    }
-    let mut result = std::fs::read_dir(current_path)?
-      .filter_map(|entry| entry.ok())
-      .filter(|entry| {
-        let path = entry.path();
-        let relative = path.strip_prefix(current_path).unwrap();
-        !relative.starts_with(".git")
-      })
-      .collect::<Vec<_>>();
-    result.sort_by_key(|a| a.file_name());
-    let result = result
-      .into_iter()
-      .map(|entry| {
-        let file_type = entry.file_type().expect("filetype invalid");
-        if file_type.is_dir() {
-          (
-            entry.path(),
-            GitObject::build_tree_object(&entry.path()).unwrap(),
-          )
-        } else if file_type.is_file() {
-          (
-            entry.path(),
-            GitObject::build_file_object(&entry.path()).unwrap(),
-          )
-        } else {
-          panic!("unsupported file type {file_type:?} for {entry:?}")
+      bail!("{} is not a directory", current_path.display())
+    }
+    let mut rules = IgnoreRules::load(current_path)?;
+    GitObject::build_subtree_object(current_path, "", &mut rules)
+  }
+
+  /// Hashes `dir`, found at `prefix` below the top of the work tree.
+  fn build_subtree_object(dir: &Path, prefix: &str, rules: &mut IgnoreRules) -> Result<GitObject> {
+    let result = std::fs::read_dir(dir)
+      .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+      .map_err(Error::io(dir))?;
+    let mut entries = Vec::new();
+    for entry in result {
+      if prefix.is_empty() && entry.file_name() == ".git" {
+        continue;
+      }
+      let name = entry.file_name().to_string_lossy().to_string();
+      let file_type = entry.file_type().map_err(Error::io(entry.path()))?;
+      if rules.is_ignored(&format!("{prefix}{name}"), file_type.is_dir())? {
+        continue;
+      }
+      let path = entry.path();
+      let (mode, oid) = if file_type.is_dir() && !path.join(".git").exists() {
+        let subtree = GitObject::build_subtree_object(&path, &format!("{prefix}{name}/"), rules)?;
+        if subtree.size == 0 {
+          continue;
         }
-      })
-      .collect::<Vec<_>>();
-    let mut output = Vec::<u8>::new();
-    for (path, entry) in result {
-      let mode = match entry.kind {
-        Kind::Blob => "100644",
-        Kind::Tree => "40000",
-        Kind::Commit => anyhow::bail!("permission not required for commit object"),
+        (0o40000, subtree.hash_bytes()?)
+      } else {
+        let worktree_entry = GitObject::build_worktree_entry(&path)?;
+        (worktree_entry.mode, worktree_entry.oid)
       };
-      let relative_path = path.strip_prefix(current_path)?;
-      output
-        .extend_from_slice(format!("{} {}\0", mode, relative_path.to_string_lossy()).as_bytes());
-      output.extend_from_slice(&entry.hash_bytes()?);
+      entries.push(TreeEntry {
+        mode: format!("{mode:o}"),
+        name: entry.file_name().as_bytes().to_vec(),
+        oid,
+      });
     }
-    let size = output.len() as u64;
-    let header = format!("tree {}\x00", size);
-    let mut data = Vec::<u8>::new();
-    data.extend_from_slice(header.as_bytes());
-    data.extend_from_slice(&output[..]);
-    Ok(GitObject {
-      kind: Kind::Tree,
-      size,
-      data,
-      _private: (),
-    })
+    // git orders a subtree as if its name ended with `/`.
+    entries.sort_by_cached_key(|entry| match entry.kind() {
+      Kind::Tree => [&entry.name[..], b"/"].concat(),
+      _ => entry.name.clone(),
+    });
+    Ok(GitObject::from_content(
+      Kind::Tree,
+      &Tree { entries }.serialize(),
+    ))
   }
 
   pub fn build_commit_object(
@@ -248,24 +571,365 @@ impl GitObject {
     repo_path: &Path,
     message: &str,
     parent: Option<String>,
-  ) -> anyhow::Result<GitObject> {
+  ) -> Result<GitObject> {
     let tree_object = GitObject::read_object(repo_path, tree_hash)?;
-    let mut commit_content = Vec::from(format!("tree {}\n", tree_object.hash()?).as_bytes());
-    if let Some(parent) = parent {
-      commit_content.extend_from_slice(format!("parent {}\n", parent).as_bytes());
-    }
-    commit_content.extend_from_slice("author Coder <coder@crafters.io>\n".as_bytes());
-    commit_content.extend_from_slice("committer Coder <coder@crafters.io>\n\n".as_bytes());
-    commit_content.extend_from_slice(message.as_bytes());
-    commit_content.extend_from_slice("\n".as_bytes());
-    let size = commit_content.len() as u64;
-    let mut data = Vec::from(format!("commit {}\x00", size).as_bytes());
-    data.extend_from_slice(&commit_content);
-    Ok(GitObject {
-      kind: Kind::Commit,
-      size,
-      data,
-      _private: (),
+    let commit = Commit {
+      tree: tree_object.hash()?,
+      parents: parent.into_iter().collect(),
+      author: b"Coder <coder@crafters.io>".to_vec(),
+      committer: b"Coder <coder@crafters.io>".to_vec(),
+      extra_headers: Vec::new(),
+      message: format!("{message}\n").into_bytes(),
+    };
+    Ok(GitObject::from_content(Kind::Commit, &commit.serialize()))
+  }
+
+  pub fn build_tag_object(
+    object: &str,
+    kind: Kind,
+    tag: &str,
+    tagger: &str,
+    message: &str,
+  ) -> Result<GitObject> {
+    ensure!(
+      object.len() == 40 && object.chars().all(|c| c.is_ascii_hexdigit()),
+      "invalid object hash: '{object}'"
+    );
+    ensure!(
+      !tag.is_empty() && !tag.contains(['\n', ' ']),
+      "invalid tag name: '{tag}'"
+    );
+    let tag = Tag {
+      object: object.to_string(),
+      kind,
+      tag: tag.to_string(),
+      tagger: Some(tagger.as_bytes().to_vec()),
+      extra_headers: Vec::new(),
+      message: format!("{message}\n").into_bytes(),
+    };
+    Ok(GitObject::from_content(Kind::Tag, &tag.serialize()))
+  }
+}
+
+/// One `<mode> <name>\0<20-byte oid>` record of a tree object. The name is
+/// kept as bytes, since git allows any byte but `/` and NUL in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+  pub mode: String,
+  pub name: Vec<u8>,
+  pub oid: [u8; 20],
+}
+
+impl TreeEntry {
+  /// The name as text, with invalid UTF-8 replaced.
+  pub fn name_str(&self) -> Cow<'_, str> {
+    String::from_utf8_lossy(&self.name)
+  }
+
+  /// The kind of object the entry points at, derived from its mode.
+  pub fn kind(&self) -> Kind {
+    match self.mode.as_str() {
+      "40000" | "040000" => Kind::Tree,
+      "160000" => Kind::Commit,
+      _ => Kind::Blob,
+    }
+  }
+
+  pub fn hash(&self) -> String {
+    hex::encode(self.oid)
+  }
+}
+
+/// A blob is opaque bytes; the type exists so every object kind has the same
+/// `parse`/`serialize` pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blob {
+  pub data: Vec<u8>,
+}
+
+impl Blob {
+  pub fn parse(content: &[u8]) -> Result<Blob> {
+    Ok(Blob {
+      data: content.to_vec(),
     })
   }
+
+  pub fn serialize(&self) -> Vec<u8> {
+    self.data.clone()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tree {
+  pub entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+  pub fn parse(content: &[u8]) -> Result<Tree> {
+    Tree::decode(content).map_err(corrupt(Kind::Tree, content))
+  }
+
+  fn decode(content: &[u8]) -> anyhow::Result<Tree> {
+    let mut entries = Vec::new();
+    let mut rest = content;
+    while !rest.is_empty() {
+      let nul = rest
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(anyhow::anyhow!("malformed tree object"))?;
+      let space = rest[..nul]
+        .iter()
+        .position(|&b| b == b' ')
+        .ok_or(anyhow::anyhow!("malformed tree object"))?;
+      let mode = std::str::from_utf8(&rest[..space]).context("malformed tree object")?;
+      let name = &rest[space + 1..nul];
+      let Some(oid) = rest
+        .get(nul + 1..nul + 21)
+        .and_then(|oid| <[u8; 20]>::try_from(oid).ok())
+      else {
+        anyhow::bail!(
+          "malformed tree object: truncated entry '{}'",
+          String::from_utf8_lossy(name)
+        );
+      };
+      entries.push(TreeEntry {
+        mode: mode.to_string(),
+        name: name.to_vec(),
+        oid,
+      });
+      rest = &rest[nul + 21..];
+    }
+    Ok(Tree { entries })
+  }
+
+  /// Entries are written in the order given; callers building new trees are
+  /// responsible for git's sort order.
+  pub fn serialize(&self) -> Vec<u8> {
+    let mut content = Vec::new();
+    for entry in &self.entries {
+      content.extend_from_slice(entry.mode.as_bytes());
+      content.push(b' ');
+      content.extend_from_slice(&entry.name);
+      content.push(0);
+      content.extend_from_slice(&entry.oid);
+    }
+    content
+  }
+}
+
+/// Reports a parse failure against the hash of the object `content` belongs
+/// to, for use with `map_err`.
+fn corrupt(kind: Kind, content: &[u8]) -> impl FnOnce(anyhow::Error) -> Error + '_ {
+  move |reason| Error::CorruptObject {
+    hash: hex::encode(Sha1::digest(&GitObject::from_content(kind, content).data)),
+    reason: reason.to_string(),
+  }
+}
+
+/// A commit or tag header value as text, for headers git requires to be
+/// UTF-8, such as object names.
+fn header_text(value: Vec<u8>, name: &str, what: &str) -> anyhow::Result<String> {
+  String::from_utf8(value)
+    .map_err(|_| anyhow::anyhow!("malformed {what} object: '{name}' header isn't valid UTF-8"))
+}
+
+/// The header fields of a commit or tag, in order.
+type Headers = Vec<(String, Vec<u8>)>;
+
+/// Splits a commit or tag into its header fields and message. Header values
+/// that span several lines keep their line breaks, without the leading
+/// continuation space. Values and message stay bytes: an `encoding` header
+/// may declare them to be in something other than UTF-8.
+fn parse_headers<'a>(content: &'a [u8], what: &str) -> anyhow::Result<(Headers, &'a [u8])> {
+  let (header, message) = match content.windows(2).position(|pair| pair == b"\n\n") {
+    Some(at) => (&content[..at], &content[at + 2..]),
+    None => (content, &content[content.len()..]),
+  };
+  let mut headers = Headers::new();
+  for line in header.split(|&b| b == b'\n') {
+    if let Some(continuation) = line.strip_prefix(b" ") {
+      let (_, value) = headers.last_mut().ok_or(anyhow::anyhow!(
+        "malformed {what} object: leading continuation line"
+      ))?;
+      value.push(b'\n');
+      value.extend_from_slice(continuation);
+    } else {
+      let space = line.iter().position(|&b| b == b' ').ok_or_else(|| {
+        anyhow::anyhow!(
+          "malformed {what} object: header line '{}'",
+          String::from_utf8_lossy(line)
+        )
+      })?;
+      let key = header_text(line[..space].to_vec(), "header", what)?;
+      headers.push((key, line[space + 1..].to_vec()));
+    }
+  }
+  Ok((headers, message))
+}
+
+fn write_header(content: &mut Vec<u8>, key: &str, value: &[u8]) {
+  content.extend_from_slice(key.as_bytes());
+  content.push(b' ');
+  for &b in value {
+    content.push(b);
+    if b == b'\n' {
+      content.push(b' ');
+    }
+  }
+  content.push(b'\n');
+}
+
+fn expect_header(
+  headers: &mut impl Iterator<Item = (String, Vec<u8>)>,
+  name: &str,
+  what: &str,
+) -> anyhow::Result<Vec<u8>> {
+  match headers.next() {
+    Some((key, value)) if key == name => Ok(value),
+    _ => anyhow::bail!("malformed {what} object: missing '{name}' header"),
+  }
+}
+
+/// A parsed commit object. Header values that span several lines (such as
+/// `gpgsig`) keep their line breaks, without the leading continuation space.
+/// Signatures, extra header values and the message are bytes, in the
+/// `encoding` the commit declares (UTF-8 if none).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+  pub tree: String,
+  pub parents: Vec<String>,
+  pub author: Vec<u8>,
+  pub committer: Vec<u8>,
+  pub extra_headers: Vec<(String, Vec<u8>)>,
+  pub message: Vec<u8>,
+}
+
+impl Commit {
+  /// The author as text, with invalid UTF-8 replaced.
+  pub fn author_str(&self) -> Cow<'_, str> {
+    String::from_utf8_lossy(&self.author)
+  }
+
+  /// The committer as text, with invalid UTF-8 replaced.
+  pub fn committer_str(&self) -> Cow<'_, str> {
+    String::from_utf8_lossy(&self.committer)
+  }
+
+  /// The message as text, with invalid UTF-8 replaced.
+  pub fn message_str(&self) -> Cow<'_, str> {
+    String::from_utf8_lossy(&self.message)
+  }
+
+  pub fn parse(content: &[u8]) -> Result<Commit> {
+    Commit::decode(content).map_err(corrupt(Kind::Commit, content))
+  }
+
+  fn decode(content: &[u8]) -> anyhow::Result<Commit> {
+    let (headers, message) = parse_headers(content, "commit")?;
+    let mut headers = headers.into_iter().peekable();
+    let tree = header_text(
+      expect_header(&mut headers, "tree", "commit")?,
+      "tree",
+      "commit",
+    )?;
+    let mut parents = Vec::new();
+    while let Some((_, parent)) = headers.next_if(|(key, _)| key == "parent") {
+      parents.push(header_text(parent, "parent", "commit")?);
+    }
+    let author = expect_header(&mut headers, "author", "commit")?;
+    let committer = expect_header(&mut headers, "committer", "commit")?;
+    Ok(Commit {
+      tree,
+      parents,
+      author,
+      committer,
+      extra_headers: headers.collect(),
+      message: message.to_vec(),
+    })
+  }
+
+  pub fn serialize(&self) -> Vec<u8> {
+    let mut content = Vec::new();
+    write_header(&mut content, "tree", self.tree.as_bytes());
+    for parent in &self.parents {
+      write_header(&mut content, "parent", parent.as_bytes());
+    }
+    write_header(&mut content, "author", &self.author);
+    write_header(&mut content, "committer", &self.committer);
+    for (key, value) in &self.extra_headers {
+      write_header(&mut content, key, value);
+    }
+    content.push(b'\n');
+    content.extend_from_slice(&self.message);
+    content
+  }
+}
+
+/// A parsed annotated tag object. `tagger` is optional because very old tags
+/// were written without one; a PGP signature is part of `message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+  pub object: String,
+  pub kind: Kind,
+  pub tag: String,
+  pub tagger: Option<Vec<u8>>,
+  pub extra_headers: Vec<(String, Vec<u8>)>,
+  pub message: Vec<u8>,
+}
+
+impl Tag {
+  /// The tagger as text, with invalid UTF-8 replaced.
+  pub fn tagger_str(&self) -> Option<Cow<'_, str>> {
+    self.tagger.as_deref().map(String::from_utf8_lossy)
+  }
+
+  /// The message as text, with invalid UTF-8 replaced.
+  pub fn message_str(&self) -> Cow<'_, str> {
+    String::from_utf8_lossy(&self.message)
+  }
+
+  pub fn parse(content: &[u8]) -> Result<Tag> {
+    Tag::decode(content).map_err(corrupt(Kind::Tag, content))
+  }
+
+  fn decode(content: &[u8]) -> anyhow::Result<Tag> {
+    let (headers, message) = parse_headers(content, "tag")?;
+    let mut headers = headers.into_iter().peekable();
+    let object = header_text(
+      expect_header(&mut headers, "object", "tag")?,
+      "object",
+      "tag",
+    )?;
+    let kind = header_text(expect_header(&mut headers, "type", "tag")?, "type", "tag")?;
+    let kind = Kind::from_str(&kind)
+      .map_err(|_| anyhow::anyhow!("malformed tag object: invalid type '{kind}'"))?;
+    let tag = header_text(expect_header(&mut headers, "tag", "tag")?, "tag", "tag")?;
+    let tagger = headers
+      .next_if(|(key, _)| key == "tagger")
+      .map(|(_, tagger)| tagger);
+    Ok(Tag {
+      object,
+      kind,
+      tag,
+      tagger,
+      extra_headers: headers.collect(),
+      message: message.to_vec(),
+    })
+  }
+
+  pub fn serialize(&self) -> Vec<u8> {
+    let mut content = Vec::new();
+    write_header(&mut content, "object", self.object.as_bytes());
+    write_header(&mut content, "type", self.kind.to_string().as_bytes());
+    write_header(&mut content, "tag", self.tag.as_bytes());
+    if let Some(tagger) = &self.tagger {
+      write_header(&mut content, "tagger", tagger);
+    }
+    for (key, value) in &self.extra_headers {
+      write_header(&mut content, key, value);
+    }
+    content.push(b'\n');
+    content.extend_from_slice(&self.message);
+    content
+  }
 }
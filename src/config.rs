@@ -0,0 +1,159 @@
+use crate::common;
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+/// The variables of a git config file, in file order. Keys are stored as
+/// `section.name` or `section.subsection.name` with the section and name
+/// lowercased; subsections keep their case, as in git.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+  entries: Vec<(String, String)>,
+}
+
+impl Config {
+  /// Reads the user's global config followed by `.git/config`, so that, as
+  /// in git, the repository's own settings win. Missing files are empty.
+  pub fn read(repo_path: &Path) -> anyhow::Result<Config> {
+    let mut config = Config::default();
+    for path in global_paths() {
+      let source = path.display().to_string();
+      config
+        .entries
+        .extend(Config::read_file(&path, &source)?.entries);
+    }
+    let path = common::git_dir(repo_path).join("config");
+    config
+      .entries
+      .extend(Config::read_file(&path, ".git/config")?.entries);
+    Ok(config)
+  }
+
+  fn read_file(path: &Path, source: &str) -> anyhow::Result<Config> {
+    if !path.is_file() {
+      return Ok(Config::default());
+    }
+    let content = std::fs::read_to_string(path).with_context(|| format!("reading {source}"))?;
+    Config::parse_source(&content, source)
+  }
+
+  pub fn parse(content: &str) -> anyhow::Result<Config> {
+    Config::parse_source(content, ".git/config")
+  }
+
+  fn parse_source(content: &str, source: &str) -> anyhow::Result<Config> {
+    let mut entries = Vec::new();
+    let mut section = None::<String>;
+    for (number, line) in content.lines().enumerate() {
+      let malformed = || anyhow::anyhow!("fatal: bad config line {} in {source}", number + 1);
+      let line = line.trim();
+      if line.is_empty() || line.starts_with(['#', ';']) {
+        continue;
+      }
+      if let Some(header) = line.strip_prefix('[') {
+        let (header, _) = header.split_once(']').ok_or_else(malformed)?;
+        section = Some(match header.split_once(' ') {
+          Some((name, subsection)) => {
+            let subsection = subsection
+              .trim()
+              .strip_prefix('"')
+              .and_then(|s| s.strip_suffix('"'))
+              .ok_or_else(malformed)?
+              .replace("\\\"", "\"")
+              .replace("\\\\", "\\");
+            format!("{}.{subsection}", name.to_ascii_lowercase())
+          }
+          // The deprecated `[section.subsection]` form is case-insensitive.
+          None => header.to_ascii_lowercase(),
+        });
+        continue;
+      }
+      let section = section.as_ref().ok_or_else(malformed)?;
+      let (name, value) = match line.split_once('=') {
+        Some((name, value)) => (name.trim(), parse_value(value).ok_or_else(malformed)?),
+        None => (line, "true".to_string()),
+      };
+      anyhow::ensure!(
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'),
+        malformed()
+      );
+      entries.push((format!("{section}.{}", name.to_ascii_lowercase()), value));
+    }
+    Ok(Config { entries })
+  }
+
+  /// The last value set for `key` (e.g. `branch.main.remote`).
+  pub fn get(&self, key: &str) -> Option<&str> {
+    let key = normalize_key(key);
+    self
+      .entries
+      .iter()
+      .rev()
+      .find(|(name, _)| *name == key)
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+/// The global config files in the order git reads them: `$GIT_CONFIG_GLOBAL`
+/// alone when set, otherwise `$XDG_CONFIG_HOME/git/config` (by default
+/// `~/.config/git/config`) and then `~/.gitconfig`.
+fn global_paths() -> Vec<PathBuf> {
+  if let Some(path) = std::env::var_os("GIT_CONFIG_GLOBAL") {
+    return vec![PathBuf::from(path)];
+  }
+  let home = std::env::var_os("HOME").map(PathBuf::from);
+  let xdg = std::env::var_os("XDG_CONFIG_HOME")
+    .filter(|dir| !dir.is_empty())
+    .map(|dir| PathBuf::from(dir).join("git/config"))
+    .or_else(|| home.as_ref().map(|home| home.join(".config/git/config")));
+  xdg
+    .into_iter()
+    .chain(home.map(|home| home.join(".gitconfig")))
+    .collect()
+}
+
+/// Lowercases the section and variable name of `key` but not the subsection.
+fn normalize_key(key: &str) -> String {
+  match (key.split_once('.'), key.rsplit_once('.')) {
+    (Some((section, _)), Some((rest, name))) if rest.len() > section.len() => format!(
+      "{}{}.{}",
+      section.to_ascii_lowercase(),
+      &rest[section.len()..],
+      name.to_ascii_lowercase()
+    ),
+    _ => key.to_ascii_lowercase(),
+  }
+}
+
+/// Unquotes a value, handling escapes and stripping trailing comments.
+fn parse_value(value: &str) -> Option<String> {
+  let mut result = String::new();
+  let mut quoted = false;
+  let mut pending_space = String::new();
+  let mut chars = value.trim_start().chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' => quoted = !quoted,
+      '#' | ';' if !quoted => break,
+      '\\' => {
+        result.push_str(&pending_space);
+        pending_space.clear();
+        match chars.next()? {
+          'n' => result.push('\n'),
+          't' => result.push('\t'),
+          'b' => {
+            result.pop();
+          }
+          c @ ('"' | '\\') => result.push(c),
+          _ => return None,
+        }
+      }
+      c if c.is_whitespace() && !quoted => pending_space.push(c),
+      c => {
+        result.push_str(&pending_space);
+        pending_space.clear();
+        result.push(c);
+      }
+    }
+  }
+  (!quoted).then_some(result)
+}
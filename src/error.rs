@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+
+/// Why a `common` or `command` function failed. The specific variants cover
+/// failures a caller may want to react to; everything else is one of git's
+/// `fatal:`/`error:` diagnostics, kept in [`Error::Other`] for display.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+  /// An object is stored but cannot be decoded or parsed as its type.
+  #[error("fatal: corrupt object {hash}: {reason}")]
+  CorruptObject { hash: String, reason: String },
+  /// No loose or packed object has this name.
+  #[error("fatal: Not a valid object name {0}")]
+  MissingObject(String),
+  /// Reading or writing a file or directory failed.
+  #[error("fatal: cannot access '{}'", path.display())]
+  Io {
+    path: PathBuf,
+    #[source]
+    source: std::io::Error,
+  },
+  /// A work tree file that git cannot store, such as a socket or FIFO.
+  #[error("error: {}: unsupported file mode {mode:o}", path.display())]
+  UnsupportedMode { path: PathBuf, mode: u32 },
+  /// A string that is not a well-formed full or abbreviated object name.
+  #[error("fatal: invalid object name '{0}'")]
+  InvalidHash(String),
+  #[error(transparent)]
+  Other(anyhow::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+  /// Wraps an I/O error with the path it concerns, for use with `map_err`.
+  pub fn io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Error {
+    let path = path.into();
+    move |source| Error::Io { path, source }
+  }
+
+  /// Wraps a failure of the writer a command prints to or the reader it takes
+  /// input from, which have no path of their own; file system errors go
+  /// through [`Error::io`] instead.
+  pub fn stream(source: std::io::Error) -> Error {
+    Error::Other(source.into())
+  }
+}
+
+/// Keeps a typed error that travelled through an `anyhow` function, such as
+/// a missing object met while resolving a revision, matchable.
+impl From<anyhow::Error> for Error {
+  fn from(error: anyhow::Error) -> Error {
+    match error.downcast::<Error>() {
+      Ok(error) => error,
+      Err(error) => Error::Other(error),
+    }
+  }
+}
+
+/// Like [`anyhow::bail!`], for functions returning [`Result`].
+macro_rules! bail {
+  ($($arg:tt)+) => {
+    return Err($crate::error::Error::Other(anyhow::anyhow!($($arg)+)))
+  };
+}
+
+/// Like [`anyhow::ensure!`], for functions returning [`Result`].
+macro_rules! ensure {
+  ($cond:expr, $($arg:tt)+) => {
+    if !$cond {
+      $crate::error::bail!($($arg)+);
+    }
+  };
+}
+
+pub(crate) use bail;
+pub(crate) use ensure;
@@ -0,0 +1,210 @@
+use crate::common;
+use crate::config::Config;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// One line of an ignore file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+  /// The line as written, trailing whitespace removed.
+  pub text: String,
+  /// The file the pattern comes from, e.g. `.gitignore`, `src/.gitignore` or
+  /// `.git/info/exclude`.
+  pub source: String,
+  /// The 1-based line number in `source`.
+  pub line: usize,
+  /// A `!` pattern, which re-includes what earlier patterns excluded.
+  pub negated: bool,
+  glob: String,
+  /// A pattern with a trailing `/` only matches directories.
+  directory_only: bool,
+  /// A pattern without a `/` matches the last path component at any depth;
+  /// others match the whole path relative to `base`.
+  basename_only: bool,
+  /// The directory of the `.gitignore` the pattern comes from, with a
+  /// trailing `/`, or empty for the top of the work tree.
+  base: String,
+}
+
+impl Pattern {
+  /// Parses one line of an ignore file; blank lines and comments give `None`.
+  fn parse(line: &str, base: &str, source: &str, number: usize) -> Option<Pattern> {
+    let mut text = line.trim_end_matches(['\n', '\r']);
+    // Trailing spaces are dropped unless escaped with a backslash.
+    while text.ends_with(' ') && !text.ends_with("\\ ") {
+      text = &text[..text.len() - 1];
+    }
+    if text.is_empty() || text.starts_with('#') {
+      return None;
+    }
+    let (negated, glob) = match text.strip_prefix('!') {
+      Some(rest) => (true, rest),
+      None => (false, text),
+    };
+    let (directory_only, glob) = match glob.strip_suffix('/') {
+      Some(rest) => (true, rest),
+      None => (false, glob),
+    };
+    let basename_only = !glob.contains('/');
+    let glob = glob.strip_prefix('/').unwrap_or(glob);
+    Some(Pattern {
+      text: text.to_string(),
+      source: source.to_string(),
+      line: number,
+      negated,
+      glob: glob.to_string(),
+      directory_only,
+      basename_only,
+      base: base.to_string(),
+    })
+  }
+
+  fn matches(&self, path: &str, is_dir: bool) -> bool {
+    if self.directory_only && !is_dir {
+      return false;
+    }
+    let Some(relative) = path.strip_prefix(&self.base) else {
+      return false;
+    };
+    if self.basename_only {
+      let name = relative.rsplit('/').next().unwrap_or(relative);
+      common::glob_match(&self.glob, name)
+    } else {
+      common::wildmatch(&self.glob, relative)
+    }
+  }
+}
+
+fn read_patterns(path: &Path, base: &str, source: &str) -> anyhow::Result<Vec<Pattern>> {
+  let content = match std::fs::read(path) {
+    Ok(content) => content,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+  };
+  Ok(
+    String::from_utf8_lossy(&content)
+      .lines()
+      .enumerate()
+      .filter_map(|(number, line)| Pattern::parse(line, base, source, number + 1))
+      .collect(),
+  )
+}
+
+/// The file named by `core.excludesFile`, usually set in the global config,
+/// or `$XDG_CONFIG_HOME/git/ignore` or `~/.config/git/ignore` by default.
+fn excludes_file(config: &Config) -> Option<PathBuf> {
+  let home = std::env::var_os("HOME").map(PathBuf::from);
+  match config.get("core.excludesFile") {
+    Some(path) => match path.strip_prefix("~/") {
+      Some(rest) => Some(home?.join(rest)),
+      None => Some(PathBuf::from(path)),
+    },
+    None => match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+      Some(dir) => Some(PathBuf::from(dir).join("git/ignore")),
+      None => Some(home?.join(".config/git/ignore")),
+    },
+  }
+}
+
+/// The leading directories of `path`, outermost first, without the
+/// top-level directory.
+fn leading_directories(path: &str) -> impl Iterator<Item = &str> {
+  path.match_indices('/').map(|(at, _)| &path[..at])
+}
+
+/// git's ignore rules for a work tree. The `.gitignore` of a directory takes
+/// precedence over those of its parents, which take precedence over
+/// `.git/info/exclude` and then `core.excludesFile`; within a file, the last
+/// matching line wins.
+#[derive(Debug)]
+pub struct IgnoreRules {
+  work_tree: PathBuf,
+  /// The `core.excludesFile` patterns followed by `.git/info/exclude`.
+  global: Vec<Pattern>,
+  /// The patterns of each directory's `.gitignore`, keyed by the directory
+  /// (empty for the top), read when a path below it is first looked up.
+  per_directory: HashMap<String, Vec<Pattern>>,
+}
+
+impl IgnoreRules {
+  pub fn load(work_tree: &Path) -> anyhow::Result<IgnoreRules> {
+    let mut global = Vec::new();
+    if let Some(path) = excludes_file(&Config::read(work_tree)?) {
+      global.extend(read_patterns(&path, "", &path.to_string_lossy())?);
+    }
+    global.extend(read_patterns(
+      &common::git_dir(work_tree).join("info/exclude"),
+      "",
+      ".git/info/exclude",
+    )?);
+    Ok(IgnoreRules {
+      work_tree: work_tree.to_path_buf(),
+      global,
+      per_directory: HashMap::new(),
+    })
+  }
+
+  fn load_directory(&mut self, dir: &str) -> anyhow::Result<()> {
+    if !self.per_directory.contains_key(dir) {
+      let (base, source) = match dir {
+        "" => (String::new(), ".gitignore".to_string()),
+        dir => (format!("{dir}/"), format!("{dir}/.gitignore")),
+      };
+      let patterns = read_patterns(&self.work_tree.join(&source), &base, &source)?;
+      self.per_directory.insert(dir.to_string(), patterns);
+    }
+    Ok(())
+  }
+
+  /// The pattern that decides whether `path` itself is ignored, regardless
+  /// of its leading directories. A negated pattern means it is not.
+  pub fn matching(&mut self, path: &str, is_dir: bool) -> anyhow::Result<Option<&Pattern>> {
+    self.load_directory("")?;
+    for dir in leading_directories(path) {
+      self.load_directory(dir)?;
+    }
+    let mut dirs = vec![""];
+    dirs.extend(leading_directories(path));
+    Ok(
+      dirs
+        .iter()
+        .rev()
+        .map(|dir| &self.per_directory[*dir])
+        .chain([&self.global])
+        .find_map(|patterns| {
+          patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+        }),
+    )
+  }
+
+  /// Like [`IgnoreRules::matching`], except that a path inside an ignored
+  /// directory is reported with the pattern excluding that directory: git
+  /// never looks inside it, so nothing below can be re-included.
+  pub fn check(&mut self, path: &str, is_dir: bool) -> anyhow::Result<Option<&Pattern>> {
+    let mut excluded_dir = None;
+    for dir in leading_directories(path) {
+      if self.is_ignored(dir, true)? {
+        excluded_dir = Some(dir);
+        break;
+      }
+    }
+    match excluded_dir {
+      Some(dir) => self.matching(dir, true),
+      None => self.matching(path, is_dir),
+    }
+  }
+
+  /// Whether `path` itself is excluded, regardless of its leading
+  /// directories.
+  pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> anyhow::Result<bool> {
+    Ok(
+      self
+        .matching(path, is_dir)?
+        .is_some_and(|pattern| !pattern.negated),
+    )
+  }
+}
@@ -0,0 +1,601 @@
+use crate::common::{git_dir, GitObject, Kind, Tree, TreeEntry};
+use crate::lockfile::LockFile;
+use anyhow::Context;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+const INDEX_SIGNATURE: &[u8; 4] = b"DIRC";
+const TREE_SIGNATURE: &[u8; 4] = b"TREE";
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_INTENT_TO_ADD: u16 = 0x2000;
+
+/// Extensions that only speed up reading and describe byte offsets into the
+/// file, so they go stale as soon as the index is rewritten.
+const DROPPED_EXTENSIONS: [&[u8; 4]; 2] = [b"EOIE", b"IEOT"];
+
+fn read_u32(bytes: &[u8], at: usize) -> anyhow::Result<u32> {
+  let slice = bytes
+    .get(at..at + 4)
+    .ok_or(anyhow::anyhow!("unexpected end of index at {at}"))?;
+  Ok(u32::from_be_bytes(slice.try_into()?))
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> anyhow::Result<u16> {
+  let slice = bytes
+    .get(at..at + 2)
+    .ok_or(anyhow::anyhow!("unexpected end of index at {at}"))?;
+  Ok(u16::from_be_bytes(slice.try_into()?))
+}
+
+/// The file system metadata git caches to tell whether a tracked file
+/// changed without rehashing it. Values are truncated to 32 bits as in git.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stat {
+  pub ctime: u32,
+  pub ctime_nsec: u32,
+  pub mtime: u32,
+  pub mtime_nsec: u32,
+  pub dev: u32,
+  pub ino: u32,
+  pub uid: u32,
+  pub gid: u32,
+  pub size: u32,
+}
+
+impl Stat {
+  pub fn from_metadata(metadata: &std::fs::Metadata) -> Stat {
+    Stat {
+      ctime: metadata.ctime() as u32,
+      ctime_nsec: metadata.ctime_nsec() as u32,
+      mtime: metadata.mtime() as u32,
+      mtime_nsec: metadata.mtime_nsec() as u32,
+      dev: metadata.dev() as u32,
+      ino: metadata.ino() as u32,
+      uid: metadata.uid(),
+      gid: metadata.gid(),
+      size: metadata.size() as u32,
+    }
+  }
+}
+
+/// One staged path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+  pub stat: Stat,
+  /// The object type and permissions, e.g. `0o100644` or `0o120000`.
+  pub mode: u32,
+  pub oid: [u8; 20],
+  /// 0 for a resolved path, 1 to 3 for the base, ours and theirs sides of a
+  /// merge conflict.
+  pub stage: u8,
+  pub assume_valid: bool,
+  pub skip_worktree: bool,
+  pub intent_to_add: bool,
+  /// The path relative to the top of the work tree, `/`-separated.
+  pub path: String,
+}
+
+impl IndexEntry {
+  /// A stage 0 entry with no cached stat data.
+  pub fn new(path: &str, mode: u32, oid: [u8; 20]) -> IndexEntry {
+    IndexEntry {
+      stat: Stat::default(),
+      mode,
+      oid,
+      stage: 0,
+      assume_valid: false,
+      skip_worktree: false,
+      intent_to_add: false,
+      path: path.to_string(),
+    }
+  }
+
+  pub fn hash(&self) -> String {
+    hex::encode(self.oid)
+  }
+
+  fn has_extended_flags(&self) -> bool {
+    self.skip_worktree || self.intent_to_add
+  }
+}
+
+/// A raw index extension, kept so that rewriting an index git produced does
+/// not lose data this crate does not interpret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extension {
+  pub signature: [u8; 4],
+  pub data: Vec<u8>,
+}
+
+/// The staging area stored in `.git/index`, in the `DIRC` format versions 2
+/// and 3. Entries are kept sorted by path and then stage, as git requires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+  pub version: u32,
+  entries: Vec<IndexEntry>,
+  pub extensions: Vec<Extension>,
+}
+
+impl Default for Index {
+  fn default() -> Index {
+    Index {
+      version: 2,
+      entries: Vec::new(),
+      extensions: Vec::new(),
+    }
+  }
+}
+
+impl Index {
+  /// Reads `.git/index`; a missing file is an empty index.
+  pub fn read(repo_path: &Path) -> anyhow::Result<Index> {
+    let path = git_dir(repo_path).join("index");
+    if !path.is_file() {
+      return Ok(Index::default());
+    }
+    let bytes = std::fs::read(&path).context("reading .git/index")?;
+    Index::parse(&bytes).context("parsing .git/index")
+  }
+
+  pub fn parse(bytes: &[u8]) -> anyhow::Result<Index> {
+    anyhow::ensure!(bytes.len() >= 12 + 20, "index file is too short");
+    anyhow::ensure!(&bytes[..4] == INDEX_SIGNATURE, "bad index file signature");
+    let version = read_u32(bytes, 4)?;
+    anyhow::ensure!(
+      version == 2 || version == 3,
+      "unsupported index version {version}"
+    );
+    let (body, checksum) = bytes.split_at(bytes.len() - 20);
+    // With `index.skipHash` git leaves the checksum zeroed.
+    anyhow::ensure!(
+      checksum.iter().all(|&b| b == 0) || GitObject::_hash(body)? == checksum,
+      "index file checksum mismatch"
+    );
+
+    let count = read_u32(bytes, 8)? as usize;
+    // The count is only as good as the (possibly unchecked) body: an entry
+    // takes at least 64 bytes, 62 of fixed fields and a NUL padded to 8.
+    let mut entries = Vec::with_capacity(count.min(body.len().saturating_sub(12) / 64));
+    let mut at = 12;
+    for _ in 0..count {
+      let start = at;
+      let field = |i: usize| read_u32(body, start + i * 4);
+      let stat = Stat {
+        ctime: field(0)?,
+        ctime_nsec: field(1)?,
+        mtime: field(2)?,
+        mtime_nsec: field(3)?,
+        dev: field(4)?,
+        ino: field(5)?,
+        uid: field(7)?,
+        gid: field(8)?,
+        size: field(9)?,
+      };
+      let mode = field(6)?;
+      let oid = body
+        .get(start + 40..start + 60)
+        .ok_or(anyhow::anyhow!("unexpected end of index at {}", start + 40))?
+        .try_into()?;
+      let flags = read_u16(body, start + 60)?;
+      at = start + 62;
+      let extended = if flags & FLAG_EXTENDED != 0 {
+        anyhow::ensure!(
+          version >= 3,
+          "extended index entry flags in version {version}"
+        );
+        at += 2;
+        read_u16(body, start + 62)?
+      } else {
+        0
+      };
+      let name_len = body[at..]
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(anyhow::anyhow!("unterminated path in index entry"))?;
+      let path = std::str::from_utf8(&body[at..at + name_len])
+        .context("index entry path is not UTF-8")?
+        .to_string();
+      // The path is NUL-padded so each entry is a multiple of 8 bytes long.
+      at = start + (at - start + name_len + 8) / 8 * 8;
+      anyhow::ensure!(at <= body.len(), "index entry for '{path}' is truncated");
+      entries.push(IndexEntry {
+        stat,
+        mode,
+        oid,
+        stage: ((flags & FLAG_STAGE_MASK) >> 12) as u8,
+        assume_valid: flags & FLAG_ASSUME_VALID != 0,
+        skip_worktree: extended & EXTENDED_SKIP_WORKTREE != 0,
+        intent_to_add: extended & EXTENDED_INTENT_TO_ADD != 0,
+        path,
+      });
+    }
+
+    let mut extensions = Vec::new();
+    while at < body.len() {
+      let signature: [u8; 4] = body
+        .get(at..at + 4)
+        .ok_or(anyhow::anyhow!("truncated index extension"))?
+        .try_into()?;
+      let size = read_u32(body, at + 4)? as usize;
+      let data = body
+        .get(at + 8..at + 8 + size)
+        .ok_or(anyhow::anyhow!("truncated index extension"))?;
+      // Lowercase signatures mark extensions a reader must understand.
+      anyhow::ensure!(
+        signature[0].is_ascii_uppercase(),
+        "index uses the {} extension, which is not supported",
+        String::from_utf8_lossy(&signature)
+      );
+      if !DROPPED_EXTENSIONS.contains(&&signature) {
+        extensions.push(Extension {
+          signature,
+          data: data.to_vec(),
+        });
+      }
+      at += 8 + size;
+    }
+    Ok(Index {
+      version,
+      entries,
+      extensions,
+    })
+  }
+
+  /// The index file content, checksum included. Version 2 is bumped to 3
+  /// when an entry needs extended flags.
+  pub fn serialize(&self) -> anyhow::Result<Vec<u8>> {
+    let version = if self.entries.iter().any(IndexEntry::has_extended_flags) {
+      self.version.max(3)
+    } else {
+      self.version
+    };
+    let mut out = Vec::new();
+    out.extend_from_slice(INDEX_SIGNATURE);
+    out.extend_from_slice(&version.to_be_bytes());
+    out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+    for entry in &self.entries {
+      let start = out.len();
+      let stat = &entry.stat;
+      for field in [
+        stat.ctime,
+        stat.ctime_nsec,
+        stat.mtime,
+        stat.mtime_nsec,
+        stat.dev,
+        stat.ino,
+        entry.mode,
+        stat.uid,
+        stat.gid,
+        stat.size,
+      ] {
+        out.extend_from_slice(&field.to_be_bytes());
+      }
+      out.extend_from_slice(&entry.oid);
+      let mut flags = (entry.path.len().min(FLAG_NAME_MASK as usize) as u16)
+        | ((entry.stage as u16) << 12) & FLAG_STAGE_MASK;
+      if entry.assume_valid {
+        flags |= FLAG_ASSUME_VALID;
+      }
+      if entry.has_extended_flags() {
+        flags |= FLAG_EXTENDED;
+      }
+      out.extend_from_slice(&flags.to_be_bytes());
+      if entry.has_extended_flags() {
+        let mut extended = 0u16;
+        if entry.skip_worktree {
+          extended |= EXTENDED_SKIP_WORKTREE;
+        }
+        if entry.intent_to_add {
+          extended |= EXTENDED_INTENT_TO_ADD;
+        }
+        out.extend_from_slice(&extended.to_be_bytes());
+      }
+      out.extend_from_slice(entry.path.as_bytes());
+      let len = out.len() - start;
+      out.resize(start + (len + 8) / 8 * 8, 0);
+    }
+    for extension in &self.extensions {
+      out.extend_from_slice(&extension.signature);
+      out.extend_from_slice(&(extension.data.len() as u32).to_be_bytes());
+      out.extend_from_slice(&extension.data);
+    }
+    let checksum = GitObject::_hash(&out)?;
+    out.extend_from_slice(&checksum);
+    Ok(out)
+  }
+
+  /// Takes `.git/index.lock`; read the index after locking it so that the
+  /// update is based on the latest content.
+  pub fn lock(repo_path: &Path) -> anyhow::Result<LockFile> {
+    LockFile::acquire(&git_dir(repo_path).join("index"))
+  }
+
+  /// Writes the index through a lock taken with [`Index::lock`].
+  pub fn write(&self, mut lock: LockFile) -> anyhow::Result<()> {
+    lock.write_all(&self.serialize()?)?;
+    lock.commit()
+  }
+
+  /// Replaces the cached stat data of the stage 0 entry for `path` after its
+  /// content was found unchanged; the tree cache stays valid.
+  pub fn refresh(&mut self, path: &str, stat: Stat) {
+    if let Ok(position) = self.position(path, 0) {
+      self.entries[position].stat = stat;
+    }
+  }
+
+  pub fn entries(&self) -> &[IndexEntry] {
+    &self.entries
+  }
+
+  /// The stage 0 entry for `path`.
+  pub fn get(&self, path: &str) -> Option<&IndexEntry> {
+    self
+      .position(path, 0)
+      .ok()
+      .map(|position| &self.entries[position])
+  }
+
+  fn position(&self, path: &str, stage: u8) -> Result<usize, usize> {
+    self
+      .entries
+      .binary_search_by(|entry| (entry.path.as_str(), entry.stage).cmp(&(path, stage)))
+  }
+
+  /// Adds `entry`, replacing any entry for the same path and stage. A stage
+  /// 0 entry resolves a conflict, so it also replaces stages 1 to 3.
+  pub fn insert(&mut self, entry: IndexEntry) {
+    let entry_path = entry.path.clone();
+    if entry.stage == 0 {
+      self
+        .entries
+        .retain(|existing| existing.path != entry.path || existing.stage == 0);
+    }
+    match self.position(&entry.path, entry.stage) {
+      Ok(position) => self.entries[position] = entry,
+      Err(position) => self.entries.insert(position, entry),
+    }
+    self.invalidate_tree_cache(&entry_path);
+  }
+
+  /// Removes every stage of `path`; returns whether it was staged.
+  pub fn remove(&mut self, path: &str) -> bool {
+    let len = self.entries.len();
+    self.entries.retain(|entry| entry.path != path);
+    let removed = self.entries.len() != len;
+    if removed {
+      self.invalidate_tree_cache(path);
+    }
+    removed
+  }
+
+  /// The cached trees of the `TREE` extension, if the index has one.
+  pub fn cache_tree(&self) -> anyhow::Result<Option<CacheTree>> {
+    self
+      .extensions
+      .iter()
+      .find(|extension| &extension.signature == TREE_SIGNATURE)
+      .map(|extension| CacheTree::parse(&extension.data))
+      .transpose()
+  }
+
+  /// Replaces the `TREE` extension, which git writes first.
+  pub fn set_cache_tree(&mut self, cache_tree: &CacheTree) {
+    self
+      .extensions
+      .retain(|extension| &extension.signature != TREE_SIGNATURE);
+    self.extensions.insert(
+      0,
+      Extension {
+        signature: *TREE_SIGNATURE,
+        data: cache_tree.serialize(),
+      },
+    );
+  }
+
+  /// Marks the cached trees of the directories containing `path` as stale,
+  /// keeping those of unrelated directories for [`Index::write_tree`].
+  fn invalidate_tree_cache(&mut self, path: &str) {
+    match self.cache_tree() {
+      Ok(Some(mut cache_tree)) => {
+        cache_tree.invalidate(path);
+        self.set_cache_tree(&cache_tree);
+      }
+      Ok(None) => {}
+      Err(_) => self
+        .extensions
+        .retain(|extension| &extension.signature != TREE_SIGNATURE),
+    }
+  }
+
+  /// Writes the tree objects for the staged entries and returns the hash of
+  /// the root tree. Directories whose cached tree is still valid are reused
+  /// without being rebuilt, and the cache is refreshed for the rest.
+  pub fn write_tree(&mut self, repo_path: &Path) -> anyhow::Result<String> {
+    if let Some(entry) = self.entries.iter().find(|entry| entry.stage != 0) {
+      anyhow::bail!(
+        "error: {}: unmerged ({})\nfatal: git-write-tree: error building trees",
+        entry.path,
+        entry.hash()
+      );
+    }
+    // A cache that fails to parse is rebuilt from scratch.
+    let cache_tree = self.cache_tree().ok().flatten();
+    let (cache_tree, oid) = build_tree(repo_path, &self.entries, 0, cache_tree.as_ref())?;
+    self.set_cache_tree(&cache_tree);
+    Ok(hex::encode(oid))
+  }
+}
+
+/// Builds the tree for `entries`, which all share a directory prefix of
+/// `prefix_len` bytes, writing it and any rebuilt subtrees to the object
+/// store.
+fn build_tree(
+  repo_path: &Path,
+  entries: &[IndexEntry],
+  prefix_len: usize,
+  cache_tree: Option<&CacheTree>,
+) -> anyhow::Result<(CacheTree, [u8; 20])> {
+  if let Some(cache_tree) = cache_tree {
+    if let Some((count, oid)) = cache_tree.cached {
+      if count == entries.len() {
+        return Ok((cache_tree.clone(), oid));
+      }
+    }
+  }
+  let mut tree_entries = Vec::new();
+  let mut subtrees = Vec::new();
+  // Intent-to-add entries stay out of the tree, so a tree built while
+  // there are any must not be reused once they are added for real.
+  let mut valid = true;
+  let mut i = 0;
+  while i < entries.len() {
+    let entry = &entries[i];
+    let name = &entry.path[prefix_len..];
+    let Some((dir, _)) = name.split_once('/') else {
+      if entry.intent_to_add {
+        valid = false;
+      } else {
+        tree_entries.push(TreeEntry {
+          mode: format!("{:o}", entry.mode),
+          name: name.as_bytes().to_vec(),
+          oid: entry.oid,
+        });
+      }
+      i += 1;
+      continue;
+    };
+    // The index sorts `dir/...` paths together, and in the same order as
+    // git sorts tree entries, so a directory is one contiguous run.
+    let child_prefix = &entry.path[..prefix_len + dir.len() + 1];
+    let end = i
+      + entries[i..]
+        .iter()
+        .take_while(|entry| entry.path.starts_with(child_prefix))
+        .count();
+    let child_cache = cache_tree.and_then(|cache_tree| {
+      cache_tree
+        .subtrees
+        .iter()
+        .find(|(name, _)| name == dir)
+        .map(|(_, subtree)| subtree)
+    });
+    let (subtree, oid) = build_tree(repo_path, &entries[i..end], child_prefix.len(), child_cache)?;
+    valid &= subtree.cached.is_some();
+    let empty = entries[i..end].iter().all(|entry| entry.intent_to_add);
+    if !empty {
+      tree_entries.push(TreeEntry {
+        mode: "40000".to_string(),
+        name: dir.as_bytes().to_vec(),
+        oid,
+      });
+    }
+    subtrees.push((dir.to_string(), subtree));
+    i = end;
+  }
+  let tree = GitObject::from_content(
+    Kind::Tree,
+    &Tree {
+      entries: tree_entries,
+    }
+    .serialize(),
+  );
+  tree.write(repo_path)?;
+  let oid = tree.hash_bytes()?;
+  // git orders cached subtrees by name length first.
+  subtrees.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+  Ok((
+    CacheTree {
+      cached: valid.then_some((entries.len(), oid)),
+      subtrees,
+    },
+    oid,
+  ))
+}
+
+/// A directory in the `TREE` extension: the tree object git last wrote for
+/// it, so that `write-tree` can skip directories that did not change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheTree {
+  /// How many index entries the directory covers and the hash of its tree;
+  /// `None` once an entry below it changed.
+  pub cached: Option<(usize, [u8; 20])>,
+  /// The cached subdirectories, by name.
+  pub subtrees: Vec<(String, CacheTree)>,
+}
+
+impl CacheTree {
+  pub fn parse(data: &[u8]) -> anyhow::Result<CacheTree> {
+    let (_, cache_tree, rest) = CacheTree::parse_node(data)?;
+    anyhow::ensure!(rest.is_empty(), "trailing data in the TREE extension");
+    Ok(cache_tree)
+  }
+
+  /// Parses `<name>\0<entry count> <subtree count>\n[<hash>]` followed by
+  /// the subtrees, returning the name and what follows.
+  fn parse_node(data: &[u8]) -> anyhow::Result<(String, CacheTree, &[u8])> {
+    let malformed = || anyhow::anyhow!("malformed TREE extension");
+    let nul = data.iter().position(|&b| b == 0).ok_or_else(malformed)?;
+    let name = std::str::from_utf8(&data[..nul])
+      .map_err(|_| malformed())?
+      .to_string();
+    let rest = &data[nul + 1..];
+    let newline = rest
+      .iter()
+      .position(|&b| b == b'\n')
+      .ok_or_else(malformed)?;
+    let header = std::str::from_utf8(&rest[..newline]).map_err(|_| malformed())?;
+    let (entry_count, subtree_count) = header.split_once(' ').ok_or_else(malformed)?;
+    let entry_count = entry_count.parse::<i64>().map_err(|_| malformed())?;
+    let subtree_count = subtree_count.parse::<usize>().map_err(|_| malformed())?;
+    let mut rest = &rest[newline + 1..];
+    let cached = if entry_count >= 0 {
+      let oid = rest.get(..20).ok_or_else(malformed)?.try_into()?;
+      rest = &rest[20..];
+      Some((entry_count as usize, oid))
+    } else {
+      None
+    };
+    let mut subtrees = Vec::with_capacity(subtree_count);
+    for _ in 0..subtree_count {
+      let (name, subtree, after) = CacheTree::parse_node(rest)?;
+      subtrees.push((name, subtree));
+      rest = after;
+    }
+    Ok((name, CacheTree { cached, subtrees }, rest))
+  }
+
+  pub fn serialize(&self) -> Vec<u8> {
+    let mut out = Vec::new();
+    self.serialize_node("", &mut out);
+    out
+  }
+
+  fn serialize_node(&self, name: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    let entry_count = self.cached.map_or(-1, |(count, _)| count as i64);
+    out.extend_from_slice(format!("{entry_count} {}\n", self.subtrees.len()).as_bytes());
+    if let Some((_, oid)) = &self.cached {
+      out.extend_from_slice(oid);
+    }
+    for (name, subtree) in &self.subtrees {
+      subtree.serialize_node(name, out);
+    }
+  }
+
+  /// Marks this directory and those on the way to `path` as stale.
+  pub fn invalidate(&mut self, path: &str) {
+    self.cached = None;
+    if let Some((dir, rest)) = path.split_once('/') {
+      if let Some((_, subtree)) = self.subtrees.iter_mut().find(|(name, _)| name == dir) {
+        subtree.invalidate(rest);
+      }
+    }
+  }
+}
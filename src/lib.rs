@@ -1,2 +1,11 @@
 pub mod command;
 pub mod common;
+pub mod config;
+pub mod error;
+pub mod ignore;
+pub mod index;
+pub mod lockfile;
+pub mod pack;
+pub mod reflog;
+pub mod refs;
+pub mod revision;
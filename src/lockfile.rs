@@ -0,0 +1,83 @@
+use anyhow::Context;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// An exclusive `<path>.lock` file, git's way of serialising writers. The new
+/// content is written to the lock file and renamed over `path` on
+/// [`LockFile::commit`]; dropping the lock without committing removes it and
+/// leaves `path` untouched.
+pub struct LockFile {
+  path: PathBuf,
+  lock_path: PathBuf,
+  file: Option<File>,
+}
+
+impl LockFile {
+  /// Creates `<path>.lock`, failing if another writer already holds it.
+  pub fn acquire(path: &Path) -> anyhow::Result<LockFile> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+    if let Some(parent) = path.parent() {
+      std::fs::create_dir_all(parent)
+        .with_context(|| format!("creating directory {}", parent.display()))?;
+    }
+    let file = match OpenOptions::new()
+      .write(true)
+      .create_new(true)
+      .open(&lock_path)
+    {
+      Ok(file) => file,
+      Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => anyhow::bail!(
+        "Unable to create '{}': File exists.\n\n\
+         Another git process seems to be running in this repository. If it\n\
+         crashed, remove the file manually to continue.",
+        lock_path.display()
+      ),
+      Err(e) => {
+        return Err(e).with_context(|| format!("Unable to create '{}'", lock_path.display()))
+      }
+    };
+    Ok(LockFile {
+      path: path.to_path_buf(),
+      lock_path,
+      file: Some(file),
+    })
+  }
+
+  /// The file this lock protects.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub fn write_all(&mut self, content: &[u8]) -> anyhow::Result<()> {
+    let file = self.file.as_mut().expect("lock file is open until dropped");
+    file
+      .write_all(content)
+      .with_context(|| format!("writing {}", self.lock_path.display()))
+  }
+
+  /// Moves the written content into place, releasing the lock.
+  pub fn commit(mut self) -> anyhow::Result<()> {
+    self
+      .file
+      .as_ref()
+      .expect("lock file is open until dropped")
+      .sync_all()
+      .with_context(|| format!("flushing {}", self.lock_path.display()))?;
+    drop(self.file.take());
+    std::fs::rename(&self.lock_path, &self.path).map_err(|e| {
+      let _ = std::fs::remove_file(&self.lock_path);
+      anyhow::anyhow!("renaming {} into place: {e}", self.lock_path.display())
+    })
+  }
+}
+
+impl Drop for LockFile {
+  fn drop(&mut self) {
+    if self.file.take().is_some() {
+      let _ = std::fs::remove_file(&self.lock_path);
+    }
+  }
+}
@@ -1,9 +1,24 @@
 use clap::Parser;
 use clap::Subcommand;
 use git_starter_rust::command::commit_tree;
-use git_starter_rust::command::{cat_file, hash_object, init, ls_tree, write_tree};
+use git_starter_rust::command::BranchListOptions;
+use git_starter_rust::command::CatFileMode;
+use git_starter_rust::command::ForEachRefOptions;
+use git_starter_rust::command::LsTreeOptions;
+use git_starter_rust::command::RevParseOptions;
+use git_starter_rust::command::ShowRefOptions;
+use git_starter_rust::command::StatusFormat;
+use git_starter_rust::command::StatusOptions;
+use git_starter_rust::command::TagListOptions;
+use git_starter_rust::command::{
+  add, branch_create, branch_delete, branch_list, branch_rename, branch_show_current, cat_file,
+  cat_file_batch, check_ignore, for_each_ref, hash_object, index_pack, init, ls_tree, mktag,
+  pack_refs, reflog_delete, reflog_expire, reflog_show, repack, rev_parse, rm, show_ref, status,
+  symbolic_ref, tag, tag_delete, tag_list, update_ref, update_ref_stdin, verify_pack, write_tree,
+};
+use git_starter_rust::pack::PackOptions;
 use std::env;
-use std::io::stdout;
+use std::io::{stdin, stdout, BufWriter, Write};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -16,10 +31,21 @@ struct Args {
 #[derive(Debug, Subcommand)]
 enum Command {
   Init,
+  #[command(group(clap::ArgGroup::new("mode").required(true)))]
   CatFile {
-    #[clap(short = 'p')]
+    #[clap(short = 'p', group = "mode", requires = "object_hash")]
     pretty_print: bool,
-    object_hash: String,
+    #[clap(short = 't', group = "mode", requires = "object_hash")]
+    show_type: bool,
+    #[clap(short = 's', group = "mode", requires = "object_hash")]
+    size: bool,
+    #[clap(short = 'e', group = "mode", requires = "object_hash")]
+    exists: bool,
+    #[clap(long, group = "mode", conflicts_with = "object_hash")]
+    batch: bool,
+    #[clap(long, group = "mode", conflicts_with = "object_hash")]
+    batch_check: bool,
+    object_hash: Option<String>,
   },
   HashObject {
     #[clap(short = 'w')]
@@ -29,7 +55,18 @@ enum Command {
   LsTree {
     #[clap(long)]
     name_only: bool,
+    #[clap(short = 'r')]
+    recursive: bool,
+    #[clap(short = 't')]
+    show_trees: bool,
+    #[clap(short = 'd')]
+    only_trees: bool,
+    #[clap(short = 'l', long = "long")]
+    long: bool,
+    #[clap(long)]
+    full_name: bool,
     object_hash: String,
+    paths: Vec<String>,
   },
   WriteTree,
   CommitTree {
@@ -39,6 +76,178 @@ enum Command {
     parent: Option<String>,
     tree_hash: String,
   },
+  Repack {
+    #[clap(short = 'd')]
+    delete: bool,
+    #[clap(long, default_value_t = 10)]
+    window: usize,
+    #[clap(long, default_value_t = 50)]
+    depth: usize,
+  },
+  IndexPack {
+    pack: PathBuf,
+  },
+  VerifyPack {
+    #[clap(short = 'v')]
+    verbose: bool,
+    pack: PathBuf,
+  },
+  Mktag,
+  #[command(group(clap::ArgGroup::new("action").args(["delete", "list"])))]
+  Tag {
+    #[clap(short = 'a', conflicts_with = "action")]
+    annotate: bool,
+    #[clap(short = 'm', conflicts_with = "action")]
+    message: Option<String>,
+    #[clap(short = 'd', long)]
+    delete: bool,
+    #[clap(short = 'l', long)]
+    list: bool,
+    /// Print up to <n> lines of each tag's annotation.
+    #[clap(short = 'n', num_args = 0..=1, default_missing_value = "1")]
+    lines: Option<usize>,
+    #[clap(long)]
+    sort: Option<String>,
+    args: Vec<String>,
+  },
+  UpdateRef {
+    #[clap(short = 'm', default_value = "")]
+    message: String,
+    #[clap(short = 'd')]
+    delete: bool,
+    #[clap(long)]
+    no_deref: bool,
+    #[clap(long, conflicts_with_all = ["delete", "ref_name"])]
+    stdin: bool,
+    #[clap(required_unless_present = "stdin")]
+    ref_name: Option<String>,
+    /// The new value, or with -d the expected old value.
+    #[clap(required_unless_present_any = ["delete", "stdin"])]
+    new_value: Option<String>,
+    old_value: Option<String>,
+  },
+  SymbolicRef {
+    #[clap(short = 'd', long, conflicts_with = "target")]
+    delete: bool,
+    #[clap(long)]
+    short: bool,
+    name: String,
+    target: Option<String>,
+  },
+  PackRefs {
+    #[clap(long)]
+    all: bool,
+  },
+  Reflog {
+    #[command(subcommand)]
+    command: Option<ReflogCommand>,
+  },
+  RevParse {
+    #[clap(long)]
+    verify: bool,
+    #[clap(long, num_args = 0..=1, default_missing_value = "7", require_equals = true)]
+    short: Option<usize>,
+    #[clap(long)]
+    abbrev_ref: bool,
+    #[clap(allow_hyphen_values = true)]
+    args: Vec<String>,
+  },
+  #[command(group(
+    clap::ArgGroup::new("action")
+      .args(["delete", "force_delete", "rename", "force_rename", "show_current"])
+  ))]
+  Branch {
+    #[clap(short = 'd', long)]
+    delete: bool,
+    #[clap(short = 'D')]
+    force_delete: bool,
+    #[clap(short = 'm', long = "move")]
+    rename: bool,
+    #[clap(short = 'M')]
+    force_rename: bool,
+    #[clap(long)]
+    show_current: bool,
+    #[clap(short = 'v', long)]
+    verbose: bool,
+    #[clap(long, conflicts_with = "action")]
+    contains: Option<String>,
+    args: Vec<String>,
+  },
+  ShowRef {
+    #[clap(long)]
+    heads: bool,
+    #[clap(long)]
+    tags: bool,
+    #[clap(long, conflicts_with_all = ["heads", "tags"])]
+    verify: bool,
+    patterns: Vec<String>,
+  },
+  Add {
+    #[clap(short = 'A', long)]
+    all: bool,
+    #[clap(short = 'u', long, conflicts_with = "all")]
+    update: bool,
+    /// Allow adding files the ignore rules exclude.
+    #[clap(short = 'f', long)]
+    force: bool,
+    pathspecs: Vec<String>,
+  },
+  CheckIgnore {
+    #[clap(short = 'v', long)]
+    verbose: bool,
+    /// Also list the paths that match no pattern.
+    #[clap(short = 'n', long, requires = "verbose")]
+    non_matching: bool,
+    #[clap(required = true)]
+    paths: Vec<String>,
+  },
+  Rm {
+    #[clap(long)]
+    cached: bool,
+    #[clap(short = 'r')]
+    recursive: bool,
+    #[clap(short = 'f', long)]
+    force: bool,
+    #[clap(required = true)]
+    pathspecs: Vec<String>,
+  },
+  Status {
+    #[clap(short = 's', long)]
+    short: bool,
+    #[clap(short = 'b', long)]
+    branch: bool,
+    /// Machine readable output, `v1` (the default) or `v2`.
+    #[clap(long, num_args = 0..=1, default_missing_value = "v1", require_equals = true)]
+    porcelain: Option<String>,
+  },
+  ForEachRef {
+    #[clap(long)]
+    format: Option<String>,
+    #[clap(long)]
+    sort: Option<String>,
+    #[clap(long)]
+    count: Option<usize>,
+    patterns: Vec<String>,
+  },
+}
+
+#[derive(Debug, Subcommand)]
+enum ReflogCommand {
+  Show {
+    #[clap(default_value = "HEAD")]
+    name: String,
+  },
+  Expire {
+    #[clap(long)]
+    expire: Option<String>,
+    #[clap(long)]
+    all: bool,
+    names: Vec<String>,
+  },
+  Delete {
+    #[clap(required = true)]
+    selectors: Vec<String>,
+  },
 }
 
 fn main() -> anyhow::Result<()> {
@@ -49,21 +258,270 @@ fn main() -> anyhow::Result<()> {
     Command::Init => init(&current_dir, &mut stdout)?,
     Command::CatFile {
       pretty_print,
+      show_type,
+      size,
+      exists,
+      batch,
+      batch_check,
       object_hash,
-    } => cat_file(&object_hash, &mut stdout, &current_dir, pretty_print)?,
+    } => {
+      if batch || batch_check {
+        let mut writer = BufWriter::new(stdout.lock());
+        cat_file_batch(&mut stdin().lock(), &mut writer, &current_dir, batch)?
+      } else {
+        let mode = if pretty_print {
+          CatFileMode::PrettyPrint
+        } else if show_type {
+          CatFileMode::Type
+        } else if size {
+          CatFileMode::Size
+        } else {
+          debug_assert!(exists);
+          CatFileMode::Exists
+        };
+        let object_hash = object_hash.expect("clap requires an object for this mode");
+        cat_file(&object_hash, &mut stdout, &current_dir, mode)?
+      }
+    }
     Command::HashObject { write, file } => {
       hash_object(file.as_path(), &mut stdout, &current_dir, write)?
     }
     Command::LsTree {
       name_only,
+      recursive,
+      show_trees,
+      only_trees,
+      long,
+      full_name,
       object_hash,
-    } => ls_tree(&object_hash, &mut stdout, &current_dir, name_only)?,
+      paths,
+    } => {
+      let options = LsTreeOptions {
+        name_only,
+        recursive,
+        show_trees,
+        only_trees,
+        long,
+        full_name,
+      };
+      ls_tree(&object_hash, &mut stdout, &current_dir, &options, &paths)?
+    }
     Command::WriteTree => write_tree(&current_dir, &mut stdout)?,
     Command::CommitTree {
       message,
       tree_hash,
       parent,
     } => commit_tree(tree_hash, &mut stdout, &current_dir, &message, parent)?,
+    Command::Repack {
+      delete,
+      window,
+      depth,
+    } => repack(
+      &current_dir,
+      &mut stdout,
+      delete,
+      &PackOptions { window, depth },
+    )?,
+    Command::IndexPack { pack } => index_pack(&pack, &mut stdout)?,
+    Command::VerifyPack { verbose, pack } => verify_pack(&pack, &mut stdout, verbose)?,
+    Command::Mktag => mktag(&mut stdin(), &mut stdout, &current_dir)?,
+    Command::Tag {
+      annotate,
+      message,
+      delete,
+      list,
+      lines,
+      sort,
+      args,
+    } => {
+      if delete {
+        tag_delete(&args, &mut stdout, &current_dir)?
+      } else if list || lines.is_some() || args.is_empty() {
+        let options = TagListOptions {
+          patterns: args,
+          sort,
+          lines,
+        };
+        tag_list(&mut stdout, &current_dir, &options)?
+      } else {
+        match &args[..] {
+          [name] => tag(name, &current_dir, annotate, message, "HEAD")?,
+          [name, object] => tag(name, &current_dir, annotate, message, object)?,
+          _ => anyhow::bail!("fatal: too many arguments"),
+        }
+      }
+    }
+    Command::UpdateRef {
+      message,
+      delete,
+      no_deref,
+      stdin: true,
+      ..
+    } => {
+      debug_assert!(!delete);
+      update_ref_stdin(&mut stdin().lock(), &current_dir, &message, no_deref)?
+    }
+    Command::UpdateRef {
+      message,
+      delete,
+      no_deref,
+      ref_name,
+      new_value,
+      old_value,
+      ..
+    } => {
+      let ref_name = ref_name.expect("clap requires a ref without --stdin");
+      let (new_value, old_value) = if delete {
+        (None, new_value)
+      } else {
+        (new_value, old_value)
+      };
+      update_ref(
+        &ref_name,
+        new_value.as_deref(),
+        old_value.as_deref(),
+        &current_dir,
+        &message,
+        delete,
+        no_deref,
+      )?
+    }
+    Command::SymbolicRef {
+      delete,
+      short,
+      name,
+      target,
+    } => symbolic_ref(
+      &name,
+      target.as_deref(),
+      &mut stdout,
+      &current_dir,
+      delete,
+      short,
+    )?,
+    Command::PackRefs { all } => pack_refs(&current_dir, all)?,
+    Command::Reflog { command } => match command {
+      None => reflog_show("HEAD", &mut stdout, &current_dir)?,
+      Some(ReflogCommand::Show { name }) => reflog_show(&name, &mut stdout, &current_dir)?,
+      Some(ReflogCommand::Expire { expire, all, names }) => {
+        reflog_expire(&names, &current_dir, expire.as_deref(), all)?
+      }
+      Some(ReflogCommand::Delete { selectors }) => reflog_delete(&selectors, &current_dir)?,
+    },
+    Command::RevParse {
+      verify,
+      short,
+      abbrev_ref,
+      args,
+    } => {
+      let options = RevParseOptions {
+        verify,
+        short,
+        abbrev_ref,
+      };
+      rev_parse(&args, &mut stdout, &current_dir, &options)?
+    }
+    Command::Branch {
+      delete,
+      force_delete,
+      rename,
+      force_rename,
+      show_current,
+      verbose,
+      contains,
+      args,
+    } => {
+      if delete || force_delete {
+        branch_delete(&args, &mut stdout, &current_dir, force_delete)?
+      } else if rename || force_rename {
+        match &args[..] {
+          [new] => branch_rename(None, new, &current_dir, force_rename)?,
+          [old, new] => branch_rename(Some(old), new, &current_dir, force_rename)?,
+          _ => anyhow::bail!("fatal: branch name required"),
+        }
+      } else if show_current {
+        branch_show_current(&mut stdout, &current_dir)?
+      } else {
+        match &args[..] {
+          [] => {
+            let options = BranchListOptions { verbose, contains };
+            branch_list(&mut stdout, &current_dir, &options)?
+          }
+          [name] => branch_create(name, None, &current_dir)?,
+          [name, start] => branch_create(name, Some(start), &current_dir)?,
+          _ => anyhow::bail!("fatal: too many arguments for a create operation"),
+        }
+      }
+    }
+    Command::ShowRef {
+      heads,
+      tags,
+      verify,
+      patterns,
+    } => {
+      let options = ShowRefOptions {
+        heads,
+        tags,
+        verify,
+      };
+      show_ref(&patterns, &mut stdout, &current_dir, &options)?
+    }
+    Command::Add {
+      all,
+      update,
+      force,
+      pathspecs,
+    } => add(&pathspecs, &current_dir, all, update, force)?,
+    Command::CheckIgnore {
+      verbose,
+      non_matching,
+      paths,
+    } => {
+      if !check_ignore(&paths, &mut stdout, &current_dir, verbose, non_matching)? {
+        stdout.flush()?;
+        std::process::exit(1);
+      }
+    }
+    Command::Rm {
+      cached,
+      recursive,
+      force,
+      pathspecs,
+    } => rm(
+      &pathspecs,
+      &mut stdout,
+      &current_dir,
+      cached,
+      recursive,
+      force,
+    )?,
+    Command::Status {
+      short,
+      branch,
+      porcelain,
+    } => {
+      let format = match porcelain.as_deref() {
+        None if short => StatusFormat::Short,
+        None => StatusFormat::Long,
+        Some("v1") => StatusFormat::Short,
+        Some("v2") => StatusFormat::PorcelainV2,
+        Some(version) => anyhow::bail!("fatal: unsupported porcelain version '{version}'"),
+      };
+      status(&mut stdout, &current_dir, &StatusOptions { format, branch })?
+    }
+    Command::ForEachRef {
+      format,
+      sort,
+      count,
+      patterns,
+    } => {
+      let options = ForEachRefOptions {
+        format,
+        sort,
+        count,
+      };
+      for_each_ref(&patterns, &mut stdout, &current_dir, &options)?
+    }
   }
   Ok(())
 }
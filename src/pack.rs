@@ -0,0 +1,948 @@
+use crate::common::git_dir;
+use crate::common::GitObject;
+use crate::common::Kind;
+use crate::common::Tree;
+use crate::error::Error;
+use anyhow::Context;
+use flate2::bufread::ZlibDecoder;
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const IDX_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+const PACK_MAGIC: &[u8; 4] = b"PACK";
+
+const OBJ_COMMIT: u8 = 1;
+const OBJ_TREE: u8 = 2;
+const OBJ_BLOB: u8 = 3;
+const OBJ_TAG: u8 = 4;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+fn kind_from_type(object_type: u8) -> anyhow::Result<Kind> {
+  match object_type {
+    OBJ_COMMIT => Ok(Kind::Commit),
+    OBJ_TREE => Ok(Kind::Tree),
+    OBJ_BLOB => Ok(Kind::Blob),
+    OBJ_TAG => Ok(Kind::Tag),
+    _ => anyhow::bail!("unknown pack object type: {object_type}"),
+  }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> anyhow::Result<u32> {
+  let slice = bytes
+    .get(at..at + 4)
+    .ok_or(anyhow::anyhow!("unexpected end of data at {at}"))?;
+  Ok(u32::from_be_bytes(slice.try_into()?))
+}
+
+/// An in-memory view of a version 2 `.idx` file.
+pub struct PackIndex {
+  fanout: [u32; 256],
+  pub oids: Vec<[u8; 20]>,
+  pub crcs: Vec<u32>,
+  pub offsets: Vec<u64>,
+  pub pack_checksum: [u8; 20],
+}
+
+impl PackIndex {
+  pub fn read(path: &Path) -> anyhow::Result<PackIndex> {
+    let bytes =
+      std::fs::read(path).with_context(|| format!("reading pack index {}", path.display()))?;
+    PackIndex::parse(&bytes).with_context(|| format!("parsing pack index {}", path.display()))
+  }
+
+  pub fn parse(bytes: &[u8]) -> anyhow::Result<PackIndex> {
+    anyhow::ensure!(
+      bytes.len() >= 8 + 256 * 4 + 40,
+      "pack index is too short: {} bytes",
+      bytes.len()
+    );
+    anyhow::ensure!(bytes[..4] == IDX_MAGIC, "pack index has bad signature");
+    let version = read_u32(bytes, 4)?;
+    anyhow::ensure!(version == 2, "unsupported pack index version: {version}");
+    let (body, checksum) = bytes.split_at(bytes.len() - 20);
+    anyhow::ensure!(
+      GitObject::_hash(body)? == checksum,
+      "pack index checksum mismatch"
+    );
+
+    let mut fanout = [0u32; 256];
+    for (i, slot) in fanout.iter_mut().enumerate() {
+      *slot = read_u32(bytes, 8 + i * 4)?;
+    }
+    let count = fanout[255] as usize;
+    let oid_start = 8 + 256 * 4;
+    let crc_start = oid_start + count * 20;
+    let offset_start = crc_start + count * 4;
+    let large_start = offset_start + count * 4;
+    anyhow::ensure!(body.len() >= large_start + 20, "pack index is truncated");
+
+    let oids = (0..count)
+      .map(|i| {
+        let at = oid_start + i * 20;
+        bytes[at..at + 20].try_into().expect("slice is 20 bytes")
+      })
+      .collect::<Vec<[u8; 20]>>();
+    // Lookups slice the object table by fanout, so the fanout has to be the
+    // running count of the (strictly sorted) object ids' first bytes.
+    anyhow::ensure!(
+      oids.windows(2).all(|pair| pair[0] < pair[1]),
+      "pack index object ids are not sorted"
+    );
+    let mut expected = [0u32; 256];
+    for oid in &oids {
+      expected[oid[0] as usize] += 1;
+    }
+    for i in 1..256 {
+      expected[i] += expected[i - 1];
+    }
+    anyhow::ensure!(
+      expected == fanout,
+      "pack index fanout does not match its object ids"
+    );
+    let crcs = (0..count)
+      .map(|i| read_u32(bytes, crc_start + i * 4))
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    let offsets = (0..count)
+      .map(|i| {
+        let offset = read_u32(bytes, offset_start + i * 4)?;
+        if offset & 0x8000_0000 == 0 {
+          return Ok(offset as u64);
+        }
+        let at = large_start + (offset & 0x7fff_ffff) as usize * 8;
+        let large = bytes
+          .get(at..at + 8)
+          .filter(|_| at + 8 <= body.len() - 20)
+          .ok_or(anyhow::anyhow!("pack index large offset out of range"))?;
+        Ok(u64::from_be_bytes(large.try_into()?))
+      })
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    let pack_checksum = body[body.len() - 20..].try_into()?;
+    Ok(PackIndex {
+      fanout,
+      oids,
+      crcs,
+      offsets,
+      pack_checksum,
+    })
+  }
+
+  /// The position of `oid` in the sorted object table.
+  pub fn position(&self, oid: &[u8; 20]) -> Option<usize> {
+    let first = oid[0] as usize;
+    let start = if first == 0 {
+      0
+    } else {
+      self.fanout[first - 1] as usize
+    };
+    let end = self.fanout[first] as usize;
+    self.oids[start..end]
+      .binary_search(oid)
+      .ok()
+      .map(|i| start + i)
+  }
+
+  pub fn find(&self, oid: &[u8; 20]) -> Option<u64> {
+    self.position(oid).map(|i| self.offsets[i])
+  }
+
+  /// Every object whose hex name starts with `prefix` (at least two hex digits).
+  pub fn find_prefix(&self, prefix: &str) -> Vec<[u8; 20]> {
+    let Ok(first) = u8::from_str_radix(&prefix[..2], 16) else {
+      return Vec::new();
+    };
+    let first = first as usize;
+    let start = if first == 0 {
+      0
+    } else {
+      self.fanout[first - 1] as usize
+    };
+    let end = self.fanout[first] as usize;
+    self.oids[start..end]
+      .iter()
+      .filter(|oid| hex::encode(oid).starts_with(prefix))
+      .copied()
+      .collect()
+  }
+}
+
+enum DeltaBase {
+  Offset(u64),
+  Oid([u8; 20]),
+}
+
+/// A single entry as stored in the `.pack`, before any delta is applied.
+struct RawEntry {
+  object_type: u8,
+  base: Option<DeltaBase>,
+  data: Vec<u8>,
+}
+
+fn read_byte(reader: &mut impl Read) -> anyhow::Result<u8> {
+  let mut byte = [0u8; 1];
+  reader
+    .read_exact(&mut byte)
+    .context("unexpected end of pack entry")?;
+  Ok(byte[0])
+}
+
+fn read_entry(reader: &mut impl BufRead, offset: u64) -> anyhow::Result<RawEntry> {
+  let mut byte = read_byte(reader)?;
+  let object_type = (byte >> 4) & 0x7;
+  let mut size = (byte & 0x0f) as u64;
+  let mut shift = 4;
+  while byte & 0x80 != 0 {
+    byte = read_byte(reader)?;
+    anyhow::ensure!(shift < 64, "pack entry size at {offset} overflows");
+    size |= ((byte & 0x7f) as u64) << shift;
+    shift += 7;
+  }
+  let base = match object_type {
+    OBJ_OFS_DELTA => {
+      let mut byte = read_byte(reader)?;
+      let mut distance = (byte & 0x7f) as u64;
+      while byte & 0x80 != 0 {
+        byte = read_byte(reader)?;
+        anyhow::ensure!(
+          distance < 1 << 56,
+          "delta base offset out of range at {offset}"
+        );
+        distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+      }
+      let base_offset = offset.checked_sub(distance).ok_or(anyhow::anyhow!(
+        "delta base offset out of range at {offset}"
+      ))?;
+      Some(DeltaBase::Offset(base_offset))
+    }
+    OBJ_REF_DELTA => {
+      let mut oid = [0u8; 20];
+      reader
+        .read_exact(&mut oid)
+        .context("reading delta base object id")?;
+      Some(DeltaBase::Oid(oid))
+    }
+    _ => None,
+  };
+  // The size comes from the pack, so it is only trusted once the data is
+  // there: inflate at most one byte past it rather than preallocating it.
+  let mut data = Vec::new();
+  ZlibDecoder::new(reader)
+    .take(size.saturating_add(1))
+    .read_to_end(&mut data)
+    .with_context(|| format!("inflating pack entry at {offset}"))?;
+  anyhow::ensure!(
+    data.len() as u64 == size,
+    "pack entry at {offset} not of expected size, expected: '{size}', actual: '{}'",
+    data.len()
+  );
+  Ok(RawEntry {
+    object_type,
+    base,
+    data,
+  })
+}
+
+fn read_varint(delta: &[u8], pos: &mut usize) -> anyhow::Result<u64> {
+  let mut value = 0u64;
+  let mut shift = 0;
+  loop {
+    let byte = *delta
+      .get(*pos)
+      .ok_or(anyhow::anyhow!("truncated delta header"))?;
+    *pos += 1;
+    anyhow::ensure!(shift < 64, "delta header size overflows");
+    value |= ((byte & 0x7f) as u64) << shift;
+    shift += 7;
+    if byte & 0x80 == 0 {
+      return Ok(value);
+    }
+  }
+}
+
+/// Rebuilds an object from its base and a git delta (copy/insert instructions).
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> anyhow::Result<Vec<u8>> {
+  let mut pos = 0;
+  let base_size = read_varint(delta, &mut pos)?;
+  anyhow::ensure!(
+    base_size == base.len() as u64,
+    "delta base size mismatch, expected: '{base_size}', actual: '{}'",
+    base.len()
+  );
+  let result_size = read_varint(delta, &mut pos)?;
+  let mut result = Vec::new();
+  while pos < delta.len() {
+    anyhow::ensure!(
+      result.len() as u64 <= result_size,
+      "delta result larger than expected size '{result_size}'"
+    );
+    let op = delta[pos];
+    pos += 1;
+    if op & 0x80 != 0 {
+      let mut operand = |bits: u8, count: usize| -> anyhow::Result<usize> {
+        let mut value = 0usize;
+        for i in 0..count {
+          if bits & (1 << i) != 0 {
+            let byte = *delta
+              .get(pos)
+              .ok_or(anyhow::anyhow!("truncated delta copy instruction"))?;
+            pos += 1;
+            value |= (byte as usize) << (i * 8);
+          }
+        }
+        Ok(value)
+      };
+      let copy_offset = operand(op & 0x0f, 4)?;
+      let copy_size = match operand((op >> 4) & 0x07, 3)? {
+        0 => 0x10000,
+        n => n,
+      };
+      let chunk = base
+        .get(copy_offset..copy_offset + copy_size)
+        .ok_or(anyhow::anyhow!("delta copy out of base range"))?;
+      result.extend_from_slice(chunk);
+    } else if op != 0 {
+      let chunk = delta
+        .get(pos..pos + op as usize)
+        .ok_or(anyhow::anyhow!("truncated delta insert instruction"))?;
+      result.extend_from_slice(chunk);
+      pos += op as usize;
+    } else {
+      anyhow::bail!("unexpected delta opcode 0");
+    }
+  }
+  anyhow::ensure!(
+    result.len() as u64 == result_size,
+    "delta result not of expected size, expected: '{result_size}', actual: '{}'",
+    result.len()
+  );
+  Ok(result)
+}
+
+const DELTA_BLOCK: usize = 16;
+const DELTA_MAX_COPY: usize = 0x10000;
+const DELTA_MAX_INSERT: usize = 0x7f;
+const DELTA_MAX_CANDIDATES: usize = 64;
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    out.push((value & 0x7f) as u8 | 0x80);
+    value >>= 7;
+  }
+  out.push(value as u8);
+}
+
+fn flush_insert(out: &mut Vec<u8>, pending: &mut Vec<u8>) {
+  for chunk in pending.chunks(DELTA_MAX_INSERT) {
+    out.push(chunk.len() as u8);
+    out.extend_from_slice(chunk);
+  }
+  pending.clear();
+}
+
+fn push_copy(out: &mut Vec<u8>, mut offset: usize, mut size: usize) {
+  while size > 0 {
+    let chunk = size.min(DELTA_MAX_COPY);
+    let mut op = 0x80u8;
+    let mut operands = Vec::with_capacity(7);
+    for i in 0..4 {
+      let byte = (offset >> (i * 8)) as u8;
+      if byte != 0 {
+        op |= 1 << i;
+        operands.push(byte);
+      }
+    }
+    for i in 0..3 {
+      let byte = (chunk >> (i * 8)) as u8;
+      if byte != 0 {
+        op |= 1 << (4 + i);
+        operands.push(byte);
+      }
+    }
+    out.push(op);
+    out.extend_from_slice(&operands);
+    offset += chunk;
+    size -= chunk;
+  }
+}
+
+/// Block index over a delta base, reused while it sits in the pack window.
+pub struct DeltaIndex<'a> {
+  base: &'a [u8],
+  blocks: HashMap<&'a [u8], Vec<usize>>,
+}
+
+impl<'a> DeltaIndex<'a> {
+  pub fn new(base: &'a [u8]) -> DeltaIndex<'a> {
+    let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for offset in (0..base.len().saturating_sub(DELTA_BLOCK - 1)).step_by(DELTA_BLOCK) {
+      let candidates = blocks
+        .entry(&base[offset..offset + DELTA_BLOCK])
+        .or_default();
+      if candidates.len() < DELTA_MAX_CANDIDATES {
+        candidates.push(offset);
+      }
+    }
+    DeltaIndex { base, blocks }
+  }
+
+  /// Encodes `target` as copy/insert instructions against the base, giving
+  /// up once the delta would reach `max_size` bytes.
+  pub fn create_delta(&self, target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    write_varint(&mut out, self.base.len() as u64);
+    write_varint(&mut out, target.len() as u64);
+    let mut pending = Vec::new();
+    let mut pos = 0;
+    while pos < target.len() {
+      let best = target
+        .get(pos..pos + DELTA_BLOCK)
+        .and_then(|block| self.blocks.get(block))
+        .and_then(|candidates| {
+          candidates
+            .iter()
+            .map(|&offset| {
+              let len = self.base[offset..]
+                .iter()
+                .zip(&target[pos..])
+                .take_while(|(a, b)| a == b)
+                .count();
+              (offset, len)
+            })
+            .max_by_key(|&(_, len)| len)
+        });
+      match best {
+        Some((mut offset, mut len)) => {
+          pos += len;
+          while offset > 0 && pending.last() == Some(&self.base[offset - 1]) {
+            pending.pop();
+            offset -= 1;
+            len += 1;
+          }
+          flush_insert(&mut out, &mut pending);
+          push_copy(&mut out, offset, len);
+        }
+        None => {
+          pending.push(target[pos]);
+          pos += 1;
+        }
+      }
+      if out.len() + pending.len() >= max_size {
+        return None;
+      }
+    }
+    flush_insert(&mut out, &mut pending);
+    (out.len() < max_size).then_some(out)
+  }
+}
+
+/// Computes a git delta that rebuilds `target` from `base`.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+  DeltaIndex::new(base)
+    .create_delta(target, usize::MAX)
+    .expect("delta has no size limit")
+}
+
+/// A `.pack` file together with its `.idx`.
+pub struct Pack {
+  pub path: PathBuf,
+  pub index: PackIndex,
+}
+
+impl Pack {
+  pub fn open(idx_path: &Path) -> anyhow::Result<Pack> {
+    let index = PackIndex::read(idx_path)?;
+    let path = idx_path.with_extension("pack");
+    let mut header = [0u8; 12];
+    let mut f =
+      std::fs::File::open(&path).with_context(|| format!("opening pack {}", path.display()))?;
+    f.read_exact(&mut header)
+      .with_context(|| format!("reading pack header {}", path.display()))?;
+    let len = f
+      .metadata()
+      .with_context(|| format!("reading pack header {}", path.display()))?
+      .len();
+    anyhow::ensure!(
+      &header[..4] == PACK_MAGIC,
+      "{} is not a pack file",
+      path.display()
+    );
+    let version = read_u32(&header, 4)?;
+    anyhow::ensure!(version == 2, "unsupported pack version: {version}");
+    let count = read_u32(&header, 8)?;
+    anyhow::ensure!(
+      count as usize == index.oids.len(),
+      "{} has {count} objects but its index has {}",
+      path.display(),
+      index.oids.len()
+    );
+    anyhow::ensure!(
+      index
+        .offsets
+        .iter()
+        .all(|&offset| offset >= 12 && offset < len),
+      "{} has an index offset outside the pack",
+      path.display()
+    );
+    Ok(Pack { path, index })
+  }
+
+  /// Lists every pack under `.git/objects/pack` that has an index.
+  pub fn all(repo_path: &Path) -> anyhow::Result<Vec<Pack>> {
+    let pack_dir = git_dir(repo_path).join("objects/pack");
+    if !pack_dir.is_dir() {
+      return Ok(Vec::new());
+    }
+    let mut idx_paths = std::fs::read_dir(&pack_dir)
+      .with_context(|| format!("listing {}", pack_dir.display()))?
+      .filter_map(|entry| entry.ok())
+      .map(|entry| entry.path())
+      .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+      .collect::<Vec<_>>();
+    idx_paths.sort();
+    idx_paths.iter().map(|path| Pack::open(path)).collect()
+  }
+
+  fn read_raw(&self, offset: u64) -> anyhow::Result<RawEntry> {
+    let mut f = std::fs::File::open(&self.path).map_err(Error::io(&self.path))?;
+    f.seek(SeekFrom::Start(offset))
+      .map_err(Error::io(&self.path))?;
+    read_entry(&mut BufReader::new(f), offset)
+  }
+
+  /// Reads the object at `offset`, following any delta chain down to its base.
+  /// The chain is walked iteratively, so a corrupt pack whose deltas lead back
+  /// to an entry already on the chain is reported instead of recursing forever.
+  pub fn read_at(&self, repo_path: &Path, offset: u64) -> anyhow::Result<(Kind, Vec<u8>)> {
+    let mut deltas = Vec::new();
+    let mut seen = HashSet::new();
+    let mut offset = offset;
+    let (kind, mut content) = loop {
+      anyhow::ensure!(
+        seen.insert(offset),
+        "delta chain loops back to the entry at {offset}"
+      );
+      let entry = self.read_raw(offset)?;
+      match entry.base {
+        None => break (kind_from_type(entry.object_type)?, entry.data),
+        Some(DeltaBase::Offset(base_offset)) => offset = base_offset,
+        Some(DeltaBase::Oid(oid)) => match self.index.find(&oid) {
+          Some(base_offset) => offset = base_offset,
+          None => {
+            deltas.push(entry.data);
+            let base = GitObject::read_object(repo_path, &hex::encode(oid))?;
+            let content = base.content().to_vec();
+            break (base.kind, content);
+          }
+        },
+      }
+      deltas.push(entry.data);
+    };
+    for delta in deltas.iter().rev() {
+      content = apply_delta(&content, delta)?;
+    }
+    Ok((kind, content))
+  }
+}
+
+/// One object of a pack, as reported by `verify-pack -v`.
+pub struct PackEntry {
+  pub oid: [u8; 20],
+  pub kind: Kind,
+  /// Size of the data stored in the pack: the delta itself for deltified entries.
+  pub size: u64,
+  pub packed_size: u64,
+  pub offset: u64,
+  pub crc: u32,
+  pub depth: usize,
+  pub base: Option<[u8; 20]>,
+}
+
+struct ScannedEntry {
+  raw: RawEntry,
+  offset: u64,
+  packed_size: u64,
+  crc: u32,
+}
+
+/// Walks a complete `.pack` held in memory, checking its trailing checksum and
+/// resolving every delta so each entry can be named by its object id.
+pub fn scan_pack(pack: &[u8]) -> anyhow::Result<(Vec<PackEntry>, [u8; 20])> {
+  anyhow::ensure!(
+    pack.len() >= 32 && &pack[..4] == PACK_MAGIC,
+    "not a pack file"
+  );
+  let version = read_u32(pack, 4)?;
+  anyhow::ensure!(version == 2, "unsupported pack version: {version}");
+  let count = read_u32(pack, 8)? as usize;
+  let (body, trailer) = pack.split_at(pack.len() - 20);
+  let checksum: [u8; 20] = trailer.try_into()?;
+  anyhow::ensure!(
+    GitObject::_hash(body)? == checksum,
+    "pack checksum mismatch"
+  );
+
+  // The count is as untrusted as the rest of the pack: let the entries that
+  // are actually there size the vector.
+  let mut scanned = Vec::new();
+  let mut offset = 12u64;
+  for _ in 0..count {
+    let mut reader = body
+      .get(offset as usize..)
+      .ok_or(anyhow::anyhow!("pack is truncated at {offset}"))?;
+    let raw = read_entry(&mut reader, offset)?;
+    let end = body.len() - reader.len();
+    let mut crc = Crc::new();
+    crc.update(&body[offset as usize..end]);
+    scanned.push(ScannedEntry {
+      raw,
+      offset,
+      packed_size: end as u64 - offset,
+      crc: crc.sum(),
+    });
+    offset = end as u64;
+  }
+  anyhow::ensure!(
+    offset as usize == body.len(),
+    "pack has {} trailing bytes after {count} objects",
+    body.len() - offset as usize
+  );
+
+  let by_offset = scanned
+    .iter()
+    .enumerate()
+    .map(|(i, entry)| (entry.offset, i))
+    .collect::<HashMap<_, _>>();
+  let mut resolved: Vec<Option<(Kind, Vec<u8>, usize)>> = Vec::new();
+  resolved.resize_with(scanned.len(), || None);
+  let mut oids: Vec<Option<[u8; 20]>> = vec![None; scanned.len()];
+  let mut by_oid = HashMap::new();
+  // Deltas are resolved in rounds because a REF_DELTA base may sit anywhere in the pack.
+  let mut pending = (0..scanned.len()).collect::<Vec<_>>();
+  while !pending.is_empty() {
+    let mut unresolved = Vec::new();
+    for &i in &pending {
+      let entry = &scanned[i];
+      let (kind, content, depth) = match &entry.raw.base {
+        None => (
+          kind_from_type(entry.raw.object_type)?,
+          entry.raw.data.clone(),
+          0,
+        ),
+        Some(base) => {
+          let base_index = match base {
+            DeltaBase::Offset(base_offset) => by_offset.get(base_offset).copied(),
+            DeltaBase::Oid(oid) => by_oid.get(oid).copied(),
+          };
+          let Some((kind, base, depth)) = base_index.and_then(|b| resolved[b].as_ref()) else {
+            unresolved.push(i);
+            continue;
+          };
+          let content = apply_delta(base, &entry.raw.data)
+            .with_context(|| format!("applying delta at {}", entry.offset))?;
+          (*kind, content, depth + 1)
+        }
+      };
+      let oid = GitObject::from_content(kind, &content).hash_bytes()?;
+      by_oid.insert(oid, i);
+      oids[i] = Some(oid);
+      resolved[i] = Some((kind, content, depth));
+    }
+    if unresolved.len() == pending.len() {
+      let missing = unresolved
+        .iter()
+        .map(|&i| match &scanned[i].raw.base {
+          Some(DeltaBase::Oid(oid)) => hex::encode(oid),
+          _ => format!("offset {}", scanned[i].offset),
+        })
+        .collect::<Vec<_>>();
+      anyhow::bail!("cannot resolve delta bases: {}", missing.join(", "));
+    }
+    pending = unresolved;
+  }
+
+  let bases = scanned
+    .iter()
+    .map(|entry| match &entry.raw.base {
+      Some(DeltaBase::Offset(base_offset)) => oids[by_offset[base_offset]],
+      Some(DeltaBase::Oid(oid)) => Some(*oid),
+      None => None,
+    })
+    .collect::<Vec<_>>();
+  let entries = scanned
+    .into_iter()
+    .zip(resolved)
+    .zip(oids)
+    .zip(bases)
+    .map(|(((entry, resolved), oid), base)| {
+      let (kind, _, depth) = resolved.expect("every entry is resolved");
+      PackEntry {
+        oid: oid.expect("every entry is resolved"),
+        kind,
+        size: entry.raw.data.len() as u64,
+        packed_size: entry.packed_size,
+        offset: entry.offset,
+        crc: entry.crc,
+        depth,
+        base,
+      }
+    })
+    .collect();
+  Ok((entries, checksum))
+}
+
+/// Looks `object_hash` up in every pack of the repository.
+pub fn read_packed_object(
+  repo_path: &Path,
+  object_hash: &str,
+) -> anyhow::Result<Option<GitObject>> {
+  let oid: [u8; 20] = hex::decode(object_hash)
+    .ok()
+    .and_then(|oid| oid.try_into().ok())
+    .ok_or(anyhow::anyhow!("invalid object hash: '{object_hash}'"))?;
+  for pack in Pack::all(repo_path)? {
+    if let Some(offset) = pack.index.find(&oid) {
+      // Failing to open the pack stays an `Error::Io`; anything that goes
+      // wrong decoding the entry or its delta chain means the pack is corrupt.
+      let (kind, content) =
+        pack
+          .read_at(repo_path, offset)
+          .map_err(|error| match Error::from(error) {
+            Error::Other(reason) => Error::CorruptObject {
+              hash: object_hash.to_string(),
+              reason: format!("{reason:#}"),
+            },
+            error => error,
+          })?;
+      return Ok(Some(GitObject::from_content(kind, &content)));
+    }
+  }
+  Ok(None)
+}
+
+fn type_from_kind(kind: &Kind) -> u8 {
+  match kind {
+    Kind::Commit => OBJ_COMMIT,
+    Kind::Tree => OBJ_TREE,
+    Kind::Blob => OBJ_BLOB,
+    Kind::Tag => OBJ_TAG,
+  }
+}
+
+fn encode_entry_header(object_type: u8, size: u64) -> Vec<u8> {
+  let mut header = Vec::new();
+  let mut byte = (object_type << 4) | (size & 0x0f) as u8;
+  let mut size = size >> 4;
+  while size != 0 {
+    header.push(byte | 0x80);
+    byte = (size & 0x7f) as u8;
+    size >>= 7;
+  }
+  header.push(byte);
+  header
+}
+
+/// Where an object landed in a `.pack`, as recorded in its `.idx`.
+pub struct IndexEntry {
+  pub oid: [u8; 20],
+  pub crc: u32,
+  pub offset: u64,
+}
+
+/// Serializes a version 2 `.idx` for the given pack entries.
+pub fn write_index(entries: &[IndexEntry], pack_checksum: &[u8; 20]) -> anyhow::Result<Vec<u8>> {
+  let mut sorted = entries.iter().collect::<Vec<_>>();
+  sorted.sort_by_key(|entry| entry.oid);
+  let mut out = Vec::from(IDX_MAGIC);
+  out.extend_from_slice(&2u32.to_be_bytes());
+  let mut fanout = [0u32; 256];
+  for entry in &sorted {
+    fanout[entry.oid[0] as usize] += 1;
+  }
+  let mut total = 0u32;
+  for count in fanout {
+    total += count;
+    out.extend_from_slice(&total.to_be_bytes());
+  }
+  for entry in &sorted {
+    out.extend_from_slice(&entry.oid);
+  }
+  for entry in &sorted {
+    out.extend_from_slice(&entry.crc.to_be_bytes());
+  }
+  let mut large_offsets = Vec::new();
+  for entry in &sorted {
+    if entry.offset < 0x8000_0000 {
+      out.extend_from_slice(&(entry.offset as u32).to_be_bytes());
+    } else {
+      let slot = 0x8000_0000 | large_offsets.len() as u32;
+      out.extend_from_slice(&slot.to_be_bytes());
+      large_offsets.push(entry.offset);
+    }
+  }
+  for offset in large_offsets {
+    out.extend_from_slice(&offset.to_be_bytes());
+  }
+  out.extend_from_slice(pack_checksum);
+  let checksum = GitObject::_hash(&out)?;
+  out.extend_from_slice(&checksum);
+  Ok(out)
+}
+
+/// Knobs for delta compression while writing a pack, mirroring
+/// `git pack-objects --window=<n> --depth=<n>`.
+pub struct PackOptions {
+  pub window: usize,
+  pub depth: usize,
+}
+
+impl Default for PackOptions {
+  fn default() -> Self {
+    PackOptions {
+      window: 10,
+      depth: 50,
+    }
+  }
+}
+
+/// git's `pack_name_hash`: groups paths by their trailing characters so that
+/// files with the same name or extension end up next to each other.
+fn name_hash(name: &[u8]) -> u32 {
+  name
+    .iter()
+    .filter(|c| !c.is_ascii_whitespace())
+    .fold(0u32, |hash, &c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+/// Maps every object referenced by a tree in `objects` to its entry name.
+fn name_hints(objects: &[GitObject]) -> HashMap<[u8; 20], Vec<u8>> {
+  let mut names = HashMap::new();
+  for object in objects.iter().filter(|object| object.kind == Kind::Tree) {
+    let Ok(tree) = Tree::parse(object.content()) else {
+      continue;
+    };
+    for entry in tree.entries {
+      names.entry(entry.oid).or_insert(entry.name);
+    }
+  }
+  names
+}
+
+fn encode_ofs_distance(mut distance: u64) -> Vec<u8> {
+  let mut bytes = vec![(distance & 0x7f) as u8];
+  distance >>= 7;
+  while distance != 0 {
+    distance -= 1;
+    bytes.push(0x80 | (distance & 0x7f) as u8);
+    distance >>= 7;
+  }
+  bytes.reverse();
+  bytes
+}
+
+/// Writes `objects` as `pack-<checksum>.pack` plus its `.idx` into `pack_dir`
+/// and returns the hex checksum naming the pair.
+///
+/// Objects are ordered by type, name and size, and each one is deltified
+/// against the best of the previous `window` objects of the same type,
+/// producing OFS_DELTA entries with chains no longer than `depth`.
+pub fn write_pack(
+  pack_dir: &Path,
+  objects: &[GitObject],
+  options: &PackOptions,
+) -> anyhow::Result<String> {
+  let names = name_hints(objects);
+  let oids = objects
+    .iter()
+    .map(|object| object.hash_bytes())
+    .collect::<crate::error::Result<Vec<_>>>()?;
+  let mut order = (0..objects.len()).collect::<Vec<_>>();
+  order.sort_by_key(|&i| {
+    let object = &objects[i];
+    let hash = names.get(&oids[i]).map(|name| name_hash(name)).unwrap_or(0);
+    (
+      type_from_kind(&object.kind),
+      hash,
+      std::cmp::Reverse(object.size),
+    )
+  });
+
+  let mut pack = Vec::from(&PACK_MAGIC[..]);
+  pack.extend_from_slice(&2u32.to_be_bytes());
+  pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+  let mut entries = Vec::with_capacity(objects.len());
+  let mut offsets = vec![0u64; objects.len()];
+  let mut depths = vec![0usize; objects.len()];
+  let mut window: VecDeque<(usize, DeltaIndex)> = VecDeque::with_capacity(options.window);
+  for &i in &order {
+    let object = &objects[i];
+    let content = object.content();
+    let mut best: Option<(usize, Vec<u8>)> = None;
+    for (base, index) in window.iter().rev() {
+      let base_object = &objects[*base];
+      if base_object.kind != object.kind || depths[*base] >= options.depth {
+        continue;
+      }
+      if base_object.size < object.size / 32 {
+        continue;
+      }
+      let max_size = best
+        .as_ref()
+        .map(|(_, delta)| delta.len())
+        .unwrap_or(content.len() / 2);
+      if let Some(delta) = index.create_delta(content, max_size) {
+        best = Some((*base, delta));
+      }
+    }
+
+    let offset = pack.len() as u64;
+    offsets[i] = offset;
+    let body = match best {
+      Some((base, delta)) => {
+        depths[i] = depths[base] + 1;
+        pack.extend_from_slice(&encode_entry_header(OBJ_OFS_DELTA, delta.len() as u64));
+        pack.extend_from_slice(&encode_ofs_distance(offset - offsets[base]));
+        delta
+      }
+      None => {
+        pack.extend_from_slice(&encode_entry_header(
+          type_from_kind(&object.kind),
+          object.size,
+        ));
+        content.to_vec()
+      }
+    };
+    let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+    e.write_all(&body)?;
+    pack.extend_from_slice(&e.finish().context("compressing pack entry")?);
+    let mut crc = Crc::new();
+    crc.update(&pack[offset as usize..]);
+    entries.push(IndexEntry {
+      oid: oids[i],
+      crc: crc.sum(),
+      offset,
+    });
+
+    if options.window > 0 {
+      if window.len() == options.window {
+        window.pop_front();
+      }
+      window.push_back((i, DeltaIndex::new(content)));
+    }
+  }
+  let checksum = GitObject::_hash(&pack)?;
+  pack.extend_from_slice(&checksum);
+  let index = write_index(&entries, &checksum)?;
+
+  let name = hex::encode(checksum);
+  std::fs::create_dir_all(pack_dir).context("creating pack directory")?;
+  let pack_path = pack_dir.join(format!("pack-{name}.pack"));
+  std::fs::write(&pack_path, &pack).with_context(|| format!("writing {}", pack_path.display()))?;
+  let idx_path = pack_path.with_extension("idx");
+  std::fs::write(&idx_path, &index).with_context(|| format!("writing {}", idx_path.display()))?;
+  Ok(name)
+}
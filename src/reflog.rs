@@ -0,0 +1,242 @@
+use crate::common;
+use crate::lockfile::LockFile;
+use crate::refs;
+use anyhow::Context;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// One line of `.git/logs/<ref>`: the ref moved from `old` to `new`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+  pub old: String,
+  pub new: String,
+  /// `Name <email>` of whoever moved the ref.
+  pub committer: String,
+  pub timestamp: i64,
+  pub tz: String,
+  pub message: String,
+}
+
+impl ReflogEntry {
+  pub fn parse(line: &str) -> anyhow::Result<ReflogEntry> {
+    let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+    let malformed = || anyhow::anyhow!("malformed reflog entry: '{line}'");
+    let (old, rest) = header.split_once(' ').ok_or_else(malformed)?;
+    let (new, identity) = rest.split_once(' ').ok_or_else(malformed)?;
+    let mut fields = identity.rsplitn(3, ' ');
+    let (Some(tz), Some(timestamp), Some(committer)) =
+      (fields.next(), fields.next(), fields.next())
+    else {
+      return Err(malformed());
+    };
+    anyhow::ensure!(
+      old.len() == 40 && new.len() == 40 && committer.ends_with('>'),
+      malformed()
+    );
+    Ok(ReflogEntry {
+      old: old.to_string(),
+      new: new.to_string(),
+      committer: committer.to_string(),
+      timestamp: timestamp.parse().map_err(|_| malformed())?,
+      tz: tz.to_string(),
+      message: message.to_string(),
+    })
+  }
+
+  /// The entry as a log line, including its trailing newline. As in git,
+  /// the tab is left out when there is no message.
+  pub fn serialize(&self) -> String {
+    let mut line = format!(
+      "{} {} {} {} {}",
+      self.old, self.new, self.committer, self.timestamp, self.tz
+    );
+    if !self.message.is_empty() {
+      line.push('\t');
+      line.push_str(&self.message);
+    }
+    line.push('\n');
+    line
+  }
+}
+
+fn log_path(repo_path: &Path, name: &str) -> PathBuf {
+  common::git_dir(repo_path).join("logs").join(name)
+}
+
+/// Whether updates to `name` are logged: git's default for a repository
+/// with a work tree covers `HEAD`, branches, remote-tracking refs and notes,
+/// plus any ref that already has a log.
+pub fn should_log(repo_path: &Path, name: &str) -> bool {
+  name == "HEAD"
+    || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+      .iter()
+      .any(|prefix| name.starts_with(prefix))
+    || log_path(repo_path, name).is_file()
+}
+
+/// Records that `name` moved from `old` to `new`, stamped with the current
+/// identity and time. Line breaks in `message` are folded into spaces.
+pub fn append(
+  repo_path: &Path,
+  name: &str,
+  old: &str,
+  new: &str,
+  message: &str,
+) -> anyhow::Result<()> {
+  let identity = common::identity()?;
+  let line = format!(
+    "{old} {new} {identity}\t{}",
+    message.trim().replace('\n', " ")
+  );
+  let entry = ReflogEntry::parse(&line)?;
+  let path = log_path(repo_path, name);
+  std::fs::create_dir_all(path.parent().expect("log path has a parent"))
+    .with_context(|| format!("creating reflog directory for {name}"))?;
+  let mut file = std::fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(&path)
+    .with_context(|| format!("opening reflog for {name}"))?;
+  file.write_all(entry.serialize().as_bytes())?;
+  Ok(())
+}
+
+/// The log of `name`, oldest entry first; empty when there is none.
+pub fn read_reflog(repo_path: &Path, name: &str) -> anyhow::Result<Vec<ReflogEntry>> {
+  let path = log_path(repo_path, name);
+  if !path.is_file() {
+    return Ok(Vec::new());
+  }
+  let content =
+    std::fs::read_to_string(&path).with_context(|| format!("reading reflog for {name}"))?;
+  content.lines().map(ReflogEntry::parse).collect()
+}
+
+pub fn write_reflog(repo_path: &Path, name: &str, entries: &[ReflogEntry]) -> anyhow::Result<()> {
+  let mut lock = LockFile::acquire(&log_path(repo_path, name))?;
+  for entry in entries {
+    lock.write_all(entry.serialize().as_bytes())?;
+  }
+  lock.commit()
+}
+
+pub fn delete_reflog(repo_path: &Path, name: &str) -> anyhow::Result<()> {
+  let path = log_path(repo_path, name);
+  if path.is_file() {
+    std::fs::remove_file(&path).with_context(|| format!("deleting reflog for {name}"))?;
+  }
+  let keep = common::git_dir(repo_path).join("logs/refs");
+  let mut dir = path.parent();
+  while let Some(current) = dir {
+    if !current.starts_with(&keep) || current == keep || std::fs::remove_dir(current).is_err() {
+      break;
+    }
+    dir = current.parent();
+  }
+  Ok(())
+}
+
+/// The names of every ref that has a log, sorted.
+pub fn reflog_names(repo_path: &Path) -> anyhow::Result<Vec<String>> {
+  fn walk(logs_dir: &Path, dir: &Path, names: &mut Vec<String>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+      let path = entry?.path();
+      if path.is_dir() {
+        walk(logs_dir, &path, names)?;
+      } else {
+        let name = path
+          .strip_prefix(logs_dir)?
+          .to_string_lossy()
+          .replace(std::path::MAIN_SEPARATOR, "/");
+        if refs::is_valid_ref_name(&name) {
+          names.push(name);
+        }
+      }
+    }
+    Ok(())
+  }
+  let logs_dir = common::git_dir(repo_path).join("logs");
+  let mut names = Vec::new();
+  if logs_dir.is_dir() {
+    walk(&logs_dir, &logs_dir, &mut names)?;
+  }
+  names.sort();
+  Ok(names)
+}
+
+/// Parses an `--expire` value into a cutoff: entries older than it are
+/// pruned. Accepts `never`, `now`/`all`, unix timestamps and relative times
+/// such as `90.days.ago` or `2 weeks ago`.
+pub fn parse_expiry(value: &str, now: i64) -> anyhow::Result<i64> {
+  match value {
+    "never" | "false" => return Ok(i64::MIN),
+    "now" | "all" => return Ok(i64::MAX),
+    _ => {}
+  }
+  if let Ok(timestamp) = value.parse::<i64>() {
+    return Ok(timestamp);
+  }
+  let words = value.split(['.', ' ']).collect::<Vec<_>>();
+  let invalid = || anyhow::anyhow!("fatal: invalid expiry date '{value}'");
+  let [count, unit, "ago"] = words[..] else {
+    return Err(invalid());
+  };
+  let count = count.parse::<i64>().map_err(|_| invalid())?;
+  let seconds = match unit.trim_end_matches('s') {
+    "second" => 1,
+    "minute" => 60,
+    "hour" => 60 * 60,
+    "day" => 24 * 60 * 60,
+    "week" => 7 * 24 * 60 * 60,
+    "month" => 30 * 24 * 60 * 60,
+    "year" => 365 * 24 * 60 * 60,
+    _ => return Err(invalid()),
+  };
+  count
+    .checked_mul(seconds)
+    .and_then(|ago| now.checked_sub(ago))
+    .ok_or_else(invalid)
+}
+
+/// Splits a `<ref>@{<n>}` selector into the full name of the logged ref and
+/// `n`; `Ok(None)` if `spec` is not of that form. An empty `<ref>` means the
+/// branch `HEAD` points at.
+pub fn parse_selector(repo_path: &Path, spec: &str) -> anyhow::Result<Option<(String, usize)>> {
+  let Some((name, n)) = spec
+    .strip_suffix('}')
+    .and_then(|spec| spec.rsplit_once("@{"))
+  else {
+    return Ok(None);
+  };
+  let n = n
+    .parse::<usize>()
+    .map_err(|_| anyhow::anyhow!("fatal: unsupported reflog selector '{spec}'"))?;
+  let full_name = if name.is_empty() {
+    refs::resolve_symbolic(repo_path, "HEAD")?
+  } else {
+    refs::dwim_ref(repo_path, name)?
+      .or_else(|| {
+        ["", "refs/", "refs/tags/", "refs/heads/", "refs/remotes/"]
+          .iter()
+          .map(|prefix| format!("{prefix}{name}"))
+          .find(|candidate| log_path(repo_path, candidate).is_file())
+      })
+      .ok_or(anyhow::anyhow!("fatal: no reflog for '{name}'"))?
+  };
+  Ok(Some((full_name, n)))
+}
+
+/// Resolves `<ref>@{<n>}` to the value the ref had `n` moves ago, with
+/// `@{0}` being its current value; `Ok(None)` if `spec` is not a selector.
+pub fn resolve_selector(repo_path: &Path, spec: &str) -> anyhow::Result<Option<String>> {
+  let Some((name, n)) = parse_selector(repo_path, spec)? else {
+    return Ok(None);
+  };
+  let entries = read_reflog(repo_path, &name)?;
+  anyhow::ensure!(
+    n < entries.len(),
+    "fatal: log for '{name}' only has {} entries",
+    entries.len()
+  );
+  Ok(Some(entries[entries.len() - 1 - n].new.clone()))
+}
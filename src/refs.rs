@@ -0,0 +1,566 @@
+use crate::common::{git_dir, GitObject, Kind, Tag};
+use crate::config::Config;
+use crate::lockfile::LockFile;
+use crate::reflog;
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+/// How many symbolic refs are followed before giving up, matching git.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// The contents of a ref file: either an object hash or, for symbolic refs
+/// such as `HEAD`, the name of another ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ref {
+  Direct(String),
+  Symbolic(String),
+}
+
+/// One ref stored in `.git/packed-refs`. `peeled` is the object an annotated
+/// tag ultimately points at, from the `^<hash>` line that follows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+  pub name: String,
+  pub hash: String,
+  pub peeled: Option<String>,
+}
+
+const PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+/// Checks a full ref name (`HEAD`, `refs/heads/main`, ...) against the rules
+/// of `git check-ref-format`.
+pub fn check_ref_format(name: &str) -> bool {
+  if name.is_empty()
+    || name == "@"
+    || name.ends_with('/')
+    || name.ends_with('.')
+    || name.contains("..")
+    || name.contains("@{")
+    || name.contains("//")
+  {
+    return false;
+  }
+  if name
+    .chars()
+    .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+  {
+    return false;
+  }
+  name
+    .split('/')
+    .all(|component| !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+/// Whether `name` may be stored under `.git`: either below `refs/` or an
+/// all-caps pseudo ref like `HEAD` or `ORIG_HEAD`.
+pub fn is_valid_ref_name(name: &str) -> bool {
+  check_ref_format(name)
+    && (name.starts_with("refs/") || name.chars().all(|c| c.is_ascii_uppercase() || c == '_'))
+}
+
+fn ref_path(repo_path: &Path, name: &str) -> anyhow::Result<PathBuf> {
+  anyhow::ensure!(is_valid_ref_name(name), "fatal: invalid ref name: '{name}'");
+  Ok(git_dir(repo_path).join(name))
+}
+
+fn is_hash(value: &str) -> bool {
+  value.len() == 40 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Parses `.git/packed-refs`, returning its refs sorted by name. A missing
+/// file is an empty list.
+pub fn read_packed_refs(repo_path: &Path) -> anyhow::Result<Vec<PackedRef>> {
+  let path = git_dir(repo_path).join("packed-refs");
+  if !path.is_file() {
+    return Ok(Vec::new());
+  }
+  let content = std::fs::read_to_string(&path).context("reading .git/packed-refs")?;
+  let mut sorted = false;
+  let mut packed = Vec::<PackedRef>::new();
+  for line in content.lines() {
+    if let Some(traits) = line.strip_prefix("# pack-refs with:") {
+      sorted = traits.split_whitespace().any(|t| t == "sorted");
+    } else if line.starts_with('#') {
+      continue;
+    } else if let Some(peeled) = line.strip_prefix('^') {
+      let last = packed
+        .last_mut()
+        .filter(|_| is_hash(peeled))
+        .ok_or(anyhow::anyhow!(
+          "fatal: unexpected line in .git/packed-refs: '{line}'"
+        ))?;
+      last.peeled = Some(peeled.to_ascii_lowercase());
+    } else {
+      let (hash, name) = line
+        .split_once(' ')
+        .filter(|(hash, name)| is_hash(hash) && check_ref_format(name))
+        .ok_or(anyhow::anyhow!(
+          "fatal: unexpected line in .git/packed-refs: '{line}'"
+        ))?;
+      packed.push(PackedRef {
+        name: name.to_string(),
+        hash: hash.to_ascii_lowercase(),
+        peeled: None,
+      });
+    }
+  }
+  if !sorted {
+    packed.sort_by(|a, b| a.name.cmp(&b.name));
+  }
+  Ok(packed)
+}
+
+/// Locks `.git/packed-refs`; hold the lock across reading and rewriting the
+/// file with [`write_packed_refs`].
+pub fn lock_packed_refs(repo_path: &Path) -> anyhow::Result<LockFile> {
+  LockFile::acquire(&git_dir(repo_path).join("packed-refs"))
+}
+
+/// Replaces `.git/packed-refs` with `packed`, sorted by name, or removes the
+/// file when there is nothing left to store.
+pub fn write_packed_refs(
+  repo_path: &Path,
+  mut lock: LockFile,
+  packed: &[PackedRef],
+) -> anyhow::Result<()> {
+  if packed.is_empty() {
+    if lock.path().exists() {
+      std::fs::remove_file(lock.path()).context("removing .git/packed-refs")?;
+    }
+    return Ok(());
+  }
+  let mut packed = packed.to_vec();
+  packed.sort_by(|a, b| a.name.cmp(&b.name));
+  let mut content = String::from(PACKED_REFS_HEADER);
+  for packed_ref in &packed {
+    content.push_str(&format!("{} {}\n", packed_ref.hash, packed_ref.name));
+    // The header promises every tag is peeled, so fill in entries read from
+    // a file that did not record it.
+    let peeled = match &packed_ref.peeled {
+      Some(peeled) => Some(peeled.clone()),
+      None => peel(repo_path, &packed_ref.hash).ok().flatten(),
+    };
+    if let Some(peeled) = &peeled {
+      content.push_str(&format!("^{peeled}\n"));
+    }
+  }
+  lock.write_all(content.as_bytes())?;
+  lock.commit()
+}
+
+fn find_packed_ref(repo_path: &Path, name: &str) -> anyhow::Result<Option<PackedRef>> {
+  let packed = read_packed_refs(repo_path)?;
+  Ok(
+    packed
+      .binary_search_by(|packed_ref| packed_ref.name.as_str().cmp(name))
+      .ok()
+      .map(|i| packed[i].clone()),
+  )
+}
+
+/// Follows an annotated tag (and tags of tags) to the first object that is
+/// not a tag; `Ok(None)` if `hash` is not a tag.
+pub fn peel(repo_path: &Path, hash: &str) -> anyhow::Result<Option<String>> {
+  let mut git_object = GitObject::read_object(repo_path, hash)?;
+  let mut peeled = None;
+  while git_object.kind == Kind::Tag {
+    let object = Tag::parse(git_object.content())?.object;
+    git_object = GitObject::read_object(repo_path, &object)?;
+    peeled = Some(object);
+  }
+  Ok(peeled)
+}
+
+/// Reads the ref `name` without following it, from its loose file or else
+/// from `.git/packed-refs`; `Ok(None)` when it exists in neither.
+pub fn read_ref(repo_path: &Path, name: &str) -> anyhow::Result<Option<Ref>> {
+  let path = ref_path(repo_path, name)?;
+  if !path.is_file() {
+    return Ok(find_packed_ref(repo_path, name)?.map(|packed_ref| Ref::Direct(packed_ref.hash)));
+  }
+  let content = std::fs::read_to_string(&path).with_context(|| format!("reading ref {name}"))?;
+  let content = content.trim_end();
+  if let Some(target) = content.strip_prefix("ref: ") {
+    return Ok(Some(Ref::Symbolic(target.trim().to_string())));
+  }
+  anyhow::ensure!(is_hash(content), "fatal: bad ref file {name}: '{content}'");
+  Ok(Some(Ref::Direct(content.to_ascii_lowercase())))
+}
+
+/// Follows symbolic refs starting at `name` and returns the name of the ref
+/// that ends the chain, which may not exist yet (an unborn branch).
+pub fn resolve_symbolic(repo_path: &Path, name: &str) -> anyhow::Result<String> {
+  let mut name = name.to_string();
+  for _ in 0..=MAX_SYMREF_DEPTH {
+    match read_ref(repo_path, &name)? {
+      Some(Ref::Symbolic(target)) => name = target,
+      _ => return Ok(name),
+    }
+  }
+  anyhow::bail!("fatal: too many levels of symbolic refs at '{name}'")
+}
+
+/// The object hash `name` ultimately points at, or `Ok(None)` if the chain
+/// ends at a ref that does not exist.
+pub fn resolve_ref(repo_path: &Path, name: &str) -> anyhow::Result<Option<String>> {
+  let name = resolve_symbolic(repo_path, name)?;
+  match read_ref(repo_path, &name)? {
+    Some(Ref::Direct(hash)) => Ok(Some(hash)),
+    Some(Ref::Symbolic(_)) => unreachable!("resolve_symbolic stops at direct refs"),
+    None => Ok(None),
+  }
+}
+
+/// The commit `HEAD` points at; `Ok(None)` on an unborn branch.
+pub fn head(repo_path: &Path) -> anyhow::Result<Option<String>> {
+  resolve_ref(repo_path, "HEAD")
+}
+
+/// Points the ref `name` directly at `hash`, replacing a symbolic ref if
+/// there was one. `message` is recorded in the reflog.
+pub fn write_ref(repo_path: &Path, name: &str, hash: &str, message: &str) -> anyhow::Result<()> {
+  transaction(
+    repo_path,
+    &[RefUpdate {
+      name: name.to_string(),
+      change: RefChange::Set(hash.to_string()),
+      old: None,
+      message: message.to_string(),
+    }],
+  )
+}
+
+pub fn write_symbolic_ref(repo_path: &Path, name: &str, target: &str) -> anyhow::Result<()> {
+  anyhow::ensure!(
+    target.starts_with("refs/") && check_ref_format(target),
+    "fatal: Refusing to point {name} outside of refs/"
+  );
+  let mut lock = LockFile::acquire(&ref_path(repo_path, name)?)?;
+  lock.write_all(format!("ref: {target}\n").as_bytes())?;
+  lock.commit()
+}
+
+/// Removes the ref `name`, both its loose file and its packed-refs entry.
+/// Deleting a ref that does not exist is not an error.
+pub fn delete_ref(repo_path: &Path, name: &str) -> anyhow::Result<()> {
+  transaction(
+    repo_path,
+    &[RefUpdate {
+      name: name.to_string(),
+      change: RefChange::Delete,
+      old: None,
+      message: String::new(),
+    }],
+  )
+}
+
+/// Renames the direct ref `old` to `new`, carrying its reflog along and
+/// repointing `HEAD` if it pointed at `old`. `new` must not exist unless
+/// `force` is set, in which case it is overwritten.
+pub fn rename_ref(
+  repo_path: &Path,
+  old: &str,
+  new: &str,
+  message: &str,
+  force: bool,
+) -> anyhow::Result<()> {
+  let Some(Ref::Direct(hash)) = read_ref(repo_path, old)? else {
+    anyhow::bail!("fatal: cannot rename '{old}': not a direct ref");
+  };
+  let entries = reflog::read_reflog(repo_path, old)?;
+  transaction(
+    repo_path,
+    &[
+      RefUpdate {
+        name: old.to_string(),
+        change: RefChange::Delete,
+        old: Some(hash.clone()),
+        message: String::new(),
+      },
+      RefUpdate {
+        name: new.to_string(),
+        change: RefChange::Set(hash.clone()),
+        old: (!force).then(|| ZERO_HASH.to_string()),
+        message: message.to_string(),
+      },
+    ],
+  )?;
+  // As in git, the log of `old` moves to `new` even where `new` would not be
+  // logged on its own, and the rename is then logged as a move from the hash
+  // to itself.
+  if !entries.is_empty() || !reflog::read_reflog(repo_path, new)?.is_empty() {
+    reflog::write_reflog(repo_path, new, &entries)?;
+    reflog::append(repo_path, new, &hash, &hash, message)?;
+  }
+  if read_ref(repo_path, "HEAD")? == Some(Ref::Symbolic(old.to_string())) {
+    write_symbolic_ref(repo_path, "HEAD", new)?;
+    reflog::append(repo_path, "HEAD", &hash, &hash, message)?;
+  }
+  Ok(())
+}
+
+/// Removes only the loose file of `name`, leaving any packed-refs entry.
+pub fn delete_loose_ref(repo_path: &Path, name: &str) -> anyhow::Result<()> {
+  let path = ref_path(repo_path, name)?;
+  let _lock = LockFile::acquire(&path)?;
+  std::fs::remove_file(&path).with_context(|| format!("error: could not delete ref {name}"))?;
+  remove_empty_ref_dirs(repo_path, &path);
+  Ok(())
+}
+
+/// The zero hash git uses to say "this ref must not exist" as an old value
+/// and "delete this ref" as a new one.
+pub const ZERO_HASH: &str = "0000000000000000000000000000000000000000";
+
+/// What a [`RefUpdate`] does to its ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefChange {
+  Set(String),
+  Delete,
+  /// Only checks `old`, changing nothing.
+  Verify,
+}
+
+/// One step of a [`transaction`]. `old` is the hash the ref must currently
+/// have, [`ZERO_HASH`] if it must not exist, or `None` to skip the check.
+/// `message` is the reason recorded in the reflog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+  pub name: String,
+  pub change: RefChange,
+  pub old: Option<String>,
+  pub message: String,
+}
+
+/// Applies `updates`: every ref is locked and checked against its expected
+/// old value, and its new value written to its lock file, before any of them
+/// changes, so a rejected update leaves all refs as they were. Should moving
+/// a lock into place fail, the refs already moved stay updated; reflogs and
+/// packed-refs only ever record updates that landed. Updates name refs
+/// directly; symbolic refs are not followed, but moving the branch `HEAD`
+/// points at is logged in `HEAD`'s reflog too.
+pub fn transaction(repo_path: &Path, updates: &[RefUpdate]) -> anyhow::Result<()> {
+  let mut updates = updates.iter().collect::<Vec<_>>();
+  updates.sort_by(|a, b| a.name.cmp(&b.name));
+  for pair in updates.windows(2) {
+    anyhow::ensure!(
+      pair[0].name != pair[1].name,
+      "fatal: multiple updates for ref '{}' not allowed",
+      pair[0].name
+    );
+  }
+  let mut locks = Vec::new();
+  for update in &updates {
+    if let RefChange::Set(hash) = &update.change {
+      anyhow::ensure!(is_hash(hash), "invalid object hash: '{hash}'");
+    }
+    let lock = LockFile::acquire(&ref_path(repo_path, &update.name)?)
+      .map_err(|e| anyhow::anyhow!("fatal: cannot lock ref '{}': {e}", update.name))?;
+    locks.push(lock);
+  }
+  let deletes_packed = updates
+    .iter()
+    .any(|update| update.change == RefChange::Delete);
+  let packed_lock = if deletes_packed {
+    Some(lock_packed_refs(repo_path)?)
+  } else {
+    None
+  };
+  let mut currents = Vec::new();
+  for update in &updates {
+    let current = match read_ref(repo_path, &update.name)? {
+      Some(Ref::Direct(hash)) => Some(hash),
+      Some(Ref::Symbolic(target)) => resolve_ref(repo_path, &target)?,
+      None => None,
+    };
+    let name = &update.name;
+    match (&update.old, &current) {
+      (None, _) => {}
+      (Some(old), None) if old == ZERO_HASH => {}
+      (Some(old), Some(_)) if old == ZERO_HASH => {
+        anyhow::bail!("fatal: cannot lock ref '{name}': reference already exists")
+      }
+      (Some(_), None) => {
+        anyhow::bail!("fatal: cannot lock ref '{name}': unable to resolve reference '{name}'")
+      }
+      (Some(old), Some(current)) => anyhow::ensure!(
+        old == current,
+        "fatal: cannot lock ref '{name}': is at {current} but expected {old}"
+      ),
+    }
+    currents.push(current);
+  }
+  let head_target = match read_ref(repo_path, "HEAD")? {
+    Some(Ref::Symbolic(target)) => Some(target),
+    _ => None,
+  };
+
+  // New values go into the lock files first, where a failure still leaves
+  // every ref untouched.
+  for (update, lock) in updates.iter().zip(&mut locks) {
+    if let RefChange::Set(hash) = &update.change {
+      lock.write_all(format!("{}\n", hash.to_ascii_lowercase()).as_bytes())?;
+    }
+  }
+  // Then the loose refs land one by one, each logged only once it has.
+  for ((update, lock), current) in updates.iter().zip(locks).zip(currents) {
+    match &update.change {
+      RefChange::Set(hash) => {
+        lock.commit()?;
+        let hash = hash.to_ascii_lowercase();
+        let old = current.as_deref().unwrap_or(ZERO_HASH);
+        if reflog::should_log(repo_path, &update.name) {
+          reflog::append(repo_path, &update.name, old, &hash, &update.message)?;
+        }
+        if head_target.as_deref() == Some(update.name.as_str()) {
+          reflog::append(repo_path, "HEAD", old, &hash, &update.message)?;
+        }
+      }
+      RefChange::Delete => {
+        let path = lock.path().to_path_buf();
+        if path.is_file() {
+          std::fs::remove_file(&path)
+            .with_context(|| format!("error: could not delete ref {}", update.name))?;
+        }
+        drop(lock);
+        remove_empty_ref_dirs(repo_path, &path);
+      }
+      RefChange::Verify => {}
+    }
+  }
+  // Deleted refs leave packed-refs, and their reflogs go, only after every
+  // loose ref has been written.
+  if let Some(packed_lock) = packed_lock {
+    let mut packed = read_packed_refs(repo_path)?;
+    let packed_len = packed.len();
+    packed.retain(|packed_ref| {
+      !updates
+        .iter()
+        .any(|update| update.change == RefChange::Delete && update.name == packed_ref.name)
+    });
+    if packed.len() != packed_len {
+      write_packed_refs(repo_path, packed_lock, &packed)?;
+    }
+  }
+  for update in &updates {
+    if update.change == RefChange::Delete {
+      reflog::delete_reflog(repo_path, &update.name)?;
+    }
+  }
+  Ok(())
+}
+
+fn remove_empty_ref_dirs(repo_path: &Path, path: &Path) {
+  let keep = git_dir(repo_path).join("refs");
+  let mut dir = path.parent();
+  while let Some(current) = dir {
+    if !current.starts_with(&keep) || current == keep || current.parent() == Some(keep.as_path()) {
+      break;
+    }
+    if std::fs::remove_dir(current).is_err() {
+      break;
+    }
+    dir = current.parent();
+  }
+}
+
+/// Every loose ref under `.git/refs`, sorted by name.
+pub fn loose_refs(repo_path: &Path) -> anyhow::Result<Vec<(String, Ref)>> {
+  fn walk(repo_path: &Path, dir: &Path, refs: &mut Vec<(String, Ref)>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+      let path = entry?.path();
+      if path.is_dir() {
+        walk(repo_path, &path, refs)?;
+        continue;
+      }
+      let name = path
+        .strip_prefix(git_dir(repo_path))?
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/");
+      if !is_valid_ref_name(&name) {
+        continue;
+      }
+      if let Some(value) = read_ref(repo_path, &name)? {
+        refs.push((name, value));
+      }
+    }
+    Ok(())
+  }
+  let mut refs = Vec::new();
+  let refs_dir = git_dir(repo_path).join("refs");
+  if refs_dir.is_dir() {
+    walk(repo_path, &refs_dir, &mut refs)?;
+  }
+  refs.sort_by(|a, b| a.0.cmp(&b.0));
+  Ok(refs)
+}
+
+/// Every ref under `refs/`, loose or packed, with the hash it resolves to,
+/// sorted by name. Symbolic refs that point nowhere are left out.
+pub fn list_refs(repo_path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+  let mut refs = std::collections::BTreeMap::new();
+  for packed_ref in read_packed_refs(repo_path)? {
+    refs.insert(packed_ref.name, packed_ref.hash);
+  }
+  for (name, value) in loose_refs(repo_path)? {
+    let hash = match value {
+      Ref::Direct(hash) => Some(hash),
+      Ref::Symbolic(target) => resolve_ref(repo_path, &target)?,
+    };
+    match hash {
+      Some(hash) => refs.insert(name, hash),
+      None => refs.remove(&name),
+    };
+  }
+  Ok(refs.into_iter().collect())
+}
+
+/// The remote-tracking ref the branch `branch` (a full `refs/heads/` name)
+/// follows, from its `branch.<name>.remote` and `branch.<name>.merge`
+/// settings; `Ok(None)` when it has no upstream.
+pub fn upstream(repo_path: &Path, branch: &str) -> anyhow::Result<Option<String>> {
+  let Some(short) = branch.strip_prefix("refs/heads/") else {
+    return Ok(None);
+  };
+  let config = Config::read(repo_path)?;
+  let (Some(remote), Some(merge)) = (
+    config.get(&format!("branch.{short}.remote")),
+    config.get(&format!("branch.{short}.merge")),
+  ) else {
+    return Ok(None);
+  };
+  if remote == "." {
+    return Ok(Some(merge.to_string()));
+  }
+  let merge = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+  Ok(Some(format!("refs/remotes/{remote}/{merge}")))
+}
+
+/// Expands a short ref name the way `git rev-parse` does, returning the
+/// first of `<name>`, `refs/<name>`, `refs/tags/<name>`, `refs/heads/<name>`,
+/// `refs/remotes/<name>` and `refs/remotes/<name>/HEAD` that exists.
+pub fn dwim_ref(repo_path: &Path, name: &str) -> anyhow::Result<Option<String>> {
+  for pattern in [
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+    "refs/remotes/{}/HEAD",
+  ] {
+    let candidate = pattern.replace("{}", name);
+    if is_valid_ref_name(&candidate) && read_ref(repo_path, &candidate)?.is_some() {
+      return Ok(Some(candidate));
+    }
+  }
+  Ok(None)
+}
+
+/// The unambiguous short form git shows for a full ref name, e.g. `main` for
+/// `refs/heads/main`.
+pub fn shorten_ref_name(name: &str) -> &str {
+  ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+    .iter()
+    .find_map(|prefix| name.strip_prefix(prefix))
+    .unwrap_or(name)
+}
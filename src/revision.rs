@@ -0,0 +1,366 @@
+use crate::common::{Commit, GitObject, Kind, Tag, Tree};
+use crate::index::{Index, IndexEntry};
+use crate::reflog;
+use crate::refs;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::path::Path;
+
+fn unknown_revision(spec: &str) -> anyhow::Error {
+  anyhow::anyhow!(
+    "fatal: ambiguous argument '{spec}': unknown revision or path not in the working tree."
+  )
+}
+
+/// Resolves a revision the way `git rev-parse` does: full or abbreviated
+/// hashes, ref names, `HEAD`/`@`, `<ref>@{<n>}`, `@{upstream}`, followed by
+/// any of `~<n>`, `^<n>`, `^{<type>}` and `^{}`, as well as `<rev>:<path>`,
+/// `:<path>` for the staged blob and `:/<text>`, which finds the newest
+/// reachable commit whose message contains `<text>`.
+pub fn resolve(repo_path: &Path, spec: &str) -> anyhow::Result<String> {
+  if let Some(text) = spec.strip_prefix(":/") {
+    return search_message(repo_path, text)?.ok_or_else(|| unknown_revision(spec));
+  }
+  if let Some(path) = spec.strip_prefix(':') {
+    return index_path(repo_path, path);
+  }
+  if let Some((rev, path)) = spec.split_once(':') {
+    let tree = peel_to(repo_path, &resolve(repo_path, rev)?, Kind::Tree)?;
+    return tree_path(repo_path, &tree, path)?
+      .ok_or_else(|| anyhow::anyhow!("fatal: path '{path}' does not exist in '{rev}'"));
+  }
+
+  let end = spec.find(['~', '^']).unwrap_or(spec.len());
+  let mut hash = resolve_base(repo_path, &spec[..end], spec)?;
+  let mut rest = &spec[end..];
+  while !rest.is_empty() {
+    if let Some(after) = rest.strip_prefix("^{") {
+      let (kind, tail) = after
+        .split_once('}')
+        .ok_or_else(|| unknown_revision(spec))?;
+      hash = match kind {
+        "" => refs::peel(repo_path, &hash)?.unwrap_or(hash),
+        "object" => hash,
+        kind => {
+          let kind = Kind::from_str(kind).map_err(|_| unknown_revision(spec))?;
+          peel_to(repo_path, &hash, kind)?
+        }
+      };
+      rest = tail;
+      continue;
+    }
+    let operator = &rest[..1];
+    rest = &rest[1..];
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let n = match digits {
+      0 => 1,
+      _ => rest[..digits]
+        .parse::<usize>()
+        .map_err(|_| unknown_revision(spec))?,
+    };
+    rest = &rest[digits..];
+    match (operator, n) {
+      ("~", _) => {
+        for _ in 0..n {
+          hash = parent(repo_path, &hash, 1)?.ok_or_else(|| unknown_revision(spec))?;
+        }
+      }
+      (_, 0) => hash = peel_to(repo_path, &hash, Kind::Commit)?,
+      _ => hash = parent(repo_path, &hash, n)?.ok_or_else(|| unknown_revision(spec))?,
+    }
+  }
+  Ok(hash)
+}
+
+/// Looks up `<path>` or `:<stage>:<path>` (after the leading `:`) in the
+/// index.
+fn index_path(repo_path: &Path, path: &str) -> anyhow::Result<String> {
+  let (stage, path) = match path.as_bytes() {
+    [stage @ b'0'..=b'3', b':', ..] => (stage - b'0', &path[2..]),
+    _ => (0, path),
+  };
+  Index::read(repo_path)?
+    .entries()
+    .iter()
+    .find(|entry| entry.path == path && entry.stage == stage)
+    .map(IndexEntry::hash)
+    .ok_or_else(|| {
+      if repo_path.join(path).exists() {
+        anyhow::anyhow!("fatal: path '{path}' exists on disk, but not in the index")
+      } else {
+        anyhow::anyhow!("fatal: path '{path}' does not exist (neither on disk nor in the index)")
+      }
+    })
+}
+
+/// Resolves the part of a revision before any `~`/`^` operators.
+fn resolve_base(repo_path: &Path, base: &str, spec: &str) -> anyhow::Result<String> {
+  if base.is_empty() {
+    return Err(unknown_revision(spec));
+  }
+  let base = if base == "@" { "HEAD" } else { base };
+  if let Some((name, selector)) = base
+    .strip_suffix('}')
+    .and_then(|base| base.rsplit_once("@{"))
+  {
+    if selector.eq_ignore_ascii_case("u") || selector.eq_ignore_ascii_case("upstream") {
+      let upstream = upstream_of(repo_path, name)?;
+      return refs::resolve_ref(repo_path, &upstream)?.ok_or_else(|| unknown_revision(spec));
+    }
+    return reflog::resolve_selector(repo_path, base)?.ok_or_else(|| unknown_revision(spec));
+  }
+  if base.len() == 40 && base.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Ok(base.to_ascii_lowercase());
+  }
+  if let Some(full_name) = refs::dwim_ref(repo_path, base)? {
+    return refs::resolve_ref(repo_path, &full_name)?.ok_or_else(|| unknown_revision(spec));
+  }
+  if base.len() >= 4 && base.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Ok(GitObject::resolve_hash(repo_path, base)?);
+  }
+  Err(unknown_revision(spec))
+}
+
+/// The full name of the upstream of `name`, an empty string meaning the
+/// current branch.
+fn upstream_of(repo_path: &Path, name: &str) -> anyhow::Result<String> {
+  let branch = if name.is_empty() || name == "HEAD" {
+    refs::resolve_symbolic(repo_path, "HEAD")?
+  } else {
+    format!("refs/heads/{name}")
+  };
+  anyhow::ensure!(
+    branch.starts_with("refs/heads/"),
+    "fatal: HEAD does not point to a branch"
+  );
+  let short = refs::shorten_ref_name(&branch);
+  refs::upstream(repo_path, &branch)?
+    .ok_or_else(|| anyhow::anyhow!("fatal: no upstream configured for branch '{short}'"))
+}
+
+/// Follows tags, and commits to their trees, until reaching an object of
+/// `kind`.
+pub fn peel_to(repo_path: &Path, hash: &str, kind: Kind) -> anyhow::Result<String> {
+  let mut hash = hash.to_string();
+  loop {
+    let git_object = GitObject::read_object(repo_path, &hash)?;
+    if git_object.kind == kind {
+      return Ok(hash);
+    }
+    hash = match git_object.kind {
+      Kind::Tag => Tag::parse(git_object.content())?.object,
+      Kind::Commit if kind == Kind::Tree => Commit::parse(git_object.content())?.tree,
+      actual => anyhow::bail!(
+        "error: {hash}: expected {kind} type, but the object dereferences to {actual} type"
+      ),
+    };
+  }
+}
+
+/// The `n`th parent (counting from 1) of the commit `hash` peels to.
+pub fn parent(repo_path: &Path, hash: &str, n: usize) -> anyhow::Result<Option<String>> {
+  let commit = read_commit(repo_path, hash)?;
+  Ok(commit.parents.get(n - 1).cloned())
+}
+
+pub fn read_commit(repo_path: &Path, hash: &str) -> anyhow::Result<Commit> {
+  let hash = peel_to(repo_path, hash, Kind::Commit)?;
+  Ok(Commit::parse(
+    GitObject::read_object(repo_path, &hash)?.content(),
+  )?)
+}
+
+/// Looks `path` up below the tree `tree`; an empty path is the tree itself.
+pub fn tree_path(repo_path: &Path, tree: &str, path: &str) -> anyhow::Result<Option<String>> {
+  let mut hash = tree.to_string();
+  for component in path.split('/').filter(|component| !component.is_empty()) {
+    let git_object = GitObject::read_object(repo_path, &hash)?;
+    if git_object.kind != Kind::Tree {
+      return Ok(None);
+    }
+    match Tree::parse(git_object.content())?
+      .entries
+      .into_iter()
+      .find(|entry| entry.name == component.as_bytes())
+    {
+      Some(entry) => hash = entry.hash(),
+      None => return Ok(None),
+    }
+  }
+  Ok(Some(hash))
+}
+
+/// Every blob, symlink and gitlink below the tree `tree`, by full path,
+/// with its mode and hash.
+pub fn tree_files(repo_path: &Path, tree: &str) -> anyhow::Result<BTreeMap<String, (u32, String)>> {
+  fn walk(
+    repo_path: &Path,
+    tree: &str,
+    prefix: &str,
+    files: &mut BTreeMap<String, (u32, String)>,
+  ) -> anyhow::Result<()> {
+    let tree = Tree::parse(GitObject::read_object(repo_path, tree)?.content())?;
+    for entry in tree.entries {
+      let path = format!("{prefix}{}", entry.name_str());
+      let mode = u32::from_str_radix(&entry.mode, 8)
+        .map_err(|_| anyhow::anyhow!("bad mode '{}' for '{path}' in tree", entry.mode))?;
+      if mode == 0o40000 {
+        walk(repo_path, &entry.hash(), &format!("{path}/"), files)?;
+      } else {
+        files.insert(path, (mode, entry.hash()));
+      }
+    }
+    Ok(())
+  }
+  let mut files = BTreeMap::new();
+  walk(repo_path, tree, "", &mut files)?;
+  Ok(files)
+}
+
+/// The unix time of a `Name <email> <time> <tz>` signature; 0 when the
+/// signature carries no time.
+pub fn signature_time(signature: &str) -> i64 {
+  signature
+    .rsplit(' ')
+    .nth(1)
+    .and_then(|time| time.parse().ok())
+    .unwrap_or(0)
+}
+
+/// The date of a signature in git's default format, e.g.
+/// `Sun Oct 18 08:21:54 2026 +0000`, shown in the signature's own time zone.
+pub fn signature_date(signature: &str) -> String {
+  const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+  const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+  ];
+  let tz = signature.rsplit(' ').next().unwrap_or("+0000");
+  let offset = match (tz.get(..1), tz.get(1..3), tz.get(3..5)) {
+    (Some(sign), Some(hours), Some(minutes)) => {
+      let minutes = hours.parse::<i64>().unwrap_or(0) * 60 + minutes.parse::<i64>().unwrap_or(0);
+      if sign == "-" {
+        -minutes * 60
+      } else {
+        minutes * 60
+      }
+    }
+    _ => 0,
+  };
+  let local = signature_time(signature) + offset;
+  let (days, seconds) = (local.div_euclid(86400), local.rem_euclid(86400));
+  // Converts days since 1970-01-01 to a civil date, after Howard Hinnant's
+  // `civil_from_days`.
+  let shifted = days + 719468;
+  let era = shifted.div_euclid(146097);
+  let day_of_era = shifted.rem_euclid(146097);
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month_index = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+  let month = if month_index < 10 {
+    month_index + 3
+  } else {
+    month_index - 9
+  };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  format!(
+    "{} {} {day} {:02}:{:02}:{:02} {year} {tz}",
+    WEEKDAYS[(days + 4).rem_euclid(7) as usize],
+    MONTHS[month as usize - 1],
+    seconds / 3600,
+    seconds / 60 % 60,
+    seconds % 60
+  )
+}
+
+/// The newest commit reachable from `HEAD` or any ref whose message contains
+/// `text`.
+fn search_message(repo_path: &Path, text: &str) -> anyhow::Result<Option<String>> {
+  let mut tips = refs::head(repo_path)?.into_iter().collect::<Vec<_>>();
+  tips.extend(
+    refs::list_refs(repo_path)?
+      .into_iter()
+      .map(|(_, hash)| hash),
+  );
+  let mut best = None::<(i64, String)>;
+  let mut seen = HashSet::new();
+  let mut queue = VecDeque::new();
+  for tip in tips {
+    if let Ok(commit) = peel_to(repo_path, &tip, Kind::Commit) {
+      queue.push_back(commit);
+    }
+  }
+  while let Some(hash) = queue.pop_front() {
+    if !seen.insert(hash.clone()) {
+      continue;
+    }
+    let commit = read_commit(repo_path, &hash)?;
+    let time = signature_time(&commit.committer_str());
+    if commit.message_str().contains(text) && best.as_ref().is_none_or(|(best, _)| time > *best) {
+      best = Some((time, hash.clone()));
+    }
+    queue.extend(commit.parents);
+  }
+  Ok(best.map(|(_, hash)| hash))
+}
+
+/// Every commit reachable from `start`, including itself.
+pub fn ancestors(repo_path: &Path, start: &str) -> anyhow::Result<HashSet<String>> {
+  let mut seen = HashSet::new();
+  let mut queue = VecDeque::from([peel_to(repo_path, start, Kind::Commit)?]);
+  while let Some(hash) = queue.pop_front() {
+    if seen.insert(hash.clone()) {
+      queue.extend(read_commit(repo_path, &hash)?.parents);
+    }
+  }
+  Ok(seen)
+}
+
+/// The best common ancestors of `a` and `b`: common ancestors that are not
+/// themselves ancestors of another common ancestor. Sorted by hash.
+pub fn merge_bases(repo_path: &Path, a: &str, b: &str) -> anyhow::Result<Vec<String>> {
+  let b_ancestors = ancestors(repo_path, b)?;
+  let common = ancestors(repo_path, a)?
+    .into_iter()
+    .filter(|hash| b_ancestors.contains(hash))
+    .collect::<HashSet<_>>();
+  let mut below = HashSet::new();
+  for hash in &common {
+    let mut queue = VecDeque::from(read_commit(repo_path, hash)?.parents);
+    while let Some(parent) = queue.pop_front() {
+      if below.insert(parent.clone()) {
+        queue.extend(read_commit(repo_path, &parent)?.parents);
+      }
+    }
+  }
+  let mut bases = common
+    .into_iter()
+    .filter(|hash| !below.contains(hash))
+    .collect::<Vec<_>>();
+  bases.sort();
+  Ok(bases)
+}
+
+/// The shortest prefix of `hash`, at least `min_len` digits long, that no
+/// other stored object shares.
+pub fn abbreviate(repo_path: &Path, hash: &str, min_len: usize) -> anyhow::Result<String> {
+  let mut len = min_len.clamp(4, 40);
+  while len < 40 && GitObject::matching_hashes(repo_path, &hash[..len])?.len() > 1 {
+    len += 1;
+  }
+  Ok(hash[..len].to_string())
+}
+
+/// The full ref name a revision names, following symbolic refs, for
+/// `rev-parse --abbrev-ref`; `HEAD` when it is detached.
+pub fn symbolic_full_name(repo_path: &Path, spec: &str) -> anyhow::Result<String> {
+  let spec = if spec == "@" { "HEAD" } else { spec };
+  if let Some(name) = spec
+    .strip_suffix("@{u}")
+    .or(spec.strip_suffix("@{upstream}"))
+  {
+    return upstream_of(repo_path, name);
+  }
+  let full_name = refs::dwim_ref(repo_path, spec)?.ok_or_else(|| unknown_revision(spec))?;
+  refs::resolve_symbolic(repo_path, &full_name)
+}
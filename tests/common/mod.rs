@@ -0,0 +1,54 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::io::Cursor;
+use std::path::Path;
+
+use git_starter_rust::{
+  command::{add, commit_tree, write_tree},
+  common::{Commit, GitObject, Kind},
+};
+
+/// Writes `message` to `file.txt`, stages it and commits the resulting tree
+/// on top of `parent`, returning the new commit's hash.
+pub fn install_commit(repo: &Path, message: &str, parent: Option<&str>) -> anyhow::Result<String> {
+  std::fs::write(repo.join("file.txt"), message)?;
+  let mut stdout = Cursor::new(Vec::new());
+  add(&["file.txt".to_string()], repo, false, false, false)?;
+  write_tree(repo, &mut stdout)?;
+  let tree = String::from_utf8(stdout.into_inner())?;
+  let mut stdout = Cursor::new(Vec::new());
+  commit_tree(
+    tree.trim_end().to_string(),
+    &mut stdout,
+    repo,
+    message,
+    parent.map(str::to_string),
+  )?;
+  Ok(
+    String::from_utf8(stdout.into_inner())?
+      .trim_end()
+      .to_string(),
+  )
+}
+
+/// Stages `file.txt` as it is and commits it with `author` as both author
+/// and committer, so the commit does not depend on the clock.
+pub fn write_commit(repo: &Path, message: &str, author: &str) -> anyhow::Result<String> {
+  let mut stdout = Cursor::new(Vec::new());
+  add(&["file.txt".to_string()], repo, false, false, false)?;
+  write_tree(repo, &mut stdout)?;
+  let commit = Commit {
+    tree: String::from_utf8(stdout.into_inner())?
+      .trim_end()
+      .to_string(),
+    parents: Vec::new(),
+    author: author.as_bytes().to_vec(),
+    committer: author.as_bytes().to_vec(),
+    extra_headers: Vec::new(),
+    message: message.as_bytes().to_vec(),
+  };
+  let git_object = GitObject::from_content(Kind::Commit, &commit.serialize());
+  git_object.write(repo)?;
+  Ok(git_object.hash()?)
+}
@@ -0,0 +1,119 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use git_starter_rust::{
+  command::{add, init, status, StatusFormat, StatusOptions},
+  common::GitObject,
+  index::Index,
+  revision,
+};
+use tempdir::TempDir;
+
+fn staged(repo: &Path) -> anyhow::Result<Vec<String>> {
+  Ok(
+    Index::read(repo)?
+      .entries()
+      .iter()
+      .map(|entry| format!("{} {}", entry.path, &entry.hash()[..7]))
+      .collect(),
+  )
+}
+
+#[test]
+pub fn test_add() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_add")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  std::fs::create_dir_all(temp_dir.join("src/deep"))?;
+  std::fs::write(temp_dir.join("a.txt"), "a\n")?;
+  std::fs::write(temp_dir.join("src/b.rs"), "b\n")?;
+  std::fs::write(temp_dir.join("src/deep/c.rs"), "c\n")?;
+
+  add(&["src".to_string()], temp_dir, false, false, false)?;
+  assert_eq!(
+    staged(temp_dir)?,
+    ["src/b.rs 6178079", "src/deep/c.rs f2ad6c7"]
+  );
+  let entry = Index::read(temp_dir)?.get("src/b.rs").unwrap().clone();
+  assert_eq!(entry.mode, 0o100644);
+  assert_eq!(entry.stat.size, 2);
+  assert_ne!(entry.stat.mtime, 0);
+  assert_eq!(
+    GitObject::read_object(temp_dir, &entry.hash())?.content(),
+    b"b\n"
+  );
+  assert_eq!(revision::resolve(temp_dir, ":src/b.rs")?, entry.hash());
+  assert!(revision::resolve(temp_dir, ":a.txt").is_err());
+
+  let error = add(&["nope".to_string()], temp_dir, false, false, false).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "fatal: pathspec 'nope' did not match any files"
+  );
+  assert!(add(&[], temp_dir, false, false, false).is_err());
+
+  // -u only touches tracked files, including deletions.
+  std::fs::write(temp_dir.join("src/b.rs"), "changed\n")?;
+  std::fs::remove_file(temp_dir.join("src/deep/c.rs"))?;
+  add(&[], temp_dir, false, true, false)?;
+  assert_eq!(staged(temp_dir)?, ["src/b.rs 5ea2ed4"]);
+
+  // A pathspec naming a deleted tracked file stages its removal.
+  add(&["*.txt".to_string()], temp_dir, false, false, false)?;
+  std::fs::remove_file(temp_dir.join("a.txt"))?;
+  add(&["a.txt".to_string()], temp_dir, false, false, false)?;
+  assert_eq!(staged(temp_dir)?, ["src/b.rs 5ea2ed4"]);
+
+  std::fs::write(temp_dir.join("new.txt"), "new\n")?;
+  add(&[], temp_dir, true, false, false)?;
+  assert_eq!(staged(temp_dir)?, ["new.txt 3e75765", "src/b.rs 5ea2ed4"]);
+  assert!(!temp_dir.join(".git/index.lock").exists());
+  Ok(())
+}
+
+#[test]
+pub fn test_add_modes() -> anyhow::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+  let temp_dir = TempDir::new("test_add_modes")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  std::fs::write(temp_dir.join("run.sh"), "#!/bin/sh\n")?;
+  std::fs::set_permissions(
+    temp_dir.join("run.sh"),
+    std::fs::Permissions::from_mode(0o755),
+  )?;
+  std::fs::write(temp_dir.join("a"), "plain\n")?;
+  std::os::unix::fs::symlink("a", temp_dir.join("link"))?;
+  add(&[".".to_string()], temp_dir, false, false, false)?;
+  let index = Index::read(temp_dir)?;
+  let modes = index
+    .entries()
+    .iter()
+    .map(|entry| format!("{:o} {}", entry.mode, entry.path))
+    .collect::<Vec<_>>();
+  assert_eq!(modes, ["100644 a", "120000 link", "100755 run.sh"]);
+  let link = index.get("link").unwrap();
+  assert_eq!(
+    GitObject::read_object(temp_dir, &link.hash())?.content(),
+    b"a"
+  );
+
+  // Mode and type changes show up in status.
+  std::fs::set_permissions(
+    temp_dir.join("run.sh"),
+    std::fs::Permissions::from_mode(0o644),
+  )?;
+  std::fs::remove_file(temp_dir.join("link"))?;
+  std::fs::write(temp_dir.join("link"), "a")?;
+  let mut stdout = Cursor::new(Vec::new());
+  let options = StatusOptions {
+    format: StatusFormat::Short,
+    branch: false,
+  };
+  status(&mut stdout, temp_dir, &options)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "A  a\nAT link\nAM run.sh\n"
+  );
+  Ok(())
+}
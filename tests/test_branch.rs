@@ -0,0 +1,201 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use git_starter_rust::{
+  command::{
+    branch_create, branch_delete, branch_list, branch_rename, branch_show_current, init,
+    update_ref, BranchListOptions,
+  },
+  reflog,
+  refs::{self, Ref},
+};
+use tempdir::TempDir;
+
+mod common;
+use common::install_commit;
+
+fn list(repo: &Path, options: &BranchListOptions) -> anyhow::Result<String> {
+  let mut stdout = Cursor::new(Vec::new());
+  branch_list(&mut stdout, repo, options)?;
+  Ok(String::from_utf8(stdout.into_inner())?)
+}
+
+#[test]
+pub fn test_branch_list_and_create() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_branch")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  assert_eq!(list(temp_dir, &BranchListOptions::default())?, "");
+  let first = install_commit(temp_dir, "first", None)?;
+  let second = install_commit(temp_dir, "second\n\nbody", Some(&first))?;
+  update_ref("HEAD", Some(&second), None, temp_dir, "", false, false)?;
+
+  branch_create("feature", Some("HEAD~1"), temp_dir)?;
+  branch_create("topic/long-name", None, temp_dir)?;
+  assert_eq!(
+    refs::resolve_ref(temp_dir, "refs/heads/feature")?,
+    Some(first.clone())
+  );
+  assert_eq!(
+    reflog::read_reflog(temp_dir, "refs/heads/feature")?[0].message,
+    "branch: Created from HEAD~1"
+  );
+  let error = branch_create("feature", None, temp_dir).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "fatal: a branch named 'feature' already exists"
+  );
+  assert!(branch_create("bad..name", None, temp_dir).is_err());
+  assert!(branch_create("HEAD", None, temp_dir).is_err());
+  assert!(branch_create("other", Some("nope"), temp_dir).is_err());
+
+  assert_eq!(
+    list(temp_dir, &BranchListOptions::default())?,
+    "  feature\n* master\n  topic/long-name\n"
+  );
+  let verbose = BranchListOptions {
+    verbose: true,
+    ..Default::default()
+  };
+  assert_eq!(
+    list(temp_dir, &verbose)?,
+    format!(
+      "  feature         {} first\n* master          {} second\n  topic/long-name {} second\n",
+      &first[..7],
+      &second[..7],
+      &second[..7]
+    )
+  );
+  let contains = BranchListOptions {
+    contains: Some(second.clone()),
+    ..Default::default()
+  };
+  assert_eq!(list(temp_dir, &contains)?, "* master\n  topic/long-name\n");
+
+  let mut stdout = Cursor::new(Vec::new());
+  branch_show_current(&mut stdout, temp_dir)?;
+  assert_eq!(String::from_utf8(stdout.into_inner())?, "master\n");
+
+  update_ref("HEAD", Some(&first), None, temp_dir, "", false, true)?;
+  assert_eq!(
+    list(temp_dir, &BranchListOptions::default())?,
+    format!(
+      "* (HEAD detached at {})\n  feature\n  master\n  topic/long-name\n",
+      &first[..7]
+    )
+  );
+  let mut stdout = Cursor::new(Vec::new());
+  branch_show_current(&mut stdout, temp_dir)?;
+  assert!(stdout.into_inner().is_empty());
+  Ok(())
+}
+
+#[test]
+pub fn test_branch_delete_and_rename() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_branch")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let first = install_commit(temp_dir, "first", None)?;
+  let second = install_commit(temp_dir, "second", Some(&first))?;
+  let side = install_commit(temp_dir, "side", Some(&first))?;
+  update_ref("HEAD", Some(&second), None, temp_dir, "", false, false)?;
+  branch_create("merged", Some(&first), temp_dir)?;
+  branch_create("unmerged", Some(&side), temp_dir)?;
+
+  let mut stdout = Cursor::new(Vec::new());
+  branch_delete(&["merged".to_string()], &mut stdout, temp_dir, false)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    format!("Deleted branch merged (was {}).\n", &first[..7])
+  );
+  assert_eq!(refs::read_ref(temp_dir, "refs/heads/merged")?, None);
+  let error = branch_delete(
+    &["unmerged".to_string()],
+    &mut Cursor::new(Vec::new()),
+    temp_dir,
+    false,
+  )
+  .unwrap_err();
+  assert!(error.to_string().contains("is not fully merged"));
+  assert!(branch_delete(
+    &["master".to_string()],
+    &mut Cursor::new(Vec::new()),
+    temp_dir,
+    true
+  )
+  .is_err());
+  assert!(branch_delete(
+    &["nope".to_string()],
+    &mut Cursor::new(Vec::new()),
+    temp_dir,
+    true
+  )
+  .is_err());
+
+  branch_rename(Some("unmerged"), "renamed", temp_dir, false)?;
+  assert_eq!(refs::read_ref(temp_dir, "refs/heads/unmerged")?, None);
+  assert_eq!(
+    refs::resolve_ref(temp_dir, "refs/heads/renamed")?,
+    Some(side.clone())
+  );
+  let entries = reflog::read_reflog(temp_dir, "refs/heads/renamed")?;
+  assert_eq!(entries.len(), 2);
+  assert_eq!(entries[0].message, format!("branch: Created from {side}"));
+  assert_eq!(
+    (entries[1].old.as_str(), entries[1].new.as_str()),
+    (side.as_str(), side.as_str())
+  );
+  assert!(reflog::read_reflog(temp_dir, "refs/heads/unmerged")?.is_empty());
+
+  let error = branch_rename(None, "renamed", temp_dir, false).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "fatal: a branch named 'renamed' already exists"
+  );
+  branch_rename(None, "main", temp_dir, false)?;
+  assert_eq!(
+    refs::read_ref(temp_dir, "HEAD")?,
+    Some(Ref::Symbolic("refs/heads/main".to_string()))
+  );
+  assert_eq!(refs::head(temp_dir)?, Some(second.clone()));
+  assert_eq!(
+    reflog::read_reflog(temp_dir, "HEAD")?
+      .last()
+      .unwrap()
+      .message,
+    "Branch: renamed refs/heads/master to refs/heads/main"
+  );
+
+  branch_rename(Some("renamed"), "main", temp_dir, true)?;
+  assert_eq!(refs::head(temp_dir)?, Some(side));
+  Ok(())
+}
+
+#[test]
+pub fn test_rename_ref_keeps_reflog_where_new_is_unlogged() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_branch")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let first = install_commit(temp_dir, "first", None)?;
+  branch_create("topic", Some(&first), temp_dir)?;
+
+  // refs/archive/ is not logged by default, so only the carried-over log
+  // can give it one.
+  refs::rename_ref(
+    temp_dir,
+    "refs/heads/topic",
+    "refs/archive/topic",
+    "archived",
+    false,
+  )?;
+  let entries = reflog::read_reflog(temp_dir, "refs/archive/topic")?;
+  assert_eq!(entries.len(), 2);
+  assert_eq!(entries[0].message, format!("branch: Created from {first}"));
+  assert_eq!(entries[1].message, "archived");
+  assert_eq!(
+    (entries[1].old.as_str(), entries[1].new.as_str()),
+    (first.as_str(), first.as_str())
+  );
+  assert!(reflog::read_reflog(temp_dir, "refs/heads/topic")?.is_empty());
+  Ok(())
+}
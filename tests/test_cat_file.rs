@@ -1,6 +1,9 @@
 use std::io::Cursor;
 
-use git_starter_rust::command::cat_file;
+use git_starter_rust::{
+  command::{cat_file, cat_file_batch, CatFileMode},
+  error::Error,
+};
 use tempdir::TempDir;
 
 #[test]
@@ -18,7 +21,7 @@ pub fn test_cat_file() -> anyhow::Result<()> {
     "557db03de997c86a4a028e1ebd3a1ceb225be238",
     &mut stdout,
     temp_dir,
-    true,
+    CatFileMode::PrettyPrint,
   )?;
   assert_eq!(
     String::from_utf8(stdout.into_inner()).unwrap(),
@@ -26,3 +29,165 @@ pub fn test_cat_file() -> anyhow::Result<()> {
   );
   Ok(())
 }
+
+#[test]
+pub fn test_cat_file_commit() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  let git_object = include_bytes!("data/fbb07636d6123deb9a0e878b97190b410e13a3ec");
+  std::fs::create_dir_all(temp_dir.join(".git/objects/fb"))?;
+  std::fs::write(
+    temp_dir.join(".git/objects/fb/b07636d6123deb9a0e878b97190b410e13a3ec"),
+    git_object,
+  )?;
+  cat_file(
+    "fbb07636d6123deb9a0e878b97190b410e13a3ec",
+    &mut stdout,
+    temp_dir,
+    CatFileMode::PrettyPrint,
+  )?;
+  let expected = r#"tree f7e2ffd419c25f7dce95ad36b10f524c18ea3df3
+parent f70dbd9f054f9eb52979184d42e5f7cf2a59d851
+parent 3d9acc2666e118f44181ea6036ada054efd3d611
+author Coder <coder@crafters.io> 1700000000 +0100
+committer Other Coder <other@crafters.io> 1700000500 -0230
+encoding ISO-8859-1
+gpgsig -----BEGIN PGP SIGNATURE-----
+ 
+ iQEzBAABCAAdFiEEexample
+ =abcd
+ -----END PGP SIGNATURE-----
+
+Merge two lines of history
+
+With a body paragraph.
+"#;
+  assert_eq!(String::from_utf8(stdout.into_inner()).unwrap(), expected);
+  Ok(())
+}
+
+#[test]
+pub fn test_cat_file_commit_latin1() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  let git_object = include_bytes!("data/5263e4bdbb6ab8019c0dbc489d2c73131775bc86");
+  std::fs::create_dir_all(temp_dir.join(".git/objects/52"))?;
+  std::fs::write(
+    temp_dir.join(".git/objects/52/63e4bdbb6ab8019c0dbc489d2c73131775bc86"),
+    git_object,
+  )?;
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  cat_file(
+    "5263e4bdbb6ab8019c0dbc489d2c73131775bc86",
+    &mut stdout,
+    temp_dir,
+    CatFileMode::PrettyPrint,
+  )?;
+  // The message is "café" in Latin-1, which is not valid UTF-8.
+  assert_eq!(
+    stdout.into_inner(),
+    b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+      author a <a@b> 1700000000 +0000\n\
+      committer a <a@b> 1700000000 +0000\n\
+      encoding ISO-8859-1\n\
+      \n\
+      caf\xe9\n"
+  );
+  Ok(())
+}
+
+fn install_hello_world(temp_dir: &std::path::Path) -> anyhow::Result<()> {
+  let git_object = include_bytes!("data/557db03de997c86a4a028e1ebd3a1ceb225be238");
+  std::fs::create_dir_all(temp_dir.join(".git/objects/55"))?;
+  std::fs::write(
+    temp_dir.join(".git/objects/55/7db03de997c86a4a028e1ebd3a1ceb225be238"),
+    git_object,
+  )?;
+  Ok(())
+}
+
+#[test]
+pub fn test_cat_file_type_size_exists() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  install_hello_world(temp_dir)?;
+  let hash = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  cat_file(hash, &mut stdout, temp_dir, CatFileMode::Type)?;
+  cat_file(hash, &mut stdout, temp_dir, CatFileMode::Size)?;
+  cat_file(hash, &mut stdout, temp_dir, CatFileMode::Exists)?;
+  assert_eq!(String::from_utf8(stdout.into_inner())?, "blob\n12\n");
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  let missing = cat_file(
+    "0000000000000000000000000000000000000000",
+    &mut stdout,
+    temp_dir,
+    CatFileMode::Exists,
+  );
+  assert!(missing.is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_cat_file_batch() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  install_hello_world(temp_dir)?;
+  let mut stdin = Cursor::new(
+    "557db03de997c86a4a028e1ebd3a1ceb225be238\n0000000000000000000000000000000000000000\nnope\n",
+  );
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  cat_file_batch(&mut stdin, &mut stdout, temp_dir, true)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "557db03de997c86a4a028e1ebd3a1ceb225be238 blob 12\nHello World\n\n\
+     0000000000000000000000000000000000000000 missing\nnope missing\n"
+  );
+
+  stdin.set_position(0);
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  cat_file_batch(&mut stdin, &mut stdout, temp_dir, false)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "557db03de997c86a4a028e1ebd3a1ceb225be238 blob 12\n\
+     0000000000000000000000000000000000000000 missing\nnope missing\n"
+  );
+
+  // An object that is there but unreadable is an error, not "missing".
+  let corrupt = "1111111111111111111111111111111111111111";
+  std::fs::create_dir_all(temp_dir.join(".git/objects/11"))?;
+  std::fs::write(
+    temp_dir.join(format!(".git/objects/11/{}", &corrupt[2..])),
+    b"not zlib",
+  )?;
+  let mut stdin = Cursor::new(format!("{corrupt}\n"));
+  let result = cat_file_batch(&mut stdin, &mut Cursor::new(Vec::new()), temp_dir, false);
+  assert!(matches!(result, Err(Error::CorruptObject { .. })));
+  Ok(())
+}
+
+#[test]
+pub fn test_cat_file_abbreviated_hash() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  install_hello_world(temp_dir)?;
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  cat_file("557db03", &mut stdout, temp_dir, CatFileMode::PrettyPrint)?;
+  assert_eq!(String::from_utf8(stdout.into_inner())?, "Hello World\n");
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  assert!(cat_file("55", &mut stdout, temp_dir, CatFileMode::PrettyPrint).is_err());
+
+  let mut stdin = Cursor::new("557d\n");
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  cat_file_batch(&mut stdin, &mut stdout, temp_dir, false)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "557db03de997c86a4a028e1ebd3a1ceb225be238 blob 12\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,162 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use git_starter_rust::{
+  command::{add, check_ignore, init, status, StatusFormat, StatusOptions},
+  common::{wildmatch, GitObject, Tree},
+  index::Index,
+};
+use tempdir::TempDir;
+
+fn run_check_ignore(
+  repo: &Path,
+  paths: &[&str],
+  verbose: bool,
+  non_matching: bool,
+) -> anyhow::Result<(bool, String)> {
+  let paths = paths
+    .iter()
+    .map(|path| path.to_string())
+    .collect::<Vec<_>>();
+  let mut stdout = Cursor::new(Vec::new());
+  let ignored = check_ignore(&paths, &mut stdout, repo, verbose, non_matching)?;
+  Ok((ignored, String::from_utf8(stdout.into_inner())?))
+}
+
+fn setup(repo: &Path) -> anyhow::Result<()> {
+  init(repo, &mut Cursor::new(Vec::new()))?;
+  std::fs::write(
+    repo.join(".gitignore"),
+    "# build output\n*.log\n!keep.log\ntarget/\n/root.txt\nsub/**/x\n",
+  )?;
+  std::fs::create_dir_all(repo.join("sub/deep"))?;
+  std::fs::create_dir_all(repo.join("target"))?;
+  std::fs::create_dir_all(repo.join(".git/info"))?;
+  std::fs::write(repo.join("sub/.gitignore"), "deep/\n*.tmp\n!b.tmp\n")?;
+  std::fs::write(repo.join(".git/info/exclude"), "a.tmp\n")?;
+  for path in [
+    "a.log",
+    "keep.log",
+    "root.txt",
+    "sub/root.txt",
+    "sub/deep/file",
+    "sub/x",
+    "target/out",
+    "a.tmp",
+    "sub/a.tmp",
+    "sub/b.tmp",
+  ] {
+    std::fs::write(repo.join(path), format!("{path}\n"))?;
+  }
+  Ok(())
+}
+
+#[test]
+pub fn test_check_ignore() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_check_ignore")?;
+  let temp_dir = temp_dir.path();
+  setup(temp_dir)?;
+
+  let paths = [
+    "a.log",
+    "keep.log",
+    "root.txt",
+    "sub/root.txt",
+    "sub/deep/file",
+    "sub/x",
+    "target",
+    "a.tmp",
+    "sub/a.tmp",
+    "sub/b.tmp",
+  ];
+  assert_eq!(
+    run_check_ignore(temp_dir, &paths, true, true)?,
+    (
+      true,
+      ".gitignore:2:*.log\ta.log\n\
+       .gitignore:3:!keep.log\tkeep.log\n\
+       .gitignore:5:/root.txt\troot.txt\n\
+       ::\tsub/root.txt\n\
+       sub/.gitignore:1:deep/\tsub/deep/file\n\
+       .gitignore:6:sub/**/x\tsub/x\n\
+       .gitignore:4:target/\ttarget\n\
+       .git/info/exclude:1:a.tmp\ta.tmp\n\
+       sub/.gitignore:2:*.tmp\tsub/a.tmp\n\
+       sub/.gitignore:3:!b.tmp\tsub/b.tmp\n"
+        .to_string()
+    )
+  );
+  assert_eq!(
+    run_check_ignore(temp_dir, &paths, false, false)?.1,
+    "a.log\nroot.txt\nsub/deep/file\nsub/x\ntarget\na.tmp\nsub/a.tmp\n"
+  );
+  assert_eq!(
+    run_check_ignore(temp_dir, &["keep.log", "missing"], false, false)?,
+    (false, String::new())
+  );
+
+  // Ignored files stay out of status, `add .` and trees, but can be forced
+  // in, after which they are tracked like any other file.
+  let mut stdout = Cursor::new(Vec::new());
+  let options = StatusOptions {
+    format: StatusFormat::Short,
+    branch: false,
+  };
+  status(&mut stdout, temp_dir, &options)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "?? .gitignore\n?? keep.log\n?? sub/\n"
+  );
+  let tree = Tree::parse(GitObject::build_tree_object(temp_dir)?.content())?;
+  let names = tree
+    .entries
+    .iter()
+    .map(|entry| entry.name_str())
+    .collect::<Vec<_>>();
+  assert_eq!(names, [".gitignore", "keep.log", "sub"]);
+  let err = add(&["a.log".to_string()], temp_dir, false, false, false).unwrap_err();
+  assert!(err
+    .to_string()
+    .starts_with("The following paths are ignored by one of your .gitignore files:\na.log\n"));
+  add(&["a.log".to_string()], temp_dir, false, false, true)?;
+  add(&[".".to_string()], temp_dir, false, false, false)?;
+  let staged = Index::read(temp_dir)?
+    .entries()
+    .iter()
+    .map(|entry| entry.path.clone())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    staged,
+    [
+      ".gitignore",
+      "a.log",
+      "keep.log",
+      "sub/.gitignore",
+      "sub/b.tmp",
+      "sub/root.txt"
+    ]
+  );
+  assert_eq!(
+    run_check_ignore(temp_dir, &["a.log"], true, true)?,
+    (false, "::\ta.log\n".to_string())
+  );
+  Ok(())
+}
+
+#[test]
+pub fn test_wildmatch() {
+  assert!(wildmatch("src/*.rs", "src/main.rs"));
+  assert!(!wildmatch("src/*.rs", "src/nested/main.rs"));
+  assert!(!wildmatch("src/?ain.rs", "src/main/rs"));
+  assert!(wildmatch("**/x", "x"));
+  assert!(wildmatch("**/x", "a/b/x"));
+  assert!(wildmatch("a/**/x", "a/x"));
+  assert!(wildmatch("a/**/x", "a/b/c/x"));
+  assert!(!wildmatch("a/**/x", "b/a/x"));
+  assert!(wildmatch("a/**", "a/b/c"));
+  assert!(!wildmatch("a/**", "a"));
+  // `**` inside a component is a plain `*`.
+  assert!(wildmatch("a**b", "axxb"));
+  assert!(!wildmatch("a**b", "a/b"));
+  assert!(wildmatch("\\#*", "#hash"));
+}
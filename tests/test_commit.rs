@@ -1,8 +1,8 @@
 use std::io::Cursor;
 
 use git_starter_rust::{
-  command::{commit_tree, init},
-  common::GitObject,
+  command::{cat_file, commit_tree, init, CatFileMode},
+  common::{Commit, GitObject, Kind},
 };
 use tempdir::TempDir;
 
@@ -30,3 +30,93 @@ pub fn test_commit() -> anyhow::Result<()> {
   assert_eq!(actual_output, "05e1fd875f25ab9683c3b40e51016abc33ee6720\n");
   Ok(())
 }
+
+#[test]
+pub fn test_parse_commit() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_commit")?;
+  let temp_dir = temp_dir.path();
+  let git_object = include_bytes!("data/fbb07636d6123deb9a0e878b97190b410e13a3ec");
+  std::fs::create_dir_all(temp_dir.join(".git/objects/fb"))?;
+  std::fs::write(
+    temp_dir.join(".git/objects/fb/b07636d6123deb9a0e878b97190b410e13a3ec"),
+    git_object,
+  )?;
+  let git_object = GitObject::read_object(temp_dir, "fbb07636d6123deb9a0e878b97190b410e13a3ec")?;
+  assert_eq!(git_object.kind, Kind::Commit);
+  let commit = Commit::parse(git_object.content())?;
+  assert_eq!(commit.tree, "f7e2ffd419c25f7dce95ad36b10f524c18ea3df3");
+  assert_eq!(
+    commit.parents,
+    vec![
+      "f70dbd9f054f9eb52979184d42e5f7cf2a59d851",
+      "3d9acc2666e118f44181ea6036ada054efd3d611"
+    ]
+  );
+  assert_eq!(
+    commit.author_str(),
+    "Coder <coder@crafters.io> 1700000000 +0100"
+  );
+  assert_eq!(
+    commit.committer_str(),
+    "Other Coder <other@crafters.io> 1700000500 -0230"
+  );
+  assert_eq!(commit.extra_headers.len(), 2);
+  assert_eq!(
+    commit.extra_headers[0],
+    ("encoding".to_string(), b"ISO-8859-1".to_vec())
+  );
+  assert_eq!(commit.extra_headers[1].0, "gpgsig");
+  assert_eq!(
+    commit.extra_headers[1].1,
+    b"-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEEexample\n=abcd\n-----END PGP SIGNATURE-----"
+  );
+  assert_eq!(
+    commit.message_str(),
+    "Merge two lines of history\n\nWith a body paragraph.\n"
+  );
+  assert_eq!(commit.serialize(), git_object.content());
+  Ok(())
+}
+
+#[test]
+pub fn test_commit_tree_round_trip() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_commit")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  std::fs::write(temp_dir.join("root_file.txt"), b"root file content\n")?;
+  let tree_object = GitObject::build_tree_object(temp_dir)?;
+  tree_object.write(temp_dir)?;
+  let mut stdout = Cursor::new(Vec::new());
+  commit_tree(
+    tree_object.hash()?,
+    &mut stdout,
+    temp_dir,
+    "test message",
+    None,
+  )?;
+  let mut stdout = Cursor::new(Vec::new());
+  commit_tree(
+    "8bc36e1".to_string(),
+    &mut stdout,
+    temp_dir,
+    "second",
+    Some("05e1fd8".to_string()),
+  )?;
+  let hash = String::from_utf8(stdout.into_inner())?;
+
+  let mut stdout = Cursor::new(Vec::new());
+  cat_file(
+    hash.trim_end(),
+    &mut stdout,
+    temp_dir,
+    CatFileMode::PrettyPrint,
+  )?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "tree 8bc36e1abc3de06227014c831f2a8d8e89bb7224\n\
+     parent 05e1fd875f25ab9683c3b40e51016abc33ee6720\n\
+     author Coder <coder@crafters.io>\n\
+     committer Coder <coder@crafters.io>\n\nsecond\n"
+  );
+  Ok(())
+}
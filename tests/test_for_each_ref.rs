@@ -0,0 +1,98 @@
+use std::io::Cursor;
+
+use git_starter_rust::command::{
+  for_each_ref, init, pack_refs, tag, update_ref, ForEachRefOptions,
+};
+use tempdir::TempDir;
+
+mod common;
+use common::write_commit;
+
+#[test]
+pub fn test_for_each_ref() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_for_each_ref")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  std::fs::write(temp_dir.join("file.txt"), "content")?;
+  let old = write_commit(
+    temp_dir,
+    "old\nwrapped subject\n\nbody\n",
+    "Coder <coder@crafters.io> 946684799 -0530",
+  )?;
+  let new = write_commit(
+    temp_dir,
+    "new\n",
+    "Coder <coder@crafters.io> 1700000000 +0100",
+  )?;
+  update_ref("HEAD", Some(&new), None, temp_dir, "", false, false)?;
+  update_ref(
+    "refs/heads/old",
+    Some(&old),
+    None,
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+  tag("v1", temp_dir, true, Some("release one".to_string()), &old)?;
+  pack_refs(temp_dir, true)?;
+  std::fs::write(
+    temp_dir.join(".git/config"),
+    "[branch \"master\"]\n\tremote = .\n\tmerge = refs/heads/old\n",
+  )?;
+
+  let run = |patterns: &[&str], options: &ForEachRefOptions| -> anyhow::Result<String> {
+    let mut stdout = Cursor::new(Vec::new());
+    let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    for_each_ref(&patterns, &mut stdout, temp_dir, options)?;
+    Ok(String::from_utf8(stdout.into_inner())?)
+  };
+  let tag_hash = std::fs::read_to_string(temp_dir.join(".git/packed-refs"))?
+    .lines()
+    .find(|line| line.ends_with(" refs/tags/v1"))
+    .map(|line| line[..40].to_string())
+    .unwrap();
+  assert_eq!(
+    run(&[], &ForEachRefOptions::default())?,
+    format!(
+      "{new} commit\trefs/heads/master\n{old} commit\trefs/heads/old\n{tag_hash} tag\trefs/tags/v1\n"
+    )
+  );
+
+  let format = ForEachRefOptions {
+    format: Some(
+      "%(refname:short)|%(objecttype)|%(subject)|%(authordate)|%(upstream:short)|%%".to_string(),
+    ),
+    ..Default::default()
+  };
+  assert_eq!(
+    run(&[], &format)?,
+    "master|commit|new|Tue Nov 14 23:13:20 2023 +0100|old|%\n\
+     old|commit|old wrapped subject|Fri Dec 31 18:29:59 1999 -0530||%\n\
+     v1|tag|release one|||%\n"
+  );
+
+  let sorted = ForEachRefOptions {
+    format: Some("%(refname)".to_string()),
+    sort: Some("-authordate".to_string()),
+    count: Some(2),
+  };
+  assert_eq!(
+    run(&["refs/heads"], &sorted)?,
+    "refs/heads/master\nrefs/heads/old\n"
+  );
+  let by_date = ForEachRefOptions {
+    sort: Some("authordate".to_string()),
+    ..sorted
+  };
+  assert_eq!(run(&["refs/*/o*"], &by_date)?, "refs/heads/old\n");
+  assert_eq!(run(&["refs/head"], &by_date)?, "");
+
+  let unknown = ForEachRefOptions {
+    format: Some("%(bogus)".to_string()),
+    ..Default::default()
+  };
+  let error = run(&[], &unknown).unwrap_err();
+  assert_eq!(error.to_string(), "fatal: unknown field name: bogus");
+  Ok(())
+}
@@ -1,4 +1,6 @@
-use git_starter_rust::common::{GitObject, Kind};
+use git_starter_rust::common::{Blob, Commit, GitObject, Kind, Tag, Tree};
+use git_starter_rust::error::Error;
+use git_starter_rust::pack::PackIndex;
 use tempdir::TempDir;
 
 #[test]
@@ -82,3 +84,261 @@ pub fn test_tree_object() -> anyhow::Result<()> {
   assert_eq!(tree_object.data, &expected_data[..]);
   Ok(())
 }
+
+#[test]
+pub fn test_tree_object_modes() -> anyhow::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+  let temp_dir = TempDir::new("test_tree_modes")?;
+  let temp_dir = temp_dir.path();
+  std::fs::write(temp_dir.join("run.sh"), "#!/bin/sh\n")?;
+  std::fs::set_permissions(
+    temp_dir.join("run.sh"),
+    std::fs::Permissions::from_mode(0o755),
+  )?;
+  std::fs::write(temp_dir.join("a"), "plain\n")?;
+  std::os::unix::fs::symlink("a", temp_dir.join("link"))?;
+  // A nested repository with a detached HEAD becomes a gitlink.
+  std::fs::create_dir_all(temp_dir.join("sub/.git"))?;
+  std::fs::write(
+    temp_dir.join("sub/.git/HEAD"),
+    "0123456789abcdef0123456789abcdef01234567\n",
+  )?;
+
+  // Same hash as `git write-tree` on this layout.
+  let tree_object = GitObject::build_tree_object(temp_dir)?;
+  assert_eq!(
+    tree_object.hash()?,
+    "cd4362f6a0309016f0771ce476b452b96544a93b"
+  );
+  let tree = Tree::parse(tree_object.content())?;
+  let entries = tree
+    .entries
+    .iter()
+    .map(|entry| format!("{} {} {}", entry.mode, entry.kind(), entry.name_str()))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    entries,
+    [
+      "100644 blob a",
+      "120000 blob link",
+      "100755 blob run.sh",
+      "160000 commit sub"
+    ]
+  );
+  assert_eq!(tree.serialize(), tree_object.content());
+  let link = GitObject::build_worktree_entry(&temp_dir.join("link"))?;
+  assert_eq!(link.blob.unwrap().content(), b"a");
+  Ok(())
+}
+
+#[test]
+pub fn test_resolve_hash() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  let git_object = include_bytes!("data/557db03de997c86a4a028e1ebd3a1ceb225be238");
+  std::fs::create_dir_all(temp_dir.join(".git/objects/55"))?;
+  std::fs::write(
+    temp_dir.join(".git/objects/55/7db03de997c86a4a028e1ebd3a1ceb225be238"),
+    git_object,
+  )?;
+  let full = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+  assert_eq!(GitObject::resolve_hash(temp_dir, "557d")?, full);
+  assert_eq!(GitObject::resolve_hash(temp_dir, "557DB03")?, full);
+  assert_eq!(GitObject::resolve_hash(temp_dir, full)?, full);
+  assert!(GitObject::resolve_hash(temp_dir, "557").is_err());
+  assert!(GitObject::resolve_hash(temp_dir, "5").is_err());
+  assert!(GitObject::resolve_hash(temp_dir, "zzzz").is_err());
+  assert!(GitObject::resolve_hash(temp_dir, "aaaa").is_err());
+
+  std::fs::write(
+    temp_dir.join(".git/objects/55/7d000000000000000000000000000000000000"),
+    git_object,
+  )?;
+  assert_eq!(GitObject::resolve_hash(temp_dir, "557db")?, full);
+  let ambiguous = GitObject::resolve_hash(temp_dir, "557d").unwrap_err();
+  assert_eq!(
+    ambiguous.to_string(),
+    "error: short object ID 557d is ambiguous\n\
+     hint: The candidates are:\n\
+     hint:   557d000000000000000000000000000000000000 blob\n\
+     hint:   557db03de997c86a4a028e1ebd3a1ceb225be238 blob"
+  );
+  Ok(())
+}
+
+#[test]
+pub fn test_resolve_hash_in_pack() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  let name = "pack-9c06564c4d74ed54ef27e9227cda5ae5ba473982";
+  std::fs::create_dir_all(temp_dir.join(".git/objects/pack"))?;
+  for ext in ["idx", "pack"] {
+    std::fs::copy(
+      format!("tests/data/{name}.{ext}"),
+      temp_dir.join(format!(".git/objects/pack/{name}.{ext}")),
+    )?;
+  }
+  assert_eq!(
+    GitObject::resolve_hash(temp_dir, "4f5a654")?,
+    "4f5a6549782cc9de977296c75ef34e5a8d2c5070"
+  );
+  // 5b2f864b... and 5bde44b6... share the "5b" fanout bucket.
+  assert_eq!(
+    GitObject::matching_hashes(temp_dir, "5b2f")?,
+    vec!["5b2f864b9c44517a8c30202a671e50173273c3ed"]
+  );
+  assert!(GitObject::read_object(temp_dir, "4f5a").is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_typed_objects_round_trip() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  let name = "pack-9c06564c4d74ed54ef27e9227cda5ae5ba473982";
+  std::fs::create_dir_all(temp_dir.join(".git/objects/pack"))?;
+  for ext in ["idx", "pack"] {
+    std::fs::copy(
+      format!("tests/data/{name}.{ext}"),
+      temp_dir.join(format!(".git/objects/pack/{name}.{ext}")),
+    )?;
+  }
+  let index = PackIndex::read(&temp_dir.join(format!(".git/objects/pack/{name}.idx")))?;
+  for oid in &index.oids {
+    let git_object = GitObject::read_object(temp_dir, &hex::encode(oid))?;
+    let content = git_object.content();
+    let serialized = match git_object.kind {
+      Kind::Blob => Blob::parse(content)?.serialize(),
+      Kind::Tree => Tree::parse(content)?.serialize(),
+      Kind::Commit => Commit::parse(content)?.serialize(),
+      Kind::Tag => Tag::parse(content)?.serialize(),
+    };
+    assert_eq!(serialized, content);
+    let rebuilt = GitObject::from_content(git_object.kind, &serialized);
+    assert_eq!(rebuilt.hash_bytes()?, *oid);
+  }
+
+  let tree = GitObject::read_object(temp_dir, "f7e2ffd419c25f7dce95ad36b10f524c18ea3df3")?;
+  let tree = Tree::parse(tree.content())?;
+  assert_eq!(
+    tree
+      .entries
+      .iter()
+      .map(|entry| (entry.mode.as_str(), &entry.name[..], entry.kind()))
+      .collect::<Vec<_>>(),
+    vec![
+      ("100644", &b"numbers.txt"[..], Kind::Blob),
+      ("40000", &b"src"[..], Kind::Tree)
+    ]
+  );
+  Ok(())
+}
+
+#[test]
+pub fn test_tag_round_trip() -> anyhow::Result<()> {
+  let content = b"object 4f5a6549782cc9de977296c75ef34e5a8d2c5070\n\
+type commit\n\
+tag v1.0\n\
+tagger Coder <coder@crafters.io> 1700000000 +0000\n\
+\n\
+Release 1.0\n\
+-----BEGIN PGP SIGNATURE-----\n\
+\n\
+iQEzBAABCAAdFiEEexample\n\
+-----END PGP SIGNATURE-----\n";
+  let tag = Tag::parse(content)?;
+  assert_eq!(tag.object, "4f5a6549782cc9de977296c75ef34e5a8d2c5070");
+  assert_eq!(tag.kind, Kind::Commit);
+  assert_eq!(tag.tag, "v1.0");
+  assert_eq!(
+    tag.tagger_str().as_deref(),
+    Some("Coder <coder@crafters.io> 1700000000 +0000")
+  );
+  assert!(tag.message.starts_with(b"Release 1.0\n-----BEGIN PGP"));
+  assert_eq!(tag.serialize(), content);
+
+  let untagged =
+    b"object 4f5a6549782cc9de977296c75ef34e5a8d2c5070\ntype commit\ntag old\n\nOld tag\n";
+  let tag = Tag::parse(untagged)?;
+  assert_eq!(tag.tagger, None);
+  assert_eq!(tag.serialize(), untagged);
+
+  assert!(Tag::parse(b"type commit\ntag v1.0\n\n").is_err());
+  assert!(Tag::parse(b"object 4f5a\ntype bogus\ntag v1.0\n\n").is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_typed_errors() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  std::fs::create_dir_all(temp_dir.join(".git/objects/55"))?;
+  let missing = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+  assert!(matches!(
+    GitObject::read_object(temp_dir, missing),
+    Err(Error::MissingObject(hash)) if hash == missing
+  ));
+  assert!(matches!(
+    GitObject::resolve_hash(temp_dir, "557d"),
+    Err(Error::MissingObject(_))
+  ));
+  assert!(matches!(
+    GitObject::resolve_hash(temp_dir, "zzzz"),
+    Err(Error::InvalidHash(_))
+  ));
+  assert!(matches!(
+    GitObject::read_object(temp_dir, "557d"),
+    Err(Error::InvalidHash(_))
+  ));
+  // Errors from revision parsing keep their variant through `command`.
+  let mut stdout = std::io::Cursor::new(Vec::new());
+  assert!(matches!(
+    git_starter_rust::command::cat_file(
+      "557d",
+      &mut stdout,
+      temp_dir,
+      git_starter_rust::command::CatFileMode::Type
+    ),
+    Err(Error::MissingObject(_))
+  ));
+
+  // A header without its terminating NUL.
+  let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+  std::io::Write::write_all(&mut encoder, b"blob 12")?;
+  std::fs::write(
+    temp_dir.join(".git/objects/55/7db03de997c86a4a028e1ebd3a1ceb225be238"),
+    encoder.finish()?,
+  )?;
+  assert!(matches!(
+    GitObject::read_object(temp_dir, missing),
+    Err(Error::CorruptObject { hash, .. }) if hash == missing
+  ));
+  let truncated = b"100644 a.txt\0abc";
+  let hash = GitObject::from_content(Kind::Tree, truncated).hash()?;
+  match Tree::parse(truncated) {
+    Err(Error::CorruptObject {
+      hash: corrupt,
+      reason,
+    }) => {
+      assert_eq!(corrupt, hash);
+      assert_eq!(reason, "malformed tree object: truncated entry 'a.txt'");
+    }
+    other => panic!("expected a corrupt object, got {other:?}"),
+  }
+
+  let work_tree = temp_dir.join("work");
+  assert!(matches!(
+    GitObject::build_worktree_entry(&work_tree.join("gone")),
+    Err(Error::Io { path, .. }) if path == work_tree.join("gone")
+  ));
+  std::fs::create_dir_all(work_tree.join(".git"))?;
+  let status = std::process::Command::new("mkfifo")
+    .arg(work_tree.join("pipe"))
+    .status()?;
+  assert!(status.success());
+  assert!(matches!(
+    GitObject::build_tree_object(&work_tree),
+    Err(Error::UnsupportedMode { path, .. }) if path == work_tree.join("pipe")
+  ));
+  Ok(())
+}
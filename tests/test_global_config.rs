@@ -0,0 +1,39 @@
+use std::io::Cursor;
+
+use git_starter_rust::command::{check_ignore, init};
+use tempdir::TempDir;
+
+// The global config is found through the environment, so this lives in its
+// own test binary rather than racing other tests for `HOME`.
+#[test]
+pub fn test_global_excludes_file() -> anyhow::Result<()> {
+  let home = TempDir::new("test_global_config_home")?;
+  std::env::set_var("HOME", home.path());
+  std::env::remove_var("XDG_CONFIG_HOME");
+  std::env::remove_var("GIT_CONFIG_GLOBAL");
+  std::fs::write(home.path().join("global-ignore"), "*.global\n")?;
+  std::fs::write(
+    home.path().join(".gitconfig"),
+    "[core]\n\texcludesFile = ~/global-ignore\n",
+  )?;
+
+  let temp_dir = TempDir::new("test_global_config")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let paths = ["a.global".to_string(), "a.local".to_string()];
+  let mut stdout = Cursor::new(Vec::new());
+  assert!(check_ignore(&paths, &mut stdout, temp_dir, false, false)?);
+  assert_eq!(String::from_utf8(stdout.into_inner())?, "a.global\n");
+
+  // The repository's own setting takes precedence.
+  std::fs::write(temp_dir.join("local-ignore"), "*.local\n")?;
+  let local = temp_dir.join("local-ignore");
+  std::fs::write(
+    temp_dir.join(".git/config"),
+    format!("[core]\n\texcludesFile = {}\n", local.display()),
+  )?;
+  let mut stdout = Cursor::new(Vec::new());
+  assert!(check_ignore(&paths, &mut stdout, temp_dir, false, false)?);
+  assert_eq!(String::from_utf8(stdout.into_inner())?, "a.local\n");
+  Ok(())
+}
@@ -0,0 +1,191 @@
+use std::io::Cursor;
+
+use git_starter_rust::{
+  command::init,
+  common::{GitObject, Tree},
+  index::{Index, IndexEntry},
+};
+use tempdir::TempDir;
+
+fn oid(hash: &str) -> anyhow::Result<[u8; 20]> {
+  Ok(hex::decode(hash)?.as_slice().try_into()?)
+}
+
+#[test]
+pub fn test_index_round_trip() -> anyhow::Result<()> {
+  // Both files were written by git: v2 after `git add .`, v3 after a further
+  // `git add -N added.txt`.
+  let bytes = std::fs::read("tests/data/index-v2")?;
+  let index = Index::parse(&bytes)?;
+  assert_eq!(index.version, 2);
+  let listing = index
+    .entries()
+    .iter()
+    .map(|entry| format!("{:o} {} {}", entry.mode, entry.hash(), entry.path))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    listing,
+    [
+      "100644 45b983be36b73c0788dc9cbcb76cbb80fc7bb057 README.md",
+      "120000 42061c01a1c70097d1e4579f29a5adf40abdec95 link",
+      "100755 1a2485251c33a70432394c93fb89330ef214bfc9 run.sh",
+      "100644 f328e4d9d04c31d0d70d16d21a07d1613be9d577 src/main.rs",
+      "100644 587be6b4c3f93f93c489c0111bba5596147a26cb src/nested/deep.txt",
+    ]
+  );
+  let readme = index.get("README.md").unwrap();
+  assert_eq!(readme.stat.size, 3);
+  assert_ne!(readme.stat.mtime, 0);
+  assert_eq!(&index.extensions[0].signature, b"TREE");
+  assert_eq!(index.serialize()?, bytes);
+
+  let bytes = std::fs::read("tests/data/index-v3")?;
+  let index = Index::parse(&bytes)?;
+  assert_eq!(index.version, 3);
+  let added = index.get("added.txt").unwrap();
+  assert!(added.intent_to_add);
+  assert!(!added.skip_worktree);
+  assert_eq!(index.serialize()?, bytes);
+
+  let mut corrupt = bytes.clone();
+  corrupt[100] ^= 1;
+  assert!(Index::parse(&corrupt).is_err());
+  assert!(Index::parse(&bytes[..bytes.len() - 1]).is_err());
+
+  // A zeroed (skipHash) checksum is not verified, so a huge entry count in
+  // a short file must fail on the missing entries rather than allocate.
+  let mut huge = b"DIRC".to_vec();
+  huge.extend_from_slice(&2u32.to_be_bytes());
+  huge.extend_from_slice(&u32::MAX.to_be_bytes());
+  huge.extend_from_slice(&[0; 20]);
+  assert!(Index::parse(&huge).is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_index_edit() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_index")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let mut index = Index::read(temp_dir)?;
+  assert!(index.entries().is_empty());
+
+  let blob = oid("557db03de997c86a4a028e1ebd3a1ceb225be238")?;
+  let other = oid("45b983be36b73c0788dc9cbcb76cbb80fc7bb057")?;
+  index.insert(IndexEntry::new("src/main.rs", 0o100644, blob));
+  index.insert(IndexEntry::new("src-file", 0o100644, blob));
+  index.insert(IndexEntry::new("README.md", 0o100644, blob));
+  index.insert(IndexEntry::new("README.md", 0o100755, other));
+  for stage in 1..=3 {
+    index.insert(IndexEntry {
+      stage,
+      ..IndexEntry::new("conflict.txt", 0o100644, blob)
+    });
+  }
+  let paths = |index: &Index| {
+    index
+      .entries()
+      .iter()
+      .map(|entry| format!("{} {}", entry.path, entry.stage))
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(
+    paths(&index),
+    [
+      "README.md 0",
+      "conflict.txt 1",
+      "conflict.txt 2",
+      "conflict.txt 3",
+      "src-file 0",
+      "src/main.rs 0"
+    ]
+  );
+  assert_eq!(index.get("README.md").unwrap().oid, other);
+  assert!(index.get("conflict.txt").is_none());
+
+  index.insert(IndexEntry::new("conflict.txt", 0o100644, other));
+  assert!(index.remove("src-file"));
+  assert!(!index.remove("src-file"));
+  assert_eq!(
+    paths(&index),
+    ["README.md 0", "conflict.txt 0", "src/main.rs 0"]
+  );
+
+  index.insert(IndexEntry {
+    skip_worktree: true,
+    ..IndexEntry::new("sparse.txt", 0o100644, blob)
+  });
+  let lock = Index::lock(temp_dir)?;
+  assert!(Index::lock(temp_dir).is_err());
+  index.write(lock)?;
+  assert!(!temp_dir.join(".git/index.lock").exists());
+  let read_back = Index::read(temp_dir)?;
+  assert_eq!(read_back.version, 3);
+  assert_eq!(read_back.entries(), index.entries());
+  assert!(read_back.get("sparse.txt").unwrap().skip_worktree);
+  Ok(())
+}
+
+#[test]
+pub fn test_index_invalidates_tree_cache() -> anyhow::Result<()> {
+  let mut index = Index::parse(&std::fs::read("tests/data/index-v2")?)?;
+  let entry = index.get("src/nested/deep.txt").unwrap().clone();
+  index.insert(entry);
+  let cache_tree = index.cache_tree()?.unwrap();
+  assert!(cache_tree.cached.is_none());
+  let (name, src) = &cache_tree.subtrees[0];
+  assert_eq!(name, "src");
+  assert!(src.cached.is_none());
+  assert!(src.subtrees[0].1.cached.is_none());
+
+  // Only the path's own directories are touched.
+  let mut index = Index::parse(&std::fs::read("tests/data/index-v2")?)?;
+  index.remove("README.md");
+  let cache_tree = index.cache_tree()?.unwrap();
+  assert!(cache_tree.cached.is_none());
+  assert!(cache_tree.subtrees[0].1.cached.is_some());
+  Ok(())
+}
+
+#[test]
+pub fn test_index_write_tree() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_index_write_tree")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+
+  // Rebuilding from scratch reproduces the cache git wrote.
+  let from_git = Index::parse(&std::fs::read("tests/data/index-v2")?)?;
+  let mut index = from_git.clone();
+  index.extensions.clear();
+  let root = index.write_tree(temp_dir)?;
+  assert_eq!(index.extensions, from_git.extensions);
+  assert_eq!(
+    root,
+    hex::encode(from_git.cache_tree()?.unwrap().cached.unwrap().1)
+  );
+
+  // A valid subtree is taken from the cache rather than rehashed.
+  let mut cache_tree = from_git.cache_tree()?.unwrap();
+  cache_tree.cached = None;
+  let bogus = oid("0123456789abcdef0123456789abcdef01234567")?;
+  cache_tree.subtrees[0].1.cached = Some((2, bogus));
+  index.set_cache_tree(&cache_tree);
+  let root = index.write_tree(temp_dir)?;
+  let tree = Tree::parse(GitObject::read_object(temp_dir, &root)?.content())?;
+  let src = tree
+    .entries
+    .iter()
+    .find(|entry| entry.name == b"src")
+    .unwrap();
+  assert_eq!(src.oid, bogus);
+
+  // Unmerged entries cannot be written.
+  let mut index = from_git.clone();
+  index.insert(IndexEntry {
+    stage: 2,
+    ..IndexEntry::new("README.md", 0o100644, bogus)
+  });
+  let err = index.write_tree(temp_dir).unwrap_err();
+  assert!(err.to_string().starts_with("error: README.md: unmerged"));
+  Ok(())
+}
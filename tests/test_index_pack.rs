@@ -0,0 +1,28 @@
+use std::io::Cursor;
+
+use git_starter_rust::command::index_pack;
+use tempdir::TempDir;
+
+#[test]
+pub fn test_index_pack() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_index_pack")?;
+  let temp_dir = temp_dir.path();
+  for name in [
+    "pack-9c06564c4d74ed54ef27e9227cda5ae5ba473982",
+    "pack-66d6a6e7cdd424ca45bd146d9169c9d7fff28e2d",
+  ] {
+    let pack_path = temp_dir.join(format!("{name}.pack"));
+    std::fs::copy(format!("tests/data/{name}.pack"), &pack_path)?;
+    let mut stdout = Cursor::new(Vec::<u8>::new());
+    index_pack(&pack_path, &mut stdout)?;
+    assert_eq!(
+      String::from_utf8(stdout.into_inner())?,
+      format!("{}\n", name.trim_start_matches("pack-"))
+    );
+    assert_eq!(
+      std::fs::read(temp_dir.join(format!("{name}.idx")))?,
+      std::fs::read(format!("tests/data/{name}.idx"))?
+    );
+  }
+  Ok(())
+}
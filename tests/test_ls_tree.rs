@@ -1,25 +1,29 @@
 use std::io::Cursor;
 
 use anyhow::Ok;
-use git_starter_rust::command::ls_tree;
+use git_starter_rust::command::{ls_tree, LsTreeOptions};
 use tempdir::TempDir;
 
 #[test]
 pub fn test_ls_tree() -> anyhow::Result<()> {
   let temp_dir = TempDir::new("tmp_test_dir")?;
   let temp_dir = temp_dir.path();
-  let tree_object = include_bytes!("data/6ae106b480544288797befd3a2debb1f79f087");
-  std::fs::create_dir_all(temp_dir.join(".git/objects/6a"))?;
+  let tree_object = include_bytes!("data/d66ae106b480544288797befd3a2debb1f79f087");
+  std::fs::create_dir_all(temp_dir.join(".git/objects/d6"))?;
   std::fs::write(
-    temp_dir.join(".git/objects/6a/e106b480544288797befd3a2debb1f79f087"),
+    temp_dir.join(".git/objects/d6/6ae106b480544288797befd3a2debb1f79f087"),
     tree_object,
   )?;
   let mut stdout = Cursor::new(Vec::<u8>::new());
   ls_tree(
-    "6ae106b480544288797befd3a2debb1f79f087",
+    "d66ae106b480544288797befd3a2debb1f79f087",
     &mut stdout,
     temp_dir,
-    true,
+    &LsTreeOptions {
+      name_only: true,
+      ..Default::default()
+    },
+    &[],
   )?;
   let expected = r#".gitattributes
 .gitignore
@@ -33,3 +37,137 @@ your_git.sh
   assert_eq!(String::from_utf8(stdout.into_inner()).unwrap(), expected);
   Ok(())
 }
+
+fn install_pack(repo: &std::path::Path) -> anyhow::Result<()> {
+  let name = "pack-9c06564c4d74ed54ef27e9227cda5ae5ba473982";
+  let pack_dir = repo.join(".git/objects/pack");
+  std::fs::create_dir_all(&pack_dir)?;
+  for ext in ["idx", "pack"] {
+    std::fs::copy(
+      format!("tests/data/{name}.{ext}"),
+      pack_dir.join(format!("{name}.{ext}")),
+    )?;
+  }
+  Ok(())
+}
+
+#[test]
+pub fn test_ls_tree_default_format() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  install_pack(temp_dir)?;
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  // A commit is peeled to its tree.
+  ls_tree(
+    "4f5a6549782cc9de977296c75ef34e5a8d2c5070",
+    &mut stdout,
+    temp_dir,
+    &LsTreeOptions::default(),
+    &[],
+  )?;
+  let expected = "100644 blob 91848999adf4c496338019f24b0c975f7be4f349\tnumbers.txt\n\
+                  040000 tree 9dde84af63d0706beb4c530c1fb378a4518807ed\tsrc\n";
+  assert_eq!(String::from_utf8(stdout.into_inner()).unwrap(), expected);
+  Ok(())
+}
+
+#[test]
+pub fn test_ls_tree_recursive_long_and_paths() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  install_pack(temp_dir)?;
+  let tree = "f7e2ffd419c25f7dce95ad36b10f524c18ea3df3";
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  let options = LsTreeOptions {
+    recursive: true,
+    show_trees: true,
+    long: true,
+    ..Default::default()
+  };
+  ls_tree(tree, &mut stdout, temp_dir, &options, &[])?;
+  let expected = "100644 blob 91848999adf4c496338019f24b0c975f7be4f349    3550\tnumbers.txt\n\
+                  040000 tree 9dde84af63d0706beb4c530c1fb378a4518807ed       -\tsrc\n\
+                  100644 blob 7148c66d56c6825c94927d0e9bccf628fdf40ce8      30\tsrc/main.rs\n";
+  assert_eq!(String::from_utf8(stdout.into_inner()).unwrap(), expected);
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  let options = LsTreeOptions {
+    name_only: true,
+    ..Default::default()
+  };
+  ls_tree(tree, &mut stdout, temp_dir, &options, &["src/".to_string()])?;
+  ls_tree(tree, &mut stdout, temp_dir, &options, &["src".to_string()])?;
+  let only_trees = LsTreeOptions {
+    only_trees: true,
+    ..Default::default()
+  };
+  ls_tree(tree, &mut stdout, temp_dir, &only_trees, &[])?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner()).unwrap(),
+    "src/main.rs\nsrc\n040000 tree 9dde84af63d0706beb4c530c1fb378a4518807ed\tsrc\n"
+  );
+  Ok(())
+}
+
+#[test]
+pub fn test_ls_tree_rejects_blob() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  install_pack(temp_dir)?;
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  let result = ls_tree(
+    "91848999adf4c496338019f24b0c975f7be4f349",
+    &mut stdout,
+    temp_dir,
+    &LsTreeOptions::default(),
+    &[],
+  );
+  assert!(result.is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_ls_tree_quotes_names() -> anyhow::Result<()> {
+  use git_starter_rust::common::{GitObject, Kind, Tree, TreeEntry};
+  let temp_dir = TempDir::new("tmp_test_dir")?;
+  let temp_dir = temp_dir.path();
+  std::fs::create_dir_all(temp_dir.join(".git/objects"))?;
+  let blob = GitObject::from_content(Kind::Blob, b"x");
+  blob.write(temp_dir)?;
+  let entries = [
+    &b"a\"b"[..],
+    b"f\xff",
+    b"plain",
+    b"t\tc",
+    "\u{e9}".as_bytes(),
+  ]
+  .iter()
+  .map(|name| TreeEntry {
+    mode: "100644".to_string(),
+    name: name.to_vec(),
+    oid: blob.hash_bytes().unwrap(),
+  })
+  .collect();
+  let content = Tree { entries }.serialize();
+  // Names are bytes, so a non-UTF-8 name survives a round trip.
+  assert_eq!(Tree::parse(&content)?.serialize(), content);
+  let tree = GitObject::from_content(Kind::Tree, &content);
+  tree.write(temp_dir)?;
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  ls_tree(
+    &tree.hash()?,
+    &mut stdout,
+    temp_dir,
+    &LsTreeOptions {
+      name_only: true,
+      ..Default::default()
+    },
+    &[],
+  )?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "\"a\\\"b\"\n\"f\\377\"\nplain\n\"t\\tc\"\n\"\\303\\251\"\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,42 @@
+use std::io::Cursor;
+
+use git_starter_rust::{
+  command::{hash_object, init, mktag},
+  common::{GitObject, Kind},
+  error::Error,
+};
+use tempdir::TempDir;
+
+#[test]
+pub fn test_mktag() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_mktag")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let file = temp_dir.join("test.md");
+  std::fs::write(&file, b"Hello World\n")?;
+  hash_object(&file, &mut Cursor::new(Vec::new()), temp_dir, true)?;
+
+  let content = "object 557db03de997c86a4a028e1ebd3a1ceb225be238\ntype blob\ntag v1.0\n\
+                 tagger Coder <coder@crafters.io> 1700000000 +0000\n\nfirst release\n";
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  mktag(&mut content.as_bytes(), &mut stdout, temp_dir)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "2e9f7cac8e6deb928fa63b0d253d32d34eb659f8\n"
+  );
+  let tag = GitObject::read_object(temp_dir, "2e9f7cac8e6deb928fa63b0d253d32d34eb659f8")?;
+  assert_eq!(tag.kind, Kind::Tag);
+  assert_eq!(tag.content(), content.as_bytes());
+
+  let wrong_type = content.replace("type blob", "type tree");
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  assert!(mktag(&mut wrong_type.as_bytes(), &mut stdout, temp_dir).is_err());
+  let no_tagger = content.replace("tagger Coder <coder@crafters.io> 1700000000 +0000\n", "");
+  assert!(mktag(&mut no_tagger.as_bytes(), &mut stdout, temp_dir).is_err());
+  let no_type = content.replace("type blob\n", "");
+  assert!(matches!(
+    mktag(&mut no_type.as_bytes(), &mut stdout, temp_dir),
+    Err(Error::CorruptObject { .. })
+  ));
+  Ok(())
+}
@@ -0,0 +1,254 @@
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+use flate2::{write::ZlibEncoder, Compression};
+use git_starter_rust::{
+  command::{commit_tree, ls_tree, LsTreeOptions},
+  common::{GitObject, Kind},
+  error::Error,
+  pack::{apply_delta, create_delta, scan_pack, write_index, IndexEntry, Pack, PackIndex},
+};
+use tempdir::TempDir;
+
+fn install_pack(repo: &Path, name: &str) -> anyhow::Result<()> {
+  let pack_dir = repo.join(".git/objects/pack");
+  std::fs::create_dir_all(&pack_dir)?;
+  for ext in ["idx", "pack"] {
+    std::fs::copy(
+      Path::new("tests/data").join(format!("{name}.{ext}")),
+      pack_dir.join(format!("{name}.{ext}")),
+    )?;
+  }
+  Ok(())
+}
+
+/// Installs a pack holding the single entry `header` followed by `data`,
+/// indexed under `oid`.
+fn install_single_entry_pack(
+  repo: &Path,
+  oid: [u8; 20],
+  header: &[u8],
+  data: &[u8],
+) -> anyhow::Result<()> {
+  let mut pack = b"PACK".to_vec();
+  pack.extend_from_slice(&2u32.to_be_bytes());
+  pack.extend_from_slice(&1u32.to_be_bytes());
+  pack.extend_from_slice(header);
+  let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(data)?;
+  pack.extend_from_slice(&encoder.finish()?);
+  let checksum = GitObject::_hash(&pack)?;
+  pack.extend_from_slice(&checksum);
+  let index = write_index(
+    &[IndexEntry {
+      oid,
+      crc: 0,
+      offset: 12,
+    }],
+    &checksum,
+  )?;
+
+  let pack_dir = repo.join(".git/objects/pack");
+  std::fs::create_dir_all(&pack_dir)?;
+  std::fs::write(pack_dir.join("pack-crafted.pack"), pack)?;
+  std::fs::write(pack_dir.join("pack-crafted.idx"), index)?;
+  Ok(())
+}
+
+#[test]
+pub fn test_read_packed_ofs_delta() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_pack")?;
+  let temp_dir = temp_dir.path();
+  install_pack(temp_dir, "pack-9c06564c4d74ed54ef27e9227cda5ae5ba473982")?;
+
+  let base = GitObject::read_object(temp_dir, "91848999adf4c496338019f24b0c975f7be4f349")?;
+  assert_eq!(base.kind, Kind::Blob);
+  assert!(base.content().ends_with(b"line number 228\nversion 4\n"));
+
+  let delta = GitObject::read_object(temp_dir, "5bde44b6271e2eee8929a4a10c03cb78a726ac79")?;
+  assert_eq!(delta.kind, Kind::Blob);
+  assert!(delta.content().ends_with(b"line number 207\nversion 1\n"));
+  assert_eq!(delta.hash()?, "5bde44b6271e2eee8929a4a10c03cb78a726ac79");
+
+  let commit = GitObject::read_object(temp_dir, "4f5a6549782cc9de977296c75ef34e5a8d2c5070")?;
+  assert_eq!(commit.kind, Kind::Commit);
+  assert!(commit
+    .content()
+    .starts_with(b"tree f7e2ffd419c25f7dce95ad36b10f524c18ea3df3\n"));
+  Ok(())
+}
+
+#[test]
+pub fn test_read_packed_ref_delta() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_pack")?;
+  let temp_dir = temp_dir.path();
+  install_pack(temp_dir, "pack-66d6a6e7cdd424ca45bd146d9169c9d7fff28e2d")?;
+
+  let delta = GitObject::read_object(temp_dir, "8feffba17525cd027c5e7b9c62801ec2ecb28159")?;
+  assert_eq!(delta.kind, Kind::Blob);
+  assert_eq!(delta.hash()?, "8feffba17525cd027c5e7b9c62801ec2ecb28159");
+  Ok(())
+}
+
+#[test]
+pub fn test_read_packed_delta_cycle() -> anyhow::Result<()> {
+  let oid = [0x11; 20];
+  let hash = "1111111111111111111111111111111111111111";
+  // An empty delta whose base is itself: OFS_DELTA at distance 0.
+  let temp_dir = TempDir::new("test_pack")?;
+  install_single_entry_pack(temp_dir.path(), oid, &[0x62, 0x00], &[0, 0])?;
+  assert!(GitObject::read_object(temp_dir.path(), hash).is_err());
+
+  // The same through a REF_DELTA naming its own object id.
+  let temp_dir = TempDir::new("test_pack")?;
+  let header = [&[0x72][..], &oid].concat();
+  install_single_entry_pack(temp_dir.path(), oid, &header, &[0, 0])?;
+  assert!(GitObject::read_object(temp_dir.path(), hash).is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_read_packed_corrupt_entry() -> anyhow::Result<()> {
+  let oid = [0x11; 20];
+  let hash = "1111111111111111111111111111111111111111";
+  let is_corrupt = |repo: &Path| {
+    matches!(
+      GitObject::read_object(repo, hash),
+      Err(Error::CorruptObject { hash: corrupt, .. }) if corrupt == hash
+    )
+  };
+  let pack_path = |repo: &Path| repo.join(".git/objects/pack/pack-crafted.pack");
+
+  // A blob whose zlib stream is garbage.
+  let temp_dir = TempDir::new("test_pack")?;
+  install_single_entry_pack(temp_dir.path(), oid, &[0x33], b"abc")?;
+  let mut pack = std::fs::read(pack_path(temp_dir.path()))?;
+  pack[13..16].copy_from_slice(b"bad");
+  std::fs::write(pack_path(temp_dir.path()), pack)?;
+  assert!(is_corrupt(temp_dir.path()));
+
+  // A blob cut off in the middle of its entry.
+  let temp_dir = TempDir::new("test_pack")?;
+  install_single_entry_pack(temp_dir.path(), oid, &[0x33], b"abc")?;
+  let mut pack = std::fs::read(pack_path(temp_dir.path()))?;
+  pack.truncate(15);
+  std::fs::write(pack_path(temp_dir.path()), pack)?;
+  assert!(is_corrupt(temp_dir.path()));
+
+  // A REF_DELTA whose delta expects a larger base than the loose one it names.
+  let temp_dir = TempDir::new("test_pack")?;
+  let base = GitObject::from_content(Kind::Blob, b"abc");
+  base.write(temp_dir.path())?;
+  let header = [&[0x72][..], &base.hash_bytes()?].concat();
+  install_single_entry_pack(temp_dir.path(), oid, &header, &[5, 0])?;
+  assert!(is_corrupt(temp_dir.path()));
+
+  // Sizes read from the pack are not trusted for allocations: a blob
+  // claiming 2^50 bytes and a delta claiming a 2^49 byte result.
+  let temp_dir = TempDir::new("test_pack")?;
+  let header = [0xb0, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x10];
+  install_single_entry_pack(temp_dir.path(), oid, &header, b"abc")?;
+  assert!(is_corrupt(temp_dir.path()));
+
+  let temp_dir = TempDir::new("test_pack")?;
+  base.write(temp_dir.path())?;
+  let header = [&[0x79][..], &base.hash_bytes()?].concat();
+  let delta = [3, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+  install_single_entry_pack(temp_dir.path(), oid, &header, &delta)?;
+  assert!(is_corrupt(temp_dir.path()));
+  Ok(())
+}
+
+#[test]
+pub fn test_pack_index_rejects_bad_fanout() -> anyhow::Result<()> {
+  let entries = [[0x11; 20], [0x22; 20]].map(|oid| IndexEntry {
+    oid,
+    crc: 0,
+    offset: 12,
+  });
+  let index = write_index(&entries, &[0; 20])?;
+  assert_eq!(PackIndex::parse(&index)?.find(&[0x22; 20]), Some(12));
+
+  // Make the fanout drop back to zero after the bucket of 0x11.
+  let mut index = index[..index.len() - 20].to_vec();
+  index[8 + 0x12 * 4..8 + 0x13 * 4].copy_from_slice(&0u32.to_be_bytes());
+  let checksum = GitObject::_hash(&index)?;
+  index.extend_from_slice(&checksum);
+  assert!(PackIndex::parse(&index).is_err());
+
+  // An offset past the end of the pack it indexes.
+  let temp_dir = TempDir::new("test_pack")?;
+  install_single_entry_pack(temp_dir.path(), [0x11; 20], &[0x33], b"abc")?;
+  let index = write_index(
+    &[IndexEntry {
+      oid: [0x11; 20],
+      crc: 0,
+      offset: 4096,
+    }],
+    &[0; 20],
+  )?;
+  let idx_path = temp_dir.path().join(".git/objects/pack/pack-crafted.idx");
+  std::fs::write(&idx_path, index)?;
+  assert!(Pack::open(&idx_path).is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_scan_pack_huge_object_count() -> anyhow::Result<()> {
+  let mut pack = b"PACK".to_vec();
+  pack.extend_from_slice(&2u32.to_be_bytes());
+  pack.extend_from_slice(&u32::MAX.to_be_bytes());
+  let checksum = GitObject::_hash(&pack)?;
+  pack.extend_from_slice(&checksum);
+  assert!(scan_pack(&pack).is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_commands_on_packed_objects() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_pack")?;
+  let temp_dir = temp_dir.path();
+  install_pack(temp_dir, "pack-9c06564c4d74ed54ef27e9227cda5ae5ba473982")?;
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  ls_tree(
+    "f7e2ffd419c25f7dce95ad36b10f524c18ea3df3",
+    &mut stdout,
+    temp_dir,
+    &LsTreeOptions {
+      name_only: true,
+      ..Default::default()
+    },
+    &[],
+  )?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner()).unwrap(),
+    "numbers.txt\nsrc\n"
+  );
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  commit_tree(
+    "f7e2ffd419c25f7dce95ad36b10f524c18ea3df3".to_string(),
+    &mut stdout,
+    temp_dir,
+    "from pack",
+    None,
+  )?;
+  assert_eq!(String::from_utf8(stdout.into_inner()).unwrap().len(), 41);
+  Ok(())
+}
+
+#[test]
+pub fn test_delta_round_trip() -> anyhow::Result<()> {
+  let base = (0..400)
+    .map(|i| format!("line number {i}\n"))
+    .collect::<String>();
+  let target = base.replace("line number 123\n", "a changed line\n") + "trailer\n";
+  let delta = create_delta(base.as_bytes(), target.as_bytes());
+  assert!(delta.len() < target.len() / 10);
+  assert_eq!(apply_delta(base.as_bytes(), &delta)?, target.as_bytes());
+
+  let unrelated = create_delta(b"abc", target.as_bytes());
+  assert_eq!(apply_delta(b"abc", &unrelated)?, target.as_bytes());
+  Ok(())
+}
@@ -0,0 +1,92 @@
+use std::io::Cursor;
+
+use git_starter_rust::{
+  command::{init, pack_refs, tag, update_ref},
+  refs::{self, PackedRef, Ref},
+};
+use tempdir::TempDir;
+
+mod common;
+use common::install_commit;
+
+#[test]
+pub fn test_pack_refs() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_pack_refs")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let commit = install_commit(temp_dir, "first", None)?;
+  update_ref("HEAD", Some(&commit), None, temp_dir, "", false, false)?;
+  update_ref(
+    "refs/tags/light",
+    Some(&commit),
+    None,
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+  tag("v1", temp_dir, true, Some("release".to_string()), &commit)?;
+  let annotated = refs::resolve_ref(temp_dir, "refs/tags/v1")?.unwrap();
+
+  pack_refs(temp_dir, false)?;
+  assert!(temp_dir.join(".git/refs/heads/master").is_file());
+  assert!(!temp_dir.join(".git/refs/tags/v1").exists());
+
+  pack_refs(temp_dir, true)?;
+  assert!(!temp_dir.join(".git/refs/heads/master").exists());
+  assert!(temp_dir.join(".git/refs/heads").is_dir());
+  assert_eq!(
+    std::fs::read_to_string(temp_dir.join(".git/packed-refs"))?,
+    format!(
+      "# pack-refs with: peeled fully-peeled sorted \n\
+       {commit} refs/heads/master\n\
+       {commit} refs/tags/light\n\
+       {annotated} refs/tags/v1\n\
+       ^{commit}\n"
+    )
+  );
+  assert_eq!(refs::head(temp_dir)?, Some(commit.clone()));
+  assert_eq!(
+    refs::read_ref(temp_dir, "refs/tags/v1")?,
+    Some(Ref::Direct(annotated.clone()))
+  );
+
+  update_ref("refs/tags/light", None, None, temp_dir, "", true, false)?;
+  assert_eq!(refs::read_ref(temp_dir, "refs/tags/light")?, None);
+  assert_eq!(refs::read_packed_refs(temp_dir)?.len(), 2);
+  Ok(())
+}
+
+#[test]
+pub fn test_read_packed_refs_without_header() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_pack_refs")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let a = "a".repeat(40);
+  let b = "b".repeat(40);
+  std::fs::write(
+    temp_dir.join(".git/packed-refs"),
+    format!("{b} refs/tags/z\n^{a}\n{a} refs/heads/main\n"),
+  )?;
+  assert_eq!(
+    refs::read_packed_refs(temp_dir)?,
+    vec![
+      PackedRef {
+        name: "refs/heads/main".to_string(),
+        hash: a.clone(),
+        peeled: None,
+      },
+      PackedRef {
+        name: "refs/tags/z".to_string(),
+        hash: b.clone(),
+        peeled: Some(a.clone()),
+      },
+    ]
+  );
+  std::fs::write(temp_dir.join(".git/HEAD"), "ref: refs/heads/main\n")?;
+  assert_eq!(refs::head(temp_dir)?, Some(a));
+
+  std::fs::write(temp_dir.join(".git/packed-refs"), "^{b}\n")?;
+  assert!(refs::read_packed_refs(temp_dir).is_err());
+  Ok(())
+}
@@ -0,0 +1,133 @@
+use std::io::Cursor;
+
+use git_starter_rust::{
+  command::{init, reflog_delete, reflog_expire, reflog_show, update_ref},
+  reflog::{self, ReflogEntry},
+  refs::ZERO_HASH,
+  revision,
+};
+use tempdir::TempDir;
+
+mod common;
+use common::install_commit;
+
+#[test]
+pub fn test_reflog() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_reflog")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let first = install_commit(temp_dir, "first", None)?;
+  let second = install_commit(temp_dir, "second", None)?;
+  update_ref(
+    "HEAD",
+    Some(&first),
+    None,
+    temp_dir,
+    "commit (initial): first",
+    false,
+    false,
+  )?;
+  update_ref(
+    "HEAD",
+    Some(&second),
+    None,
+    temp_dir,
+    "commit: second",
+    false,
+    false,
+  )?;
+  update_ref(
+    "refs/heads/master",
+    Some(&first),
+    None,
+    temp_dir,
+    "reset:\nback",
+    false,
+    false,
+  )?;
+  update_ref(
+    "refs/tags/v1",
+    Some(&first),
+    None,
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+
+  let entries = reflog::read_reflog(temp_dir, "refs/heads/master")?;
+  assert_eq!(entries, reflog::read_reflog(temp_dir, "HEAD")?);
+  assert_eq!(
+    entries
+      .iter()
+      .map(|entry| (
+        entry.old.as_str(),
+        entry.new.as_str(),
+        entry.message.as_str()
+      ))
+      .collect::<Vec<_>>(),
+    vec![
+      (ZERO_HASH, first.as_str(), "commit (initial): first"),
+      (first.as_str(), second.as_str(), "commit: second"),
+      (second.as_str(), first.as_str(), "reset: back"),
+    ]
+  );
+  assert_eq!(entries[0].committer, "Coder <coder@crafters.io>");
+  assert!(!temp_dir.join(".git/logs/refs/tags/v1").exists());
+
+  let mut stdout = Cursor::new(Vec::new());
+  reflog_show("master", &mut stdout, temp_dir)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    format!(
+      "{} master@{{0}}: reset: back\n{} master@{{1}}: commit: second\n{} master@{{2}}: commit (initial): first\n",
+      &first[..7],
+      &second[..7],
+      &first[..7]
+    )
+  );
+  assert_eq!(revision::resolve(temp_dir, "HEAD@{1}")?, second);
+  assert_eq!(revision::resolve(temp_dir, "@{2}")?, first);
+  assert!(revision::resolve(temp_dir, "master@{3}").is_err());
+
+  reflog_delete(&["master@{1}".to_string()], temp_dir)?;
+  assert_eq!(reflog::read_reflog(temp_dir, "refs/heads/master")?.len(), 2);
+  assert_eq!(revision::resolve(temp_dir, "master@{1}")?, first);
+
+  reflog_expire(&["HEAD".to_string()], temp_dir, Some("never"), false)?;
+  assert_eq!(reflog::read_reflog(temp_dir, "HEAD")?.len(), 3);
+  reflog_expire(&[], temp_dir, Some("now"), true)?;
+  assert!(reflog::read_reflog(temp_dir, "HEAD")?.is_empty());
+  assert!(reflog::read_reflog(temp_dir, "refs/heads/master")?.is_empty());
+
+  update_ref("refs/heads/master", None, None, temp_dir, "", true, false)?;
+  assert!(!temp_dir.join(".git/logs/refs/heads/master").exists());
+  Ok(())
+}
+
+#[test]
+pub fn test_reflog_entry_and_expiry() -> anyhow::Result<()> {
+  let line = format!(
+    "{ZERO_HASH} {} A U Thor <a@b.c> 1700000000 -0230",
+    "1".repeat(40)
+  );
+  let entry = ReflogEntry::parse(&line)?;
+  assert_eq!(entry.committer, "A U Thor <a@b.c>");
+  assert_eq!(entry.timestamp, 1700000000);
+  assert_eq!(entry.tz, "-0230");
+  assert_eq!(entry.message, "");
+  assert_eq!(entry.serialize(), format!("{line}\n"));
+  assert!(ReflogEntry::parse("not a reflog line").is_err());
+
+  let now = 1_000_000_000;
+  assert_eq!(reflog::parse_expiry("90.days.ago", now)?, now - 90 * 86400);
+  assert_eq!(reflog::parse_expiry("2 weeks ago", now)?, now - 14 * 86400);
+  assert_eq!(reflog::parse_expiry("1.hour.ago", now)?, now - 3600);
+  assert_eq!(reflog::parse_expiry("12345", now)?, 12345);
+  assert_eq!(reflog::parse_expiry("never", now)?, i64::MIN);
+  assert_eq!(reflog::parse_expiry("all", now)?, i64::MAX);
+  assert!(reflog::parse_expiry("yesterday-ish", now).is_err());
+  assert!(reflog::parse_expiry("99999999999999.weeks.ago", now).is_err());
+  assert!(reflog::parse_expiry("-9223372036854775807.seconds.ago", now).is_err());
+  Ok(())
+}
@@ -0,0 +1,91 @@
+use std::io::Cursor;
+
+use git_starter_rust::{
+  command::{init, repack},
+  common::{GitObject, Kind},
+  pack::PackOptions,
+};
+use tempdir::TempDir;
+
+#[test]
+pub fn test_repack() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_repack")?;
+  let temp_dir = temp_dir.path();
+  let mut stdout = Cursor::new(Vec::new());
+  init(temp_dir, &mut stdout)?;
+  let subdir = temp_dir.join("subdir");
+  std::fs::create_dir_all(&subdir)?;
+  std::fs::write(subdir.join("subdir_file.txt"), b"subdir file content\n")?;
+  std::fs::write(temp_dir.join("root_file.txt"), b"root file content\n")?;
+  let tree_object = GitObject::build_tree_object(temp_dir)?;
+  tree_object.write(temp_dir)?;
+  GitObject::build_tree_object(&subdir)?.write(temp_dir)?;
+  GitObject::build_file_object(&temp_dir.join("root_file.txt"))?.write(temp_dir)?;
+  GitObject::build_file_object(&subdir.join("subdir_file.txt"))?.write(temp_dir)?;
+  let hashes = GitObject::loose_object_hashes(temp_dir)?;
+  assert_eq!(hashes.len(), 4);
+
+  let mut stdout = Cursor::new(Vec::new());
+  repack(temp_dir, &mut stdout, true, &PackOptions::default())?;
+  let name = String::from_utf8(stdout.into_inner())?;
+  let name = name.trim_end();
+  let pack_dir = temp_dir.join(".git/objects/pack");
+  assert!(pack_dir.join(format!("pack-{name}.pack")).is_file());
+  assert!(pack_dir.join(format!("pack-{name}.idx")).is_file());
+  assert!(GitObject::loose_object_hashes(temp_dir)?.is_empty());
+
+  for hash in hashes {
+    assert_eq!(GitObject::read_object(temp_dir, &hash)?.hash()?, hash);
+  }
+  let tree = GitObject::read_object(temp_dir, &tree_object.hash()?)?;
+  assert_eq!(tree.kind, Kind::Tree);
+  assert_eq!(tree.data, tree_object.data);
+
+  let mut stdout = Cursor::new(Vec::new());
+  repack(temp_dir, &mut stdout, true, &PackOptions::default())?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "Nothing new to pack.\n"
+  );
+  Ok(())
+}
+
+fn repack_versions(options: &PackOptions) -> anyhow::Result<u64> {
+  let temp_dir = TempDir::new("test_repack")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let file = temp_dir.join("numbers.txt");
+  for version in 0..5 {
+    let content = (0..500)
+      .map(|i| {
+        format!(
+          "line {i} of version {}\n",
+          if i % 97 == 0 { version } else { 0 }
+        )
+      })
+      .collect::<String>()
+      + &format!("version {version}\n");
+    std::fs::write(&file, content)?;
+    GitObject::build_file_object(&file)?.write(temp_dir)?;
+  }
+  let hashes = GitObject::loose_object_hashes(temp_dir)?;
+  let mut stdout = Cursor::new(Vec::new());
+  repack(temp_dir, &mut stdout, true, options)?;
+  let name = String::from_utf8(stdout.into_inner())?;
+  let pack = temp_dir.join(format!(".git/objects/pack/pack-{}.pack", name.trim_end()));
+  for hash in hashes {
+    assert_eq!(GitObject::read_object(temp_dir, &hash)?.hash()?, hash);
+  }
+  Ok(std::fs::metadata(pack)?.len())
+}
+
+#[test]
+pub fn test_repack_deltifies_similar_blobs() -> anyhow::Result<()> {
+  let without_deltas = repack_versions(&PackOptions {
+    window: 0,
+    depth: 50,
+  })?;
+  let with_deltas = repack_versions(&PackOptions::default())?;
+  assert!(with_deltas * 2 < without_deltas);
+  Ok(())
+}
@@ -0,0 +1,161 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use git_starter_rust::{
+  command::{init, rev_parse, tag, update_ref, RevParseOptions},
+  common::{Commit, GitObject, Kind},
+  revision,
+};
+use tempdir::TempDir;
+
+fn write_commit(
+  repo: &Path,
+  tree: &str,
+  parents: &[&str],
+  message: &str,
+) -> anyhow::Result<String> {
+  let commit = Commit {
+    tree: tree.to_string(),
+    parents: parents.iter().map(|parent| parent.to_string()).collect(),
+    author: b"Coder <coder@crafters.io> 1700000000 +0000".to_vec(),
+    committer: format!(
+      "Coder <coder@crafters.io> {} +0000",
+      1700000000 + parents.len()
+    )
+    .into_bytes(),
+    extra_headers: Vec::new(),
+    message: format!("{message}\n").into_bytes(),
+  };
+  let git_object = GitObject::from_content(Kind::Commit, &commit.serialize());
+  git_object.write(repo)?;
+  Ok(git_object.hash()?)
+}
+
+/// Builds `first <- second <- merge` and `first <- side <- merge` with
+/// `master` at the merge, `side` at its second parent and `v1` an annotated
+/// tag of `second`.
+fn install_history(repo: &Path) -> anyhow::Result<[String; 5]> {
+  init(repo, &mut Cursor::new(Vec::new()))?;
+  std::fs::create_dir_all(repo.join("src"))?;
+  std::fs::write(repo.join("src/main.rs"), "fn main() {}\n")?;
+  let main_rs = GitObject::build_file_object(&repo.join("src/main.rs"))?;
+  main_rs.write(repo)?;
+  GitObject::build_tree_object(&repo.join("src"))?.write(repo)?;
+  let tree = GitObject::build_tree_object(repo)?;
+  tree.write(repo)?;
+  let tree = tree.hash()?;
+  let first = write_commit(repo, &tree, &[], "first commit")?;
+  let second = write_commit(repo, &tree, &[&first], "second: fix typo")?;
+  let side = write_commit(repo, &tree, &[&first], "side work")?;
+  let merge = write_commit(repo, &tree, &[&second, &side], "merge side")?;
+  update_ref("HEAD", Some(&merge), None, repo, "", false, false)?;
+  update_ref("refs/heads/side", Some(&side), None, repo, "", false, false)?;
+  tag("v1", repo, true, Some("release".to_string()), &second)?;
+  Ok([first, second, side, merge, main_rs.hash()?])
+}
+
+#[test]
+pub fn test_resolve_revisions() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_rev_parse")?;
+  let temp_dir = temp_dir.path();
+  let [first, second, side, merge, main_rs] = install_history(temp_dir)?;
+  let resolve = |spec: &str| revision::resolve(temp_dir, spec);
+
+  assert_eq!(resolve("HEAD")?, merge);
+  assert_eq!(resolve("@")?, merge);
+  assert_eq!(resolve("master")?, merge);
+  assert_eq!(resolve("refs/heads/side")?, side);
+  assert_eq!(resolve(&merge[..8])?, merge);
+  assert_eq!(resolve("HEAD~1")?, second);
+  assert_eq!(resolve("HEAD^")?, second);
+  assert_eq!(resolve("HEAD^2")?, side);
+  assert_eq!(resolve("HEAD~2")?, first);
+  assert_eq!(resolve("HEAD^2~1")?, first);
+  assert_eq!(resolve("HEAD^0")?, merge);
+  assert_ne!(resolve("v1")?, second);
+  assert_eq!(resolve("v1^{}")?, second);
+  assert_eq!(resolve("v1^{commit}")?, second);
+  assert_eq!(resolve("v1~1")?, first);
+  let tree = resolve("HEAD^{tree}")?;
+  assert_eq!(GitObject::read_object(temp_dir, &tree)?.kind, Kind::Tree);
+  assert_eq!(resolve("HEAD:")?, tree);
+  assert_eq!(resolve("v1:src/main.rs")?, main_rs);
+  assert_eq!(resolve(":/fix typo")?, second);
+  assert_eq!(resolve(":/commit")?, first);
+  assert_eq!(resolve("HEAD@{0}")?, merge);
+
+  assert!(resolve("HEAD~3").is_err());
+  assert!(resolve("HEAD^3").is_err());
+  assert!(resolve("nope").is_err());
+  assert!(resolve("HEAD:nope.rs").is_err());
+  assert!(resolve("HEAD:src/main.rs^{tree}").is_err());
+  assert!(resolve(":/no such message").is_err());
+  assert!(resolve("@{upstream}").is_err());
+
+  std::fs::write(
+    temp_dir.join(".git/config"),
+    "[branch \"master\"]\n\tremote = .\n\tmerge = refs/heads/side\n",
+  )?;
+  assert_eq!(resolve("@{u}")?, side);
+  assert_eq!(resolve("master@{upstream}~1")?, first);
+  Ok(())
+}
+
+#[test]
+pub fn test_rev_parse_output() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_rev_parse")?;
+  let temp_dir = temp_dir.path();
+  let [first, second, side, merge, _] = install_history(temp_dir)?;
+  let run = |args: &[&str], options: &RevParseOptions| -> anyhow::Result<String> {
+    let mut stdout = Cursor::new(Vec::new());
+    let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    rev_parse(&args, &mut stdout, temp_dir, options)?;
+    Ok(String::from_utf8(stdout.into_inner())?)
+  };
+  let default = RevParseOptions::default();
+
+  assert_eq!(
+    run(&["HEAD", "side"], &default)?,
+    format!("{merge}\n{side}\n")
+  );
+  assert_eq!(
+    run(&["HEAD~2..HEAD"], &default)?,
+    format!("{merge}\n^{first}\n")
+  );
+  assert_eq!(run(&["..side"], &default)?, format!("{side}\n^{merge}\n"));
+  assert_eq!(
+    run(&["side...HEAD~1"], &default)?,
+    format!("{second}\n{side}\n^{first}\n")
+  );
+  assert_eq!(run(&["^side"], &default)?, format!("^{side}\n"));
+
+  let short = RevParseOptions {
+    short: Some(7),
+    ..Default::default()
+  };
+  assert_eq!(run(&["HEAD"], &short)?, format!("{}\n", &merge[..7]));
+  let abbrev_ref = RevParseOptions {
+    abbrev_ref: true,
+    ..Default::default()
+  };
+  assert_eq!(run(&["HEAD", "side"], &abbrev_ref)?, "master\nside\n");
+  // The merge base has no name, so it stays a full hash.
+  assert_eq!(
+    run(&["side...master"], &abbrev_ref)?,
+    format!("master\nside\n^{side}\n")
+  );
+  assert_eq!(
+    run(&["side...master"], &short)?,
+    format!("{}\n{}\n^{}\n", &merge[..7], &side[..7], &side[..7])
+  );
+
+  let verify = RevParseOptions {
+    verify: true,
+    ..Default::default()
+  };
+  assert_eq!(run(&["HEAD^2"], &verify)?, format!("{side}\n"));
+  let error = run(&["HEAD", "side"], &verify).unwrap_err();
+  assert_eq!(error.to_string(), "fatal: Needed a single revision");
+  assert!(run(&["0123456789012345678901234567890123456789"], &verify).is_err());
+  Ok(())
+}
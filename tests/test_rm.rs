@@ -0,0 +1,115 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use git_starter_rust::{
+  command::{add, commit_tree, init, rm, update_ref},
+  common::{GitObject, Kind, Tree, TreeEntry},
+  index::Index,
+};
+use tempdir::TempDir;
+
+/// Commits the staged files, which must all sit at the top level.
+fn commit_index(repo: &Path) -> anyhow::Result<()> {
+  let entries = Index::read(repo)?
+    .entries()
+    .iter()
+    .map(|entry| TreeEntry {
+      mode: "100644".to_string(),
+      name: entry.path.clone().into_bytes(),
+      oid: entry.oid,
+    })
+    .collect();
+  let tree = GitObject::from_content(Kind::Tree, &Tree { entries }.serialize());
+  tree.write(repo)?;
+  let mut stdout = Cursor::new(Vec::new());
+  commit_tree(tree.hash()?, &mut stdout, repo, "commit", None)?;
+  let commit = String::from_utf8(stdout.into_inner())?;
+  update_ref(
+    "HEAD",
+    Some(commit.trim_end()),
+    None,
+    repo,
+    "",
+    false,
+    false,
+  )?;
+  Ok(())
+}
+
+fn run_rm(
+  repo: &Path,
+  pathspecs: &[&str],
+  cached: bool,
+  recursive: bool,
+  force: bool,
+) -> anyhow::Result<String> {
+  let pathspecs = pathspecs.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+  let mut stdout = Cursor::new(Vec::new());
+  rm(&pathspecs, &mut stdout, repo, cached, recursive, force)?;
+  Ok(String::from_utf8(stdout.into_inner())?)
+}
+
+#[test]
+pub fn test_rm() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_rm")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  for name in ["a.txt", "b.txt", "c.txt"] {
+    std::fs::write(temp_dir.join(name), name)?;
+  }
+  add(&[".".to_string()], temp_dir, false, false, false)?;
+  commit_index(temp_dir)?;
+
+  assert_eq!(
+    run_rm(temp_dir, &["a.txt"], false, false, false)?,
+    "rm 'a.txt'\n"
+  );
+  assert!(!temp_dir.join("a.txt").exists());
+
+  std::fs::write(temp_dir.join("b.txt"), "changed")?;
+  let error = run_rm(temp_dir, &["b.txt"], false, false, false).unwrap_err();
+  assert!(error.to_string().contains("has local modifications"));
+  assert_eq!(
+    run_rm(temp_dir, &["b.txt"], true, false, false)?,
+    "rm 'b.txt'\n"
+  );
+  assert!(temp_dir.join("b.txt").exists());
+
+  std::fs::create_dir_all(temp_dir.join("dir/sub"))?;
+  std::fs::write(temp_dir.join("dir/sub/d.txt"), "d")?;
+  add(&["dir".to_string()], temp_dir, false, false, false)?;
+  let error = run_rm(temp_dir, &["dir/sub/d.txt"], false, false, false).unwrap_err();
+  assert!(error
+    .to_string()
+    .contains("has changes staged in the index"));
+  let error = run_rm(temp_dir, &["dir"], false, false, true).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "fatal: not removing 'dir' recursively without -r"
+  );
+  assert_eq!(
+    run_rm(temp_dir, &["dir"], false, true, true)?,
+    "rm 'dir/sub/d.txt'\n"
+  );
+  assert!(!temp_dir.join("dir").exists());
+  assert!(run_rm(temp_dir, &["nope"], false, false, false).is_err());
+
+  let paths = Index::read(temp_dir)?
+    .entries()
+    .iter()
+    .map(|entry| entry.path.clone())
+    .collect::<Vec<_>>();
+  assert_eq!(paths, ["c.txt"]);
+
+  // A glob matches files, so it needs no -r even when they sit in a
+  // directory.
+  std::fs::create_dir_all(temp_dir.join("dir"))?;
+  std::fs::write(temp_dir.join("dir/e.txt"), "e")?;
+  add(&[".".to_string()], temp_dir, false, false, false)?;
+  assert_eq!(
+    run_rm(temp_dir, &["*.txt"], true, false, true)?,
+    "rm 'b.txt'\nrm 'c.txt'\nrm 'dir/e.txt'\n"
+  );
+  assert!(Index::read(temp_dir)?.entries().is_empty());
+  Ok(())
+}
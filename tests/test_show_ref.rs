@@ -0,0 +1,64 @@
+use std::io::Cursor;
+
+use git_starter_rust::command::{init, pack_refs, show_ref, tag, update_ref, ShowRefOptions};
+use tempdir::TempDir;
+
+mod common;
+use common::install_commit;
+
+#[test]
+pub fn test_show_ref() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_show_ref")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let commit = install_commit(temp_dir, "first", None)?;
+  update_ref("HEAD", Some(&commit), None, temp_dir, "", false, false)?;
+  tag("v1", temp_dir, false, None, &commit)?;
+  pack_refs(temp_dir, true)?;
+  update_ref(
+    "refs/heads/topic/v1",
+    Some(&commit),
+    None,
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+
+  let run = |patterns: &[&str], options: &ShowRefOptions| -> anyhow::Result<String> {
+    let mut stdout = Cursor::new(Vec::new());
+    let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    show_ref(&patterns, &mut stdout, temp_dir, options)?;
+    Ok(String::from_utf8(stdout.into_inner())?)
+  };
+  assert_eq!(
+    run(&[], &ShowRefOptions::default())?,
+    format!("{commit} refs/heads/master\n{commit} refs/heads/topic/v1\n{commit} refs/tags/v1\n")
+  );
+  let heads = ShowRefOptions {
+    heads: true,
+    ..Default::default()
+  };
+  assert_eq!(
+    run(&["v1"], &heads)?,
+    format!("{commit} refs/heads/topic/v1\n")
+  );
+  let tags = ShowRefOptions {
+    tags: true,
+    ..Default::default()
+  };
+  assert_eq!(run(&[], &tags)?, format!("{commit} refs/tags/v1\n"));
+  assert!(run(&["1"], &ShowRefOptions::default()).is_err());
+
+  let verify = ShowRefOptions {
+    verify: true,
+    ..Default::default()
+  };
+  assert_eq!(
+    run(&["refs/tags/v1", "HEAD"], &verify)?,
+    format!("{commit} refs/tags/v1\n{commit} HEAD\n")
+  );
+  let error = run(&["v1"], &verify).unwrap_err();
+  assert_eq!(error.to_string(), "fatal: 'v1' - not a valid ref");
+  Ok(())
+}
@@ -0,0 +1,220 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use git_starter_rust::{
+  command::{add, commit_tree, init, status, update_ref, write_tree, StatusFormat, StatusOptions},
+  index::{Index, Stat},
+};
+use tempdir::TempDir;
+
+fn commit(repo: &Path, message: &str) -> anyhow::Result<String> {
+  let mut stdout = Cursor::new(Vec::new());
+  write_tree(repo, &mut stdout)?;
+  let tree = String::from_utf8(stdout.into_inner())?;
+  let parent = git_starter_rust::refs::head(repo)?;
+  let mut stdout = Cursor::new(Vec::new());
+  commit_tree(
+    tree.trim_end().to_string(),
+    &mut stdout,
+    repo,
+    message,
+    parent,
+  )?;
+  let commit = String::from_utf8(stdout.into_inner())?
+    .trim_end()
+    .to_string();
+  update_ref("HEAD", Some(&commit), None, repo, "", false, false)?;
+  Ok(commit)
+}
+
+fn run_status(repo: &Path, format: StatusFormat, branch: bool) -> anyhow::Result<String> {
+  let mut stdout = Cursor::new(Vec::new());
+  status(&mut stdout, repo, &StatusOptions { format, branch })?;
+  Ok(String::from_utf8(stdout.into_inner())?)
+}
+
+#[test]
+pub fn test_status_long() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_status_long")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::Long, false)?,
+    "On branch master\n\nNo commits yet\n\n\
+     nothing to commit (create/copy files and use \"git add\" to track)\n"
+  );
+
+  std::fs::write(temp_dir.join("a.txt"), "a\n")?;
+  std::fs::create_dir_all(temp_dir.join("dir/sub"))?;
+  std::fs::write(temp_dir.join("dir/sub/b.txt"), "b\n")?;
+  add(&["a.txt".to_string()], temp_dir, false, false, false)?;
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::Long, false)?,
+    "On branch master\n\nNo commits yet\n\n\
+     Changes to be committed:\n  (use \"git rm --cached <file>...\" to unstage)\n\
+     \tnew file:   a.txt\n\n\
+     Untracked files:\n  (use \"git add <file>...\" to include in what will be committed)\n\
+     \tdir/\n\n"
+  );
+
+  commit(temp_dir, "first")?;
+  std::fs::write(temp_dir.join("a.txt"), "changed\n")?;
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::Long, false)?,
+    "On branch master\n\
+     Changes not staged for commit:\n  \
+     (use \"git add <file>...\" to update what will be committed)\n  \
+     (use \"git restore <file>...\" to discard changes in working directory)\n\
+     \tmodified:   a.txt\n\n\
+     Untracked files:\n  (use \"git add <file>...\" to include in what will be committed)\n\
+     \tdir/\n\n\
+     no changes added to commit (use \"git add\" and/or \"git commit -a\")\n"
+  );
+
+  add(&[".".to_string()], temp_dir, false, false, false)?;
+  commit(temp_dir, "second")?;
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::Long, false)?,
+    "On branch master\nnothing to commit, working tree clean\n"
+  );
+  Ok(())
+}
+
+#[test]
+pub fn test_status_short_and_porcelain_v2() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_status_short")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  for name in ["kept", "modified", "removed", "staged"] {
+    std::fs::write(temp_dir.join(name), format!("{name}\n"))?;
+  }
+  add(&[".".to_string()], temp_dir, false, false, false)?;
+  let first = commit(temp_dir, "first")?;
+  std::fs::write(temp_dir.join("staged"), "staged again\n")?;
+  std::fs::write(temp_dir.join("new"), "new\n")?;
+  add(
+    &["staged".to_string(), "new".to_string()],
+    temp_dir,
+    false,
+    false,
+    false,
+  )?;
+  std::fs::write(temp_dir.join("modified"), "modified again\n")?;
+  std::fs::remove_file(temp_dir.join("removed"))?;
+  std::fs::write(temp_dir.join("untracked"), "untracked\n")?;
+
+  // The branch is one commit ahead of its upstream.
+  let second = commit(temp_dir, "second")?;
+  update_ref(
+    "refs/remotes/origin/master",
+    Some(&first),
+    None,
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+  std::fs::write(
+    temp_dir.join(".git/config"),
+    "[branch \"master\"]\n\tremote = origin\n\tmerge = refs/heads/master\n",
+  )?;
+  std::fs::write(temp_dir.join("staged"), "staged twice\n")?;
+  add(&["staged".to_string()], temp_dir, false, false, false)?;
+
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::Short, true)?,
+    "## master...origin/master [ahead 1]\n M modified\n D removed\nM  staged\n?? untracked\n"
+  );
+  let staged = Index::read(temp_dir)?.get("staged").unwrap().hash();
+  let committed = git_starter_rust::revision::resolve(temp_dir, "HEAD:staged")?;
+  let modified = Index::read(temp_dir)?.get("modified").unwrap().hash();
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::PorcelainV2, true)?,
+    format!(
+      "# branch.oid {second}\n# branch.head master\n\
+       # branch.upstream origin/master\n# branch.ab +1 -0\n\
+       1 .M N... 100644 100644 100644 {modified} {modified} modified\n\
+       1 .D N... 100644 100644 000000 {removed} {removed} removed\n\
+       1 M. N... 100644 100644 100644 {committed} {staged} staged\n\
+       ? untracked\n",
+      removed = git_starter_rust::revision::resolve(temp_dir, "HEAD:removed")?
+    )
+  );
+  Ok(())
+}
+
+#[test]
+pub fn test_status_quotes_paths() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_status_quotes_paths")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  std::fs::write(temp_dir.join("t\tc"), "x")?;
+  std::fs::write(temp_dir.join("\u{e9}"), "x")?;
+  std::fs::write(temp_dir.join("n\nl"), "x")?;
+  add(&["t\tc".to_string()], temp_dir, false, false, false)?;
+
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::Short, false)?,
+    "A  \"t\\tc\"\n?? \"n\\nl\"\n?? \"\\303\\251\"\n"
+  );
+  let v2 = run_status(temp_dir, StatusFormat::PorcelainV2, false)?;
+  assert!(v2.lines().next().unwrap().ends_with(" \"t\\tc\""));
+  assert!(v2.ends_with("? \"n\\nl\"\n? \"\\303\\251\"\n"));
+  Ok(())
+}
+
+#[test]
+pub fn test_status_refreshes_stat_data() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_status_refresh")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  std::fs::write(temp_dir.join("file.txt"), "content\n")?;
+  add(&["file.txt".to_string()], temp_dir, false, false, false)?;
+  commit(temp_dir, "first")?;
+
+  // Same content but stale stat data: reported clean and refreshed.
+  let mut index = Index::read(temp_dir)?;
+  let mut entry = index.get("file.txt").unwrap().clone();
+  entry.stat = Stat::default();
+  index.insert(entry);
+  index.write(Index::lock(temp_dir)?)?;
+  assert_eq!(run_status(temp_dir, StatusFormat::Short, false)?, "");
+  let metadata = std::fs::symlink_metadata(temp_dir.join("file.txt"))?;
+  assert_eq!(
+    Index::read(temp_dir)?.get("file.txt").unwrap().stat,
+    Stat::from_metadata(&metadata)
+  );
+  Ok(())
+}
+
+#[test]
+pub fn test_status_submodule_with_gitdir_file() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_status_gitdir")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let sub = temp_dir.join("sub");
+  std::fs::create_dir(&sub)?;
+  init(&sub, &mut Cursor::new(Vec::new()))?;
+  std::fs::write(sub.join("s"), "s\n")?;
+  add(&["s".to_string()], &sub, false, false, false)?;
+  let sub_head = commit(&sub, "first")?;
+
+  // Move the repository out of the work tree, as `git submodule add` does.
+  std::fs::create_dir(temp_dir.join(".git/modules"))?;
+  std::fs::rename(sub.join(".git"), temp_dir.join(".git/modules/sub"))?;
+  std::fs::write(sub.join(".git"), "gitdir: ../.git/modules/sub\n")?;
+
+  add(&["sub".to_string()], temp_dir, false, false, false)?;
+  let entry = Index::read(temp_dir)?.get("sub").unwrap().clone();
+  assert_eq!((entry.mode, entry.hash()), (0o160000, sub_head));
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::Short, false)?,
+    "A  sub\n"
+  );
+  std::fs::write(sub.join("s"), "changed\n")?;
+  assert_eq!(
+    run_status(temp_dir, StatusFormat::Short, false)?,
+    "Am sub\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,54 @@
+use std::io::Cursor;
+
+use git_starter_rust::command::{init, symbolic_ref};
+use tempdir::TempDir;
+
+#[test]
+pub fn test_symbolic_ref() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_symbolic_ref")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+
+  let mut stdout = Cursor::new(Vec::new());
+  symbolic_ref("HEAD", None, &mut stdout, temp_dir, false, false)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "refs/heads/master\n"
+  );
+
+  symbolic_ref(
+    "HEAD",
+    Some("refs/heads/main"),
+    &mut Cursor::new(Vec::new()),
+    temp_dir,
+    false,
+    false,
+  )?;
+  assert_eq!(
+    std::fs::read_to_string(temp_dir.join(".git/HEAD"))?,
+    "ref: refs/heads/main\n"
+  );
+  let mut stdout = Cursor::new(Vec::new());
+  symbolic_ref("HEAD", None, &mut stdout, temp_dir, false, true)?;
+  assert_eq!(String::from_utf8(stdout.into_inner())?, "main\n");
+
+  let outside = symbolic_ref(
+    "HEAD",
+    Some("main"),
+    &mut Cursor::new(Vec::new()),
+    temp_dir,
+    false,
+    false,
+  );
+  assert!(outside.is_err());
+  let not_symbolic = symbolic_ref(
+    "refs/heads/main",
+    None,
+    &mut Cursor::new(Vec::new()),
+    temp_dir,
+    false,
+    false,
+  );
+  assert!(not_symbolic.is_err());
+  Ok(())
+}
@@ -0,0 +1,139 @@
+use std::io::Cursor;
+
+use git_starter_rust::{
+  command::{cat_file, hash_object, init, tag, tag_delete, tag_list, CatFileMode, TagListOptions},
+  common::{glob_match, GitObject, Kind},
+  refs,
+};
+use tempdir::TempDir;
+
+#[test]
+pub fn test_tag_annotated() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_tag")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let file = temp_dir.join("test.md");
+  std::fs::write(&file, b"Hello World\n")?;
+  hash_object(&file, &mut Cursor::new(Vec::new()), temp_dir, true)?;
+
+  tag(
+    "v1.0",
+    temp_dir,
+    true,
+    Some("first release".to_string()),
+    "557db03de997c86a4a028e1ebd3a1ceb225be238",
+  )?;
+  let tag_hash = std::fs::read_to_string(temp_dir.join(".git/refs/tags/v1.0"))?;
+  let tag_hash = tag_hash.trim_end();
+  let tag_object = GitObject::read_object(temp_dir, tag_hash)?;
+  assert_eq!(tag_object.kind, Kind::Tag);
+
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  cat_file(tag_hash, &mut stdout, temp_dir, CatFileMode::PrettyPrint)?;
+  let output = String::from_utf8(stdout.into_inner())?;
+  assert!(output.starts_with(
+    "object 557db03de997c86a4a028e1ebd3a1ceb225be238\ntype blob\ntag v1.0\ntagger Coder <coder@crafters.io> "
+  ));
+  assert!(output.ends_with(" +0000\n\nfirst release\n"));
+
+  let again = tag(
+    "v1.0",
+    temp_dir,
+    true,
+    Some("again".to_string()),
+    "557db03de997c86a4a028e1ebd3a1ceb225be238",
+  );
+  assert!(again.is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_tag_lightweight_list_and_delete() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_tag")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let file = temp_dir.join("test.md");
+  std::fs::write(&file, b"Hello World\n")?;
+  hash_object(&file, &mut Cursor::new(Vec::new()), temp_dir, true)?;
+  let blob = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+
+  tag("v1.2", temp_dir, false, None, blob)?;
+  assert_eq!(
+    refs::resolve_ref(temp_dir, "refs/tags/v1.2")?.as_deref(),
+    Some(blob)
+  );
+  tag(
+    "v1.10",
+    temp_dir,
+    false,
+    Some("ten\n\nnotes".to_string()),
+    blob,
+  )?;
+  tag("v1.9", temp_dir, true, Some("nine".to_string()), blob)?;
+  tag("other", temp_dir, false, None, blob)?;
+  assert!(tag("v1.9", temp_dir, false, None, blob).is_err());
+  assert!(tag("v2", temp_dir, true, None, blob).is_err());
+
+  let list = |options: &TagListOptions| -> anyhow::Result<String> {
+    let mut stdout = Cursor::new(Vec::new());
+    tag_list(&mut stdout, temp_dir, options)?;
+    Ok(String::from_utf8(stdout.into_inner())?)
+  };
+  assert_eq!(
+    list(&TagListOptions::default())?,
+    "other\nv1.10\nv1.2\nv1.9\n"
+  );
+  let by_version = TagListOptions {
+    patterns: vec!["v1.*".to_string()],
+    sort: Some("version:refname".to_string()),
+    ..Default::default()
+  };
+  assert_eq!(list(&by_version)?, "v1.2\nv1.9\nv1.10\n");
+  let reversed = TagListOptions {
+    sort: Some("-v:refname".to_string()),
+    ..by_version
+  };
+  assert_eq!(list(&reversed)?, "v1.10\nv1.9\nv1.2\n");
+  let annotations = TagListOptions {
+    patterns: vec!["v1.[19]*".to_string()],
+    lines: Some(3),
+    ..Default::default()
+  };
+  assert_eq!(
+    list(&annotations)?,
+    "v1.10           ten\n    \n    notes\nv1.9            nine\n"
+  );
+  let unsupported = TagListOptions {
+    sort: Some("creatordate".to_string()),
+    ..Default::default()
+  };
+  assert!(list(&unsupported).is_err());
+
+  let mut stdout = Cursor::new(Vec::new());
+  tag_delete(&["v1.2".to_string()], &mut stdout, temp_dir)?;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    "Deleted tag 'v1.2' (was 557db03)\n"
+  );
+  assert!(tag_delete(
+    &["v1.2".to_string()],
+    &mut Cursor::new(Vec::new()),
+    temp_dir
+  )
+  .is_err());
+  assert_eq!(list(&TagListOptions::default())?, "other\nv1.10\nv1.9\n");
+  Ok(())
+}
+
+#[test]
+pub fn test_glob_match() {
+  assert!(glob_match("v1.*", "v1.10"));
+  assert!(glob_match("*", "release/v1"));
+  assert!(glob_match("v?.[0-9]", "v1.5"));
+  assert!(glob_match("v[!2]*", "v1.0"));
+  assert!(glob_match("a\\*", "a*"));
+  assert!(!glob_match("a\\*", "ab"));
+  assert!(!glob_match("v[!1]*", "v1.0"));
+  assert!(!glob_match("v1.?", "v1.10"));
+  assert!(glob_match("[]", "[]"));
+}
@@ -0,0 +1,259 @@
+use std::io::Cursor;
+
+use git_starter_rust::{
+  command::{init, pack_refs, update_ref, update_ref_stdin},
+  reflog,
+  refs::{self, Ref},
+};
+use tempdir::TempDir;
+
+mod common;
+use common::install_commit;
+
+#[test]
+pub fn test_update_ref_through_head() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_update_ref")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  assert_eq!(refs::head(temp_dir)?, None);
+
+  let commit = install_commit(temp_dir, "first", None)?;
+  update_ref("HEAD", Some(&commit[..7]), None, temp_dir, "", false, false)?;
+  assert_eq!(
+    std::fs::read_to_string(temp_dir.join(".git/refs/heads/master"))?,
+    format!("{commit}\n")
+  );
+  assert_eq!(refs::head(temp_dir)?, Some(commit.clone()));
+  assert_eq!(
+    refs::read_ref(temp_dir, "HEAD")?,
+    Some(Ref::Symbolic("refs/heads/master".to_string()))
+  );
+
+  update_ref(
+    "refs/heads/topic/one",
+    Some(&commit),
+    None,
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+  assert_eq!(
+    refs::resolve_ref(temp_dir, "refs/heads/topic/one")?,
+    Some(commit.clone())
+  );
+  update_ref(
+    "refs/heads/topic/one",
+    None,
+    None,
+    temp_dir,
+    "",
+    true,
+    false,
+  )?;
+  assert!(!temp_dir.join(".git/refs/heads/topic").exists());
+  assert!(temp_dir.join(".git/refs/heads").is_dir());
+
+  assert!(update_ref(
+    "refs/heads/bad..name",
+    Some(&commit),
+    None,
+    temp_dir,
+    "",
+    false,
+    false
+  )
+  .is_err());
+  assert!(update_ref(
+    "refs/heads/x",
+    Some("0000000"),
+    None,
+    temp_dir,
+    "",
+    false,
+    false
+  )
+  .is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_update_ref_no_deref() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_update_ref")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let commit = install_commit(temp_dir, "first", None)?;
+
+  update_ref("HEAD", Some(&commit), None, temp_dir, "", false, true)?;
+  assert_eq!(
+    refs::read_ref(temp_dir, "HEAD")?,
+    Some(Ref::Direct(commit.clone()))
+  );
+  assert!(!temp_dir.join(".git/refs/heads/master").exists());
+  Ok(())
+}
+
+#[test]
+pub fn test_update_ref_compare_and_swap() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_update_ref")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let first = install_commit(temp_dir, "first", None)?;
+  let second = install_commit(temp_dir, "second", None)?;
+  let zero = refs::ZERO_HASH;
+  let branch = "refs/heads/main";
+
+  update_ref(branch, Some(&first), Some(zero), temp_dir, "", false, false)?;
+  let exists = update_ref(
+    branch,
+    Some(&second),
+    Some(zero),
+    temp_dir,
+    "",
+    false,
+    false,
+  );
+  assert!(exists
+    .unwrap_err()
+    .to_string()
+    .contains("reference already exists"));
+
+  let stale = update_ref(
+    branch,
+    Some(&first),
+    Some(&second),
+    temp_dir,
+    "",
+    false,
+    false,
+  );
+  assert_eq!(
+    stale.unwrap_err().to_string(),
+    format!("fatal: cannot lock ref '{branch}': is at {first} but expected {second}")
+  );
+  update_ref(
+    branch,
+    Some(&second),
+    Some(&first),
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+  assert_eq!(refs::resolve_ref(temp_dir, branch)?, Some(second.clone()));
+
+  std::fs::write(temp_dir.join(".git/refs/heads/main.lock"), "")?;
+  let locked = update_ref(branch, Some(&first), None, temp_dir, "", false, false);
+  assert!(locked.unwrap_err().to_string().contains("File exists"));
+  assert_eq!(refs::resolve_ref(temp_dir, branch)?, Some(second.clone()));
+  std::fs::remove_file(temp_dir.join(".git/refs/heads/main.lock"))?;
+
+  update_ref(branch, None, Some(&second), temp_dir, "", true, false)?;
+  assert_eq!(refs::read_ref(temp_dir, branch)?, None);
+  assert!(!temp_dir.join(".git/refs/heads/main.lock").exists());
+  Ok(())
+}
+
+#[test]
+pub fn test_update_ref_stdin_transaction() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_update_ref")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let first = install_commit(temp_dir, "first", None)?;
+  let second = install_commit(temp_dir, "second", None)?;
+  update_ref(
+    "refs/heads/old",
+    Some(&first),
+    None,
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+
+  let commands = format!(
+    "create refs/heads/a {first}\n\
+     update HEAD {second}\n\
+     delete refs/heads/old {first}\n\
+     verify refs/heads/missing\n"
+  );
+  update_ref_stdin(&mut commands.as_bytes(), temp_dir, "", false)?;
+  assert_eq!(
+    refs::resolve_ref(temp_dir, "refs/heads/a")?,
+    Some(first.clone())
+  );
+  assert_eq!(refs::head(temp_dir)?, Some(second.clone()));
+  assert_eq!(refs::read_ref(temp_dir, "refs/heads/old")?, None);
+
+  // The stale old value for refs/heads/a aborts the whole batch.
+  let commands = format!(
+    "update refs/heads/b {second}\n\
+     update refs/heads/a {second} {second}\n"
+  );
+  assert!(update_ref_stdin(&mut commands.as_bytes(), temp_dir, "", false).is_err());
+  assert_eq!(refs::read_ref(temp_dir, "refs/heads/b")?, None);
+  assert_eq!(
+    refs::resolve_ref(temp_dir, "refs/heads/a")?,
+    Some(first.clone())
+  );
+  assert!(!temp_dir.join(".git/refs/heads/b.lock").exists());
+
+  let commands = format!("update refs/heads/a {second}\ndelete refs/heads/a\n");
+  let duplicate = update_ref_stdin(&mut commands.as_bytes(), temp_dir, "", false);
+  assert!(duplicate
+    .unwrap_err()
+    .to_string()
+    .contains("multiple updates"));
+  assert!(update_ref_stdin(
+    &mut "frobnicate refs/heads/a\n".as_bytes(),
+    temp_dir,
+    "",
+    false
+  )
+  .is_err());
+  Ok(())
+}
+
+#[test]
+pub fn test_update_ref_stdin_failed_commit() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_update_ref")?;
+  let temp_dir = temp_dir.path();
+  init(temp_dir, &mut Cursor::new(Vec::new()))?;
+  let first = install_commit(temp_dir, "first", None)?;
+  update_ref(
+    "refs/heads/gone",
+    Some(&first),
+    None,
+    temp_dir,
+    "",
+    false,
+    false,
+  )?;
+  pack_refs(temp_dir, true)?;
+  // A directory in the way of refs/heads/b only fails once its lock is
+  // moved into place, after every check has passed.
+  std::fs::create_dir_all(temp_dir.join(".git/refs/heads/b/in-the-way"))?;
+
+  let commands = format!(
+    "create refs/heads/a {first}\n\
+     update refs/heads/b {first}\n\
+     delete refs/heads/gone {first}\n"
+  );
+  assert!(update_ref_stdin(&mut commands.as_bytes(), temp_dir, "", false).is_err());
+  // refs/heads/a landed before the failure and is logged; refs/heads/b is
+  // neither written nor logged, and refs/heads/gone is still packed.
+  assert_eq!(
+    refs::resolve_ref(temp_dir, "refs/heads/a")?,
+    Some(first.clone())
+  );
+  assert_eq!(reflog::read_reflog(temp_dir, "refs/heads/a")?.len(), 1);
+  assert!(reflog::read_reflog(temp_dir, "refs/heads/b")?.is_empty());
+  assert_eq!(
+    refs::resolve_ref(temp_dir, "refs/heads/gone")?,
+    Some(first.clone())
+  );
+  assert_eq!(reflog::read_reflog(temp_dir, "refs/heads/gone")?.len(), 1);
+  assert!(!temp_dir.join(".git/refs/heads/b.lock").exists());
+  assert!(!temp_dir.join(".git/packed-refs.lock").exists());
+  Ok(())
+}
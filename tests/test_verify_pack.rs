@@ -0,0 +1,59 @@
+use std::io::Cursor;
+
+use git_starter_rust::command::verify_pack;
+use tempdir::TempDir;
+
+const PACK: &str = "pack-9c06564c4d74ed54ef27e9227cda5ae5ba473982";
+
+#[test]
+pub fn test_verify_pack() -> anyhow::Result<()> {
+  let pack_path = format!("tests/data/{PACK}.pack");
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  verify_pack(pack_path.as_ref(), &mut stdout, true)?;
+  let expected = r#"4f5a6549782cc9de977296c75ef34e5a8d2c5070 commit 207 139 12
+098ee816db5eef826fcea3b36fdd5acb97ba7e33 commit 159 111 151
+3d9acc2666e118f44181ea6036ada054efd3d611 commit 207 140 262
+f70dbd9f054f9eb52979184d42e5f7cf2a59d851 commit 207 140 402
+91848999adf4c496338019f24b0c975f7be4f349 blob   3550 532 542
+7148c66d56c6825c94927d0e9bccf628fdf40ce8 blob   30 40 1074
+f7e2ffd419c25f7dce95ad36b10f524c18ea3df3 tree   69 79 1114
+9dde84af63d0706beb4c530c1fb378a4518807ed tree   35 46 1193
+fe4dbd288f40d97cc3587e57f22a3f5b576ba3a3 tree   69 79 1239
+5bde44b6271e2eee8929a4a10c03cb78a726ac79 blob   18 30 1318 1 91848999adf4c496338019f24b0c975f7be4f349
+71fcb6a5ec84c843c98271770dbc385e90d66c95 tree   35 46 1348
+a929d68bcf0adafcecaa1ccb60e647fad5b810e1 blob   30 40 1394
+f502041996546d706d004d9bb89feb3539ac952b tree   69 80 1434
+8feffba17525cd027c5e7b9c62801ec2ecb28159 blob   18 30 1514 1 91848999adf4c496338019f24b0c975f7be4f349
+92e61e3ea99ae228dbc2b9be741dff3f89d930cd tree   35 45 1544
+5b2f864b9c44517a8c30202a671e50173273c3ed blob   30 40 1589
+5b0005587c8c3f9ad2562fa0887ba3f34eb52c6d tree   69 80 1629
+44e2c8cfbb07a86d11c22614c60c20875789c4c7 blob   18 30 1709 1 91848999adf4c496338019f24b0c975f7be4f349
+dcb1cdf7d89bf7bd2cc57dd14082b5780de2c041 tree   35 46 1739
+20be8d876bb0f55c360ca718c21611cdf4eca4de blob   30 40 1785
+non delta: 17 objects
+chain length = 1: 3 objects
+"#;
+  assert_eq!(
+    String::from_utf8(stdout.into_inner())?,
+    format!("{expected}{pack_path}: ok\n")
+  );
+  Ok(())
+}
+
+#[test]
+pub fn test_verify_pack_checksum_mismatch() -> anyhow::Result<()> {
+  let temp_dir = TempDir::new("test_verify_pack")?;
+  let temp_dir = temp_dir.path();
+  let mut pack = std::fs::read(format!("tests/data/{PACK}.pack"))?;
+  pack[600] ^= 0xff;
+  std::fs::write(temp_dir.join(format!("{PACK}.pack")), pack)?;
+  std::fs::copy(
+    format!("tests/data/{PACK}.idx"),
+    temp_dir.join(format!("{PACK}.idx")),
+  )?;
+  let mut stdout = Cursor::new(Vec::<u8>::new());
+  let result = verify_pack(&temp_dir.join(format!("{PACK}.idx")), &mut stdout, true);
+  assert!(result.is_err());
+  assert!(stdout.into_inner().is_empty());
+  Ok(())
+}
@@ -1,16 +1,22 @@
-use git_starter_rust::{command::write_tree, common::GitObject};
+use git_starter_rust::{
+  command::{add, init, write_tree},
+  common::GitObject,
+  index::Index,
+};
 use tempdir::TempDir;
 
 #[test]
 pub fn test_write_tree() -> anyhow::Result<()> {
   let temp_dir = TempDir::new("test_write_tree")?;
   let temp_dir = temp_dir.path();
+  init(temp_dir, &mut std::io::Cursor::new(Vec::new()))?;
   let subdir = temp_dir.join("subdir");
   std::fs::create_dir_all(&subdir)?;
   let subdir_file = subdir.join("subdir_file.txt");
   std::fs::write(subdir_file, b"subdir file content\n")?;
   let root_file = temp_dir.join("root_file.txt");
   std::fs::write(root_file.clone(), b"root file content\n")?;
+  add(&[".".to_string()], temp_dir, false, false, false)?;
   let mut stdout = std::io::Cursor::new(Vec::<u8>::new());
 
   let tree_obj = GitObject::build_tree_object(temp_dir)?;
@@ -22,11 +28,17 @@ pub fn test_write_tree() -> anyhow::Result<()> {
   content.extend_from_slice(&subdir_obj.hash_bytes()?);
   assert_eq!(tree_obj.data, &content[..]);
 
+  // Unstaged files stay out of the tree.
+  std::fs::write(temp_dir.join("untracked.txt"), b"untracked\n")?;
   write_tree(temp_dir, &mut stdout)?;
   let expected_hash = format!("{}\n", tree_obj.hash()?);
   assert_eq!(
     String::from_utf8(stdout.into_inner()).unwrap(),
     expected_hash
   );
+
+  let cache_tree = Index::read(temp_dir)?.cache_tree()?.unwrap();
+  assert_eq!(cache_tree.cached.unwrap().0, 2);
+  assert_eq!(cache_tree.subtrees[0].0, "subdir");
   Ok(())
 }